@@ -3,7 +3,7 @@
 //! Run with: cargo bench
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
-use luminex_buffer::TextBuffer;
+use luminex_buffer::{SearchOptions, SearchQuery, TextBuffer};
 
 /// Generates a large text string for benchmarking.
 fn generate_large_text(lines: usize) -> String {
@@ -198,6 +198,99 @@ fn bench_search(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmarks streaming search over a 100k-line buffer.
+///
+/// None of these should copy the whole buffer into a `String`.
+fn bench_search_large(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search_large");
+
+    let text = generate_large_text(100000);
+    let buffer = TextBuffer::from(text.as_str());
+
+    group.bench_function("find_all_literal", |b| {
+        b.iter(|| {
+            let matches = buffer.find_all(black_box("sample"));
+            black_box(matches)
+        })
+    });
+
+    group.bench_function("find_next_near_end", |b| {
+        b.iter(|| {
+            let result = buffer.find_next(black_box("Line 99999:"), 0);
+            black_box(result)
+        })
+    });
+
+    group.bench_function("find_next_first_match", |b| {
+        let from = buffer.len_chars() / 2;
+        b.iter(|| {
+            let result = buffer.find_next(black_box("sample"), from);
+            black_box(result)
+        })
+    });
+
+    let regex = SearchQuery::new(
+        r"Line \d+5:",
+        SearchOptions {
+            regex: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    group.bench_function("search_all_regex", |b| {
+        b.iter(|| {
+            let matches = buffer.search_all(black_box(&regex));
+            black_box(matches)
+        })
+    });
+
+    let multiline = SearchQuery::new(
+        r"purposes\.\nLine \d+0:",
+        SearchOptions {
+            regex: true,
+            multiline: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    group.bench_function("search_all_multiline", |b| {
+        b.iter(|| {
+            let matches = buffer.search_all(black_box(&multiline));
+            black_box(matches)
+        })
+    });
+
+    // No match: every block back to the start, then again after wrapping
+    let multiline_missing = SearchQuery::new(
+        r"purposes\.\nmissing",
+        SearchOptions {
+            regex: true,
+            multiline: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    group.bench_function("search_prev_multiline_no_match", |b| {
+        let before = buffer.len_chars();
+        b.iter(|| {
+            let result = buffer.search_prev(black_box(&multiline_missing), before);
+            black_box(result)
+        })
+    });
+
+    group.bench_function("replace_all_regex", |b| {
+        b.iter_with_setup(
+            || TextBuffer::from(text.as_str()),
+            |mut buffer| {
+                let count = buffer.replace_all_matches(&regex, "L$0").unwrap();
+                black_box(count)
+            },
+        )
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_buffer_creation,
@@ -206,6 +299,7 @@ criterion_group!(
     bench_undo_redo,
     bench_line_access,
     bench_search,
+    bench_search_large,
 );
 
 criterion_main!(benches);
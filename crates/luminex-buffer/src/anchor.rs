@@ -0,0 +1,118 @@
+//! Positions that stay attached to the text around them.
+//!
+//! ## Learning: Anchors
+//!
+//! A mark saved as "line 40, column 8" is wrong as soon as a line is
+//! inserted above it. Instead the buffer keeps a table of anchors and
+//! shifts every one of them on each edit, the same way cursors are
+//! mapped through a [`ChangeSet`](crate::ChangeSet):
+//!
+//! ```text
+//!   insert 3 chars at 10:   anchor 4 -> 4,  anchor 12 -> 15
+//!   delete 10..20:          anchor 15 -> 10 (its text is gone)
+//! ```
+//!
+//! An anchor exactly where text is inserted stays before the new text
+//! with [`Affinity::Backward`] and moves past it with
+//! [`Affinity::Forward`]. Holders refer to anchors by [`AnchorId`], so
+//! they never see a stale index.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cursor::Affinity;
+
+/// A handle to a position tracked by a [`TextBuffer`](crate::TextBuffer).
+///
+/// Ids are never reused, even across buffers: an id kept after its
+/// buffer was replaced finds nothing rather than someone else's anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AnchorId(u64);
+
+/// The next [`AnchorId`] to hand out, in any buffer.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The anchors of one buffer.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Anchors {
+    positions: HashMap<AnchorId, (usize, Affinity)>,
+}
+
+impl Anchors {
+    /// Starts tracking `char_idx`.
+    pub(crate) fn add(&mut self, char_idx: usize, affinity: Affinity) -> AnchorId {
+        let id = AnchorId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        self.positions.insert(id, (char_idx, affinity));
+        id
+    }
+
+    /// Returns where an anchor is now.
+    pub(crate) fn get(&self, id: AnchorId) -> Option<usize> {
+        self.positions.get(&id).map(|&(char_idx, _)| char_idx)
+    }
+
+    /// Stops tracking an anchor. Returns false if it didn't exist.
+    pub(crate) fn remove(&mut self, id: AnchorId) -> bool {
+        self.positions.remove(&id).is_some()
+    }
+
+    /// Shifts anchors after an insertion of `len` chars at `char_idx`.
+    pub(crate) fn insert(&mut self, char_idx: usize, len: usize) {
+        for (pos, affinity) in self.positions.values_mut() {
+            if *pos > char_idx || (*pos == char_idx && *affinity == Affinity::Forward) {
+                *pos += len;
+            }
+        }
+    }
+
+    /// Shifts anchors after `range` was deleted. Anchors inside it move
+    /// to its start.
+    pub(crate) fn delete(&mut self, range: Range<usize>) {
+        for (pos, _) in self.positions.values_mut() {
+            if *pos >= range.end {
+                *pos -= range.len();
+            } else if *pos > range.start {
+                *pos = range.start;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextBuffer;
+
+    #[test]
+    fn test_anchors_follow_edits() {
+        let mut buffer = TextBuffer::from("one two three");
+        let two = buffer.add_anchor(4, Affinity::Backward).unwrap();
+        let three = buffer.add_anchor(8, Affinity::Forward).unwrap();
+
+        buffer.insert(4, "and ").unwrap();
+        assert_eq!(buffer.anchor(two), Some(4));
+        assert_eq!(buffer.anchor(three), Some(12));
+
+        // Deleting an anchor's text leaves it at the start of the gap
+        buffer.delete(2..14).unwrap();
+        assert_eq!(buffer.anchor(two), Some(2));
+        assert_eq!(buffer.anchor(three), Some(2));
+
+        // Undo and redo replay edits, so anchors follow those too
+        while buffer.undo().is_ok() {}
+        assert_eq!(buffer.text(), "one two three");
+        assert_eq!(buffer.anchor(two), Some(2));
+        assert_eq!(buffer.anchor_position(three).unwrap().column, 10);
+
+        assert!(buffer.remove_anchor(two));
+        assert_eq!(buffer.anchor(two), None);
+        assert!(buffer.add_anchor(100, Affinity::Forward).is_err());
+
+        // Another buffer's anchors never share an id
+        let mut other = TextBuffer::from("one two three");
+        let first = other.add_anchor(0, Affinity::Forward).unwrap();
+        assert_ne!(first, two);
+        assert_eq!(other.anchor(three), None);
+    }
+}
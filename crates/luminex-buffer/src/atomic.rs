@@ -0,0 +1,250 @@
+//! Writing a file so that a crash never leaves it half written.
+//!
+//! ## Learning: Write, Sync, Rename
+//!
+//! Writing straight over a file truncates it first, so a crash (or a
+//! full disk) halfway through loses both the old and the new text. The
+//! safe way writes a complete copy next to it, then swaps it in:
+//!
+//! ```text
+//!   1. create  .main.rs.1234-0.tmp   (a name nobody else uses)
+//!   2. write   the new bytes, then fsync so they're really on disk
+//!   3. chmod   the old file's mode bits onto the copy
+//!   4. rename  over main.rs          (atomic: readers see old or new)
+//! ```
+//!
+//! A few details make the difference between "usually works" and safe:
+//!
+//! - The temp file is created with `create_new`, so it can never
+//!   clobber an existing file, and lives in the same directory, since
+//!   a rename can't cross filesystems.
+//! - A symlink is followed to the file it points at. Renaming over the
+//!   link itself would replace it with a regular file.
+//! - Some files can't be replaced by renaming: the directory may not be
+//!   writable, or the file may be a bind mount. Then the bytes are
+//!   written in place, which isn't atomic but is the best left to do.
+
+use std::fs::{File, OpenOptions, Permissions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{BufferError, BufferResult};
+
+/// How many links may be followed before giving up, as on Linux.
+const MAX_SYMLINKS: usize = 40;
+
+/// Distinguishes the temp files of one process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Replaces the contents of `path` with `bytes`, atomically where the
+/// filesystem allows.
+pub(crate) fn write_file(path: &Path, bytes: &[u8]) -> BufferResult<()> {
+    let target = resolve_symlinks(path)?;
+    let permissions = std::fs::metadata(&target).ok().map(|m| m.permissions());
+
+    let (temp_path, mut temp) = match create_temp(&target) {
+        Ok(created) => created,
+        // The directory is off limits, but the file itself may not be
+        Err(BufferError::CreateTemp { source, .. })
+            if source.kind() == ErrorKind::PermissionDenied =>
+        {
+            return write_in_place(&target, bytes);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let written = fill_temp(&temp_path, &mut temp, bytes, permissions);
+    drop(temp);
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    if std::fs::rename(&temp_path, &target).is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+        return write_in_place(&target, bytes);
+    }
+    sync_dir(&target);
+    Ok(())
+}
+
+/// Follows `path` through any symlinks to the file they point at. The
+/// file itself doesn't have to exist.
+fn resolve_symlinks(path: &Path) -> BufferResult<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINKS {
+        match std::fs::read_link(&path) {
+            // Relative links are relative to the link's directory
+            Ok(target) => {
+                path = path
+                    .parent()
+                    .map_or(target.clone(), |dir| dir.join(&target))
+            }
+            Err(_) => return Ok(path),
+        }
+    }
+    Err(BufferError::SymlinkLoop(path))
+}
+
+/// Creates an empty temp file next to `target`, with a name no other
+/// file has.
+fn create_temp(target: &Path) -> BufferResult<(PathBuf, File)> {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let pid = std::process::id();
+    loop {
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let temp_path = target.with_file_name(format!(".{name}.{pid}-{n}.tmp"));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            // Left behind by a crashed process with our pid: try the next
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(source) => {
+                let dir = target.parent().map(Path::to_path_buf).unwrap_or_default();
+                return Err(BufferError::CreateTemp { dir, source });
+            }
+        }
+    }
+}
+
+/// Writes and syncs the temp file and gives it the old file's mode.
+fn fill_temp(
+    temp_path: &Path,
+    temp: &mut File,
+    bytes: &[u8],
+    permissions: Option<Permissions>,
+) -> BufferResult<()> {
+    let path = temp_path.to_path_buf();
+    temp.write_all(bytes).map_err(|source| BufferError::Write {
+        path: path.clone(),
+        source,
+    })?;
+    temp.sync_all().map_err(|source| BufferError::Sync {
+        path: path.clone(),
+        source,
+    })?;
+    if let Some(permissions) = permissions {
+        temp.set_permissions(permissions)
+            .map_err(|source| BufferError::Permissions { path, source })?;
+    }
+    Ok(())
+}
+
+/// Overwrites `target` where it is, keeping its inode, mode and owner.
+fn write_in_place(target: &Path, bytes: &[u8]) -> BufferResult<()> {
+    let write = || -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(target)?;
+        file.write_all(bytes)?;
+        file.sync_all()
+    };
+    write().map_err(|source| BufferError::InPlaceWrite {
+        path: target.to_path_buf(),
+        source,
+    })
+}
+
+/// Syncs the directory holding `target`, so the rename itself survives
+/// a crash. Not every platform can open a directory; that's not an
+/// error worth failing a save over.
+fn sync_dir(target: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = target.parent()
+        && let Ok(dir) = File::open(if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        })
+    {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = target;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_file_leaves_neighbours_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.rs");
+        let tmp = dir.path().join("foo.tmp");
+        std::fs::write(&tmp, "not mine").unwrap();
+
+        write_file(&path, b"new").unwrap();
+        write_file(&path, b"newer").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "newer");
+        assert_eq!(std::fs::read_to_string(&tmp).unwrap(), "not mine");
+        // No temp files left behind
+        assert_eq!(dir_entries(dir.path()), ["foo.rs", "foo.tmp"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_file_keeps_symlinks_and_mode() {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("real")).unwrap();
+        let target = dir.path().join("real").join("script.sh");
+        std::fs::write(&target, "old").unwrap();
+        std::fs::set_permissions(&target, Permissions::from_mode(0o751)).unwrap();
+        let link = dir.path().join("link.sh");
+        symlink("real/script.sh", &link).unwrap();
+
+        write_file(&link, b"new").unwrap();
+        assert!(
+            std::fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o751);
+
+        // A dangling link gets its target created
+        let dangling = dir.path().join("dangling");
+        symlink("real/created.txt", &dangling).unwrap();
+        write_file(&dangling, b"hi").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("real/created.txt")).unwrap(),
+            "hi"
+        );
+
+        let looped = dir.path().join("loop");
+        symlink("loop", &looped).unwrap();
+        assert!(matches!(
+            write_file(&looped, b"x"),
+            Err(BufferError::SymlinkLoop(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_file_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("no_such_dir").join("file.txt");
+        assert!(matches!(
+            write_file(&missing, b"x"),
+            Err(BufferError::CreateTemp { .. })
+        ));
+    }
+}
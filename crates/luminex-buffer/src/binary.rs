@@ -0,0 +1,403 @@
+//! Binary file detection and a byte-oriented buffer for hex editing.
+//!
+//! ## Learning: Text Is a Guess
+//!
+//! Nothing in a file says "I am text". Like `git` and `file`, we look
+//! at the first few kilobytes and guess:
+//! - a BOM, or NULs in the pattern of UTF-16, means text
+//! - any other NUL byte means binary; text never contains one
+//! - many control characters (other than tabs, line breaks and the
+//!   like) means binary
+//! - invalid UTF-8 alone is not enough, since text in a legacy 8-bit
+//!   encoding is mostly invalid UTF-8 too. It only counts when some
+//!   control characters come with it.
+//!
+//! Binary files get a [`ByteBuffer`] instead of a rope: no encoding, no
+//! lines, just offsets into a `Vec<u8>`, shown as a hex plus ASCII dump.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::atomic;
+use crate::encoding::TextEncoding;
+use crate::{BufferError, BufferResult};
+
+/// How much of a file [`is_binary`] looks at.
+pub const BINARY_SAMPLE_SIZE: usize = 8 * 1024;
+
+/// Guesses whether file content is binary from its first few kilobytes.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SAMPLE_SIZE)];
+    if sample.is_empty() {
+        return false;
+    }
+
+    let detected = TextEncoding::detect(sample, TextEncoding::default());
+    if detected.has_bom() || (detected.is_unicode() && detected != TextEncoding::utf8()) {
+        return false;
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+
+    let controls = sample.iter().filter(|&&b| is_control(b)).count();
+    let invalid: usize = sample
+        .utf8_chunks()
+        .map(|chunk| chunk.invalid().len())
+        .sum();
+    // More than 10% controls, or 30% invalid UTF-8 with over 2% controls
+    controls * 10 > sample.len()
+        || (invalid * 10 > sample.len() * 3 && controls * 50 > sample.len())
+}
+
+/// Control bytes that don't appear in ordinary text.
+fn is_control(b: u8) -> bool {
+    matches!(b, 0x01..=0x07 | 0x0E..=0x1A | 0x1C..=0x1F | 0x7F)
+}
+
+/// One step of byte edit history: `removed` was replaced by `inserted`.
+#[derive(Debug, Clone)]
+struct ByteEdit {
+    /// Identifies the state after this edit, for tracking saves
+    id: u64,
+    offset: usize,
+    removed: Vec<u8>,
+    inserted: Vec<u8>,
+}
+
+/// A byte-addressed buffer for editing binary files.
+///
+/// Offsets are byte indices. Edits can overwrite bytes in place (the
+/// usual hex editor mode) or insert and delete them, and each edit is
+/// one undo step.
+#[derive(Debug, Clone)]
+pub struct ByteBuffer {
+    data: Vec<u8>,
+    undo_stack: Vec<ByteEdit>,
+    redo_stack: Vec<ByteEdit>,
+    /// Maximum undo steps to keep
+    max_history: usize,
+    /// Id for the next edit
+    next_id: u64,
+    /// Id of the state last saved (0 = as loaded)
+    saved_id: u64,
+    file_path: Option<PathBuf>,
+}
+
+impl ByteBuffer {
+    /// Creates a buffer holding `data`.
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            max_history: 1000,
+            next_id: 1,
+            saved_id: 0,
+            file_path: None,
+        }
+    }
+
+    /// Loads a file as raw bytes.
+    pub fn from_file(path: impl AsRef<Path>) -> BufferResult<Self> {
+        let path = path.as_ref();
+        let mut buffer = Self::from_bytes(std::fs::read(path)?);
+        buffer.file_path = Some(path.to_path_buf());
+        Ok(buffer)
+    }
+
+    // ==================== Access ====================
+
+    /// Returns the number of bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true if the buffer holds no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the byte at `offset`.
+    pub fn get(&self, offset: usize) -> Option<u8> {
+        self.data.get(offset).copied()
+    }
+
+    /// Returns a range of bytes.
+    pub fn bytes(&self, range: Range<usize>) -> BufferResult<&[u8]> {
+        self.check_range(&range)?;
+        Ok(&self.data[range])
+    }
+
+    /// Returns the whole content.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of rows in a dump `bytes_per_row` wide.
+    pub fn rows(&self, bytes_per_row: usize) -> usize {
+        self.data.len().div_ceil(bytes_per_row.max(1))
+    }
+
+    /// Formats one row of a hex dump, `hexdump -C` style:
+    ///
+    /// ```text
+    /// 00000010  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|
+    /// ```
+    ///
+    /// Bytes outside printable ASCII show as `.` on the right.
+    pub fn hex_row(&self, row: usize, bytes_per_row: usize) -> Option<String> {
+        let bytes_per_row = bytes_per_row.max(1);
+        let start = row.checked_mul(bytes_per_row)?;
+        if start >= self.data.len() {
+            return None;
+        }
+        let bytes = &self.data[start..(start + bytes_per_row).min(self.data.len())];
+
+        let mut line = format!("{start:08x} ");
+        for i in 0..bytes_per_row {
+            if i % 8 == 0 {
+                line.push(' ');
+            }
+            match bytes.get(i) {
+                Some(b) => line.push_str(&format!("{b:02x} ")),
+                None => line.push_str("   "),
+            }
+        }
+        line.push_str(" |");
+        line.extend(bytes.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        line.push('|');
+        Some(line)
+    }
+
+    // ==================== Editing ====================
+
+    /// Replaces bytes starting at `offset` with `bytes`.
+    ///
+    /// Bytes past the end of the buffer are appended.
+    pub fn overwrite(&mut self, offset: usize, bytes: &[u8]) -> BufferResult<()> {
+        if offset > self.data.len() {
+            return Err(BufferError::InvalidByteIndex(offset));
+        }
+        let end = (offset + bytes.len()).min(self.data.len());
+        self.apply(offset, end, bytes.to_vec());
+        Ok(())
+    }
+
+    /// Inserts bytes at `offset`, shifting the rest along.
+    pub fn insert(&mut self, offset: usize, bytes: &[u8]) -> BufferResult<()> {
+        if offset > self.data.len() {
+            return Err(BufferError::InvalidByteIndex(offset));
+        }
+        self.apply(offset, offset, bytes.to_vec());
+        Ok(())
+    }
+
+    /// Deletes a range of bytes, returning them.
+    pub fn delete(&mut self, range: Range<usize>) -> BufferResult<Vec<u8>> {
+        self.check_range(&range)?;
+        let removed = self.data[range.clone()].to_vec();
+        self.apply(range.start, range.end, Vec::new());
+        Ok(removed)
+    }
+
+    /// Records and performs an edit.
+    fn apply(&mut self, offset: usize, end: usize, inserted: Vec<u8>) {
+        if end == offset && inserted.is_empty() {
+            return;
+        }
+        let removed: Vec<u8> = self
+            .data
+            .splice(offset..end, inserted.iter().copied())
+            .collect();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.undo_stack.push(ByteEdit {
+            id,
+            offset,
+            removed,
+            inserted,
+        });
+        if self.undo_stack.len() > self.max_history {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    // ==================== Undo/Redo ====================
+
+    /// Undoes the last edit, returning the offset it was made at.
+    pub fn undo(&mut self) -> BufferResult<usize> {
+        let edit = self.undo_stack.pop().ok_or(BufferError::NothingToUndo)?;
+        let offset = edit.offset;
+        self.data.splice(
+            offset..offset + edit.inserted.len(),
+            edit.removed.iter().copied(),
+        );
+        self.redo_stack.push(edit);
+        Ok(offset)
+    }
+
+    /// Redoes the last undone edit, returning the offset it was made at.
+    pub fn redo(&mut self) -> BufferResult<usize> {
+        let edit = self.redo_stack.pop().ok_or(BufferError::NothingToRedo)?;
+        let offset = edit.offset;
+        self.data.splice(
+            offset..offset + edit.removed.len(),
+            edit.inserted.iter().copied(),
+        );
+        self.undo_stack.push(edit);
+        Ok(offset)
+    }
+
+    /// Returns true if there are edits to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Returns true if there are edits to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // ==================== Files ====================
+
+    /// Returns true if the content differs from the last save.
+    ///
+    /// Undoing back to the saved state counts as unmodified.
+    pub fn is_modified(&self) -> bool {
+        self.current_id() != self.saved_id
+    }
+
+    /// Returns the associated file path, if any.
+    pub fn file_path(&self) -> Option<&Path> {
+        self.file_path.as_deref()
+    }
+
+    /// Saves the buffer to its associated file.
+    pub fn save(&mut self) -> BufferResult<()> {
+        let path = self
+            .file_path
+            .clone()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No file path set"))?;
+        self.save_as(&path)
+    }
+
+    /// Saves the buffer to a specific path.
+    pub fn save_as(&mut self, path: impl AsRef<Path>) -> BufferResult<()> {
+        let path = path.as_ref();
+
+        atomic::write_file(path, &self.data)?;
+
+        self.file_path = Some(path.to_path_buf());
+        self.saved_id = self.current_id();
+        Ok(())
+    }
+
+    /// Id of the state the buffer is in.
+    fn current_id(&self) -> u64 {
+        self.undo_stack.last().map_or(0, |edit| edit.id)
+    }
+
+    /// Fails unless `range` lies within the buffer.
+    fn check_range(&self, range: &Range<usize>) -> BufferResult<()> {
+        if range.start > range.end {
+            return Err(BufferError::InvalidByteIndex(range.start));
+        }
+        if range.end > self.data.len() {
+            return Err(BufferError::InvalidByteIndex(range.end));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ByteBuffer {
+    fn default() -> Self {
+        Self::from_bytes(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary(b"fn main() {\n\tprintln!(\"hi\");\r\n}\n"));
+        assert!(!is_binary("héllo wörld".as_bytes()));
+        // UTF-16 is full of NULs but is text
+        let utf16: Vec<u8> = "hello".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert!(!is_binary(&utf16));
+        // Legacy 8-bit text is invalid UTF-8 but has no controls
+        assert!(!is_binary(b"\xCF\xF0\xE8\xE2\xE5\xF2 \xEC\xE8\xF0\n"));
+
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x03\x04\x05\x06"));
+        assert!(is_binary(&[
+            0xFF, 0xD8, 0xFF, 0xE0, 0x10, 0x4A, 0x46, 0x49, 0x46, 0x01
+        ]));
+    }
+
+    #[test]
+    fn test_byte_editing_and_undo() {
+        let mut buffer = ByteBuffer::from_bytes(vec![0x00, 0x11, 0x22, 0x33]);
+        buffer.overwrite(1, &[0xAA]).unwrap();
+        buffer.insert(0, &[0xFF, 0xFE]).unwrap();
+        assert_eq!(buffer.delete(4..5).unwrap(), [0x22]);
+        // Overwriting past the end appends
+        buffer.overwrite(4, &[0x44, 0x55]).unwrap();
+        assert_eq!(buffer.as_bytes(), [0xFF, 0xFE, 0x00, 0xAA, 0x44, 0x55]);
+        assert!(buffer.overwrite(7, &[0]).is_err());
+        assert!(buffer.delete(5..7).is_err());
+
+        assert_eq!(buffer.undo().unwrap(), 4);
+        assert_eq!(buffer.undo().unwrap(), 4);
+        assert_eq!(buffer.undo().unwrap(), 0);
+        assert_eq!(buffer.as_bytes(), [0x00, 0xAA, 0x22, 0x33]);
+        buffer.redo().unwrap();
+        assert_eq!(buffer.bytes(0..3).unwrap(), [0xFF, 0xFE, 0x00]);
+    }
+
+    #[test]
+    fn test_hex_rows() {
+        let buffer = ByteBuffer::from_bytes(b"Hello, world!\n\x00\xffxyz".to_vec());
+        assert_eq!(buffer.rows(16), 2);
+        assert_eq!(
+            buffer.hex_row(0, 16).unwrap(),
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|"
+        );
+        assert_eq!(
+            buffer.hex_row(1, 16).unwrap(),
+            "00000010  78 79 7a                                          |xyz|"
+        );
+        assert_eq!(buffer.hex_row(2, 16), None);
+    }
+
+    #[test]
+    fn test_save_tracks_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, [1, 2, 3]).unwrap();
+
+        let mut buffer = ByteBuffer::from_file(&path).unwrap();
+        assert!(!buffer.is_modified());
+        buffer.overwrite(0, &[9]).unwrap();
+        assert!(buffer.is_modified());
+        buffer.undo().unwrap();
+        assert!(!buffer.is_modified());
+
+        buffer.redo().unwrap();
+        buffer.save().unwrap();
+        assert!(!buffer.is_modified());
+        assert_eq!(std::fs::read(&path).unwrap(), [9, 2, 3]);
+        buffer.undo().unwrap();
+        assert!(buffer.is_modified());
+    }
+}
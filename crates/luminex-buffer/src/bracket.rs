@@ -0,0 +1,195 @@
+//! Finding the partner of a bracket.
+//!
+//! ## Learning: Counting Depth
+//!
+//! The `)` matching a `(` isn't the next `)`, it's the first one that
+//! brings the nesting depth back to zero:
+//!
+//! ```text
+//!   f(a, g(b), c)
+//!    ^ depth 1  ^ back to 0: the partner
+//!          ^ ^ depth 2, then 1
+//! ```
+//!
+//! Only brackets of the same kind count, so `[` and `{` in between are
+//! ignored. Without a parser the scan can't tell code from the text of
+//! strings and comments, so a `")"` in a string throws the count off.
+//! Callers that have a syntax tree pass a predicate saying which chars
+//! are code; the rest are skipped. Brackets escaped with a backslash
+//! are always skipped.
+
+use ropey::Rope;
+use std::ops::Range;
+
+/// The bracket pairs matching works on. `<` and `>` are left out:
+/// they are more often comparison operators than brackets.
+pub const BRACKET_PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// A bracket and its partner, as char indices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BracketMatch {
+    /// The bracket at or next to the cursor
+    pub bracket: usize,
+    /// The bracket it pairs with
+    pub partner: usize,
+}
+
+impl BracketMatch {
+    /// Returns the index of the opening bracket.
+    pub fn open(&self) -> usize {
+        self.bracket.min(self.partner)
+    }
+
+    /// Returns the index of the closing bracket.
+    pub fn close(&self) -> usize {
+        self.bracket.max(self.partner)
+    }
+
+    /// Returns the range from the opening bracket through the closing one.
+    pub fn range(&self) -> Range<usize> {
+        self.open()..self.close() + 1
+    }
+}
+
+/// Finds the partner of the bracket at `char_idx`, or else of the one
+/// just before it. Chars for which `is_code` is false are skipped.
+pub(crate) fn matching_bracket(
+    rope: &Rope,
+    char_idx: usize,
+    is_code: &impl Fn(usize) -> bool,
+) -> Option<BracketMatch> {
+    let candidates = [Some(char_idx), char_idx.checked_sub(1)];
+    for bracket in candidates.into_iter().flatten() {
+        if bracket >= rope.len_chars() || !counts(rope, bracket, is_code) {
+            continue;
+        }
+        let c = rope.char(bracket);
+        let partner = BRACKET_PAIRS.iter().find_map(|&(open, close)| {
+            if c == open {
+                matching_close(rope, bracket, open, close, is_code)
+            } else if c == close {
+                enclosing_open(rope, bracket, open, close, is_code)
+            } else {
+                None
+            }
+        });
+        if let Some(partner) = partner {
+            return Some(BracketMatch { bracket, partner });
+        }
+    }
+    None
+}
+
+/// Returns the index of the unmatched `open` before `char_idx`.
+pub(crate) fn enclosing_open(
+    rope: &Rope,
+    char_idx: usize,
+    open: char,
+    close: char,
+    is_code: &impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut chars = rope.chars_at(char_idx);
+    let mut idx = char_idx;
+    while let Some(c) = chars.prev() {
+        idx -= 1;
+        if (c != open && c != close) || !counts(rope, idx, is_code) {
+            continue;
+        }
+        if c == close {
+            depth += 1;
+        } else if depth == 0 {
+            return Some(idx);
+        } else {
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// Returns the index of the `close` matching the `open` at `open_idx`.
+pub(crate) fn matching_close(
+    rope: &Rope,
+    open_idx: usize,
+    open: char,
+    close: char,
+    is_code: &impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in rope.chars_at(open_idx + 1).enumerate() {
+        let idx = open_idx + 1 + i;
+        if (c != open && c != close) || !counts(rope, idx, is_code) {
+            continue;
+        }
+        if c == open {
+            depth += 1;
+        } else if depth == 0 {
+            return Some(idx);
+        } else {
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// True if the char at `char_idx` is code and not escaped.
+fn counts(rope: &Rope, char_idx: usize, is_code: &impl Fn(usize) -> bool) -> bool {
+    is_code(char_idx) && !is_escaped(rope, char_idx)
+}
+
+/// True if the char at `char_idx` follows an odd number of backslashes.
+pub(crate) fn is_escaped(rope: &Rope, char_idx: usize) -> bool {
+    let mut chars = rope.chars_at(char_idx);
+    let mut backslashes = 0;
+    while chars.prev() == Some('\\') {
+        backslashes += 1;
+    }
+    backslashes % 2 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partner(text: &str, char_idx: usize) -> Option<usize> {
+        matching_bracket(&Rope::from_str(text), char_idx, &|_| true).map(|m| m.partner)
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let text = "f(a, g[b], {c})";
+        assert_eq!(partner(text, 1), Some(14));
+        assert_eq!(partner(text, 14), Some(1));
+        // Just after a bracket
+        assert_eq!(partner(text, 15), Some(1));
+        assert_eq!(partner(text, 7), Some(8));
+        assert_eq!(partner(text, 11), Some(13));
+        assert_eq!(partner(text, 3), None);
+        // Unbalanced
+        assert_eq!(partner("(()", 0), None);
+        assert_eq!(partner("(()", 1), Some(2));
+        // Escaped brackets don't count
+        assert_eq!(partner(r"(\))", 0), Some(3));
+        assert_eq!(partner(r"(\\)", 0), Some(3));
+    }
+
+    #[test]
+    fn test_skips_non_code() {
+        let text = r#"f(")", x)"#;
+        let rope = Rope::from_str(text);
+        assert_eq!(partner(text, 1), Some(3));
+        // Chars 2..5 are a string
+        let is_code = |idx: usize| !(2..5).contains(&idx);
+        let found = matching_bracket(&rope, 1, &is_code).unwrap();
+        assert_eq!(
+            found,
+            BracketMatch {
+                bracket: 1,
+                partner: 8
+            }
+        );
+        assert_eq!(found.range(), 1..9);
+        // The bracket in the string itself has no partner
+        assert_eq!(matching_bracket(&rope, 3, &is_code), None);
+    }
+}
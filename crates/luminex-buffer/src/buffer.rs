@@ -18,11 +18,25 @@
 //! ```
 
 use ropey::Rope;
-use std::ops::Range;
+use std::ops::{ControlFlow, Range};
 use std::path::Path;
 
-use crate::history::{Edit, EditKind, History};
-use crate::{BufferError, BufferResult, Position};
+use crate::anchor::{AnchorId, Anchors};
+use crate::atomic;
+use crate::bracket::{self, BracketMatch};
+use crate::change_set::ChangeSet;
+use crate::encoding::TextEncoding;
+use crate::grapheme;
+use crate::history::{Edit, EditKind, History, UndoBranch, UndoStep, UndoTarget};
+use crate::indent::IndentStyle;
+use crate::line_ending::LineEnding;
+use crate::loader::FileLoader;
+use crate::search::{self, SearchQuery};
+use crate::snapshot::BufferSnapshot;
+use crate::text_object::{self, ObjectExtent, TextObject};
+use crate::undo_cache::UndoCache;
+use crate::word::{self, WordKind};
+use crate::{Affinity, BufferError, BufferResult, MultiCursor, Position};
 
 /// A high-performance text buffer backed by a rope data structure.
 ///
@@ -30,7 +44,9 @@ use crate::{BufferError, BufferResult, Position};
 ///
 /// `TextBuffer` is `Send` but not `Sync` - it can be moved between threads
 /// but shouldn't be accessed from multiple threads simultaneously.
-/// For concurrent editing, clone the buffer or use message passing.
+/// To read the text from another task, take a [`BufferSnapshot`]
+/// with [`TextBuffer::snapshot`]; cloning the buffer would copy the
+/// whole undo history too.
 #[derive(Debug, Clone)]
 pub struct TextBuffer {
     /// The rope holding our text content
@@ -45,6 +61,18 @@ pub struct TextBuffer {
     /// Associated file path (if any)
     file_path: Option<std::path::PathBuf>,
 
+    /// Encoding used when reading and writing the file
+    encoding: TextEncoding,
+
+    /// Line ending written on save (the rope only holds `\n`)
+    line_ending: LineEnding,
+
+    /// Bumped on every change a snapshot can observe
+    version: u64,
+
+    /// Positions kept in place across edits (marks, jump list entries)
+    anchors: Anchors,
+
     /// Buffer-specific settings
     config: BufferConfig,
 }
@@ -63,6 +91,13 @@ pub struct BufferConfig {
 
     /// Auto-detect indentation from file
     pub detect_indentation: bool,
+
+    /// Where undo history is kept between sessions (`None`, the
+    /// default, disables it)
+    pub undo_cache: Option<UndoCache>,
+
+    /// Merge rapid keystrokes into one undo step
+    pub coalesce_edits: bool,
 }
 
 impl Default for BufferConfig {
@@ -72,6 +107,8 @@ impl Default for BufferConfig {
             tab_width: 4,
             use_spaces: true,
             detect_indentation: true,
+            undo_cache: None,
+            coalesce_edits: true,
         }
     }
 }
@@ -92,17 +129,27 @@ impl TextBuffer {
             history: History::new(1000),
             modified: false,
             file_path: None,
+            encoding: TextEncoding::default(),
+            line_ending: LineEnding::default(),
+            version: 0,
+            anchors: Anchors::default(),
             config: BufferConfig::default(),
         }
     }
 
     /// Creates a buffer with custom configuration.
     pub fn with_config(config: BufferConfig) -> Self {
+        let mut history = History::new(config.max_history);
+        history.set_coalescing(config.coalesce_edits);
         Self {
             rope: Rope::new(),
-            history: History::new(config.max_history),
+            history,
             modified: false,
             file_path: None,
+            encoding: TextEncoding::default(),
+            line_ending: LineEnding::default(),
+            version: 0,
+            anchors: Anchors::default(),
             config,
         }
     }
@@ -120,17 +167,71 @@ impl TextBuffer {
     /// }
     /// ```
     pub fn from_file(path: impl AsRef<Path>) -> BufferResult<Self> {
-        let path = path.as_ref();
-        let content = std::fs::read_to_string(path)?;
-        let rope = Rope::from_str(&content);
+        Self::from_file_with_fallback(path, TextEncoding::default())
+    }
+
+    /// Loads a buffer from a file with custom configuration.
+    ///
+    /// With an `undo_cache`, undo history saved by an earlier session is
+    /// restored if the file hasn't changed since (see [`UndoCache`]).
+    pub fn from_file_with_config(
+        path: impl AsRef<Path>,
+        config: BufferConfig,
+    ) -> BufferResult<Self> {
+        FileLoader::open(path, TextEncoding::default())?.finish(config)
+    }
 
-        Ok(Self {
+    /// Loads a buffer from a file, detecting its encoding.
+    ///
+    /// `fallback` is used when the content is neither UTF-8 nor UTF-16
+    /// (see [`TextEncoding::detect`]).
+    pub fn from_file_with_fallback(
+        path: impl AsRef<Path>,
+        fallback: TextEncoding,
+    ) -> BufferResult<Self> {
+        FileLoader::open(path, fallback)?.finish(BufferConfig::default())
+    }
+
+    /// Loads a buffer from a file using an explicit encoding.
+    ///
+    /// A BOM matching the encoding is still recognized and kept.
+    pub fn from_file_with_encoding(
+        path: impl AsRef<Path>,
+        encoding: TextEncoding,
+    ) -> BufferResult<Self> {
+        FileLoader::open_with_encoding(path, encoding)?.finish(BufferConfig::default())
+    }
+
+    /// Assembles a buffer for a file that has just been read.
+    ///
+    /// Undo history saved by an earlier session is picked up if
+    /// `content_hash` still matches.
+    pub(crate) fn from_loaded(
+        path: &Path,
+        rope: Rope,
+        content_hash: u64,
+        encoding: TextEncoding,
+        line_ending: LineEnding,
+        config: BufferConfig,
+    ) -> Self {
+        let mut history = config
+            .undo_cache
+            .as_ref()
+            .and_then(|cache| cache.load_hashed(path, content_hash))
+            .unwrap_or_else(|| History::new(config.max_history));
+        history.set_coalescing(config.coalesce_edits);
+
+        Self {
             rope,
-            history: History::new(1000),
+            history,
             modified: false,
             file_path: Some(path.to_path_buf()),
-            config: BufferConfig::default(),
-        })
+            encoding,
+            line_ending,
+            version: 0,
+            anchors: Anchors::default(),
+            config,
+        }
     }
 
     /// Saves the buffer to its associated file.
@@ -145,10 +246,14 @@ impl TextBuffer {
     pub fn save_as(&mut self, path: impl AsRef<Path>) -> BufferResult<()> {
         let path = path.as_ref();
 
-        // Write to a temporary file first, then rename (atomic write)
-        let temp_path = path.with_extension("tmp");
-        std::fs::write(&temp_path, self.text().as_bytes())?;
-        std::fs::rename(&temp_path, path)?;
+        let text = self.text();
+        let bytes = self.encoding.encode(&self.line_ending.apply(&text))?;
+        atomic::write_file(path, &bytes)?;
+
+        // The file is safe; losing its undo history is not worth an error
+        if let Some(cache) = &self.config.undo_cache {
+            let _ = cache.store(path, &bytes, &self.history);
+        }
 
         self.file_path = Some(path.to_path_buf());
         self.modified = false;
@@ -195,6 +300,29 @@ impl TextBuffer {
         Ok(self.rope.char(idx))
     }
 
+    /// Returns an immutable, cheaply cloned view of the current text.
+    ///
+    /// The snapshot shares the rope's chunks, so this is O(1) no matter
+    /// how large the buffer is. It can be sent to another task and
+    /// compared against [`version`](Self::version) later.
+    pub fn snapshot(&self) -> BufferSnapshot {
+        BufferSnapshot::new(
+            self.rope.clone(),
+            self.version,
+            self.encoding,
+            self.line_ending,
+        )
+    }
+
+    /// Returns the buffer's version.
+    ///
+    /// It starts at 0 and grows with every edit, undo and redo, and
+    /// when the encoding or line ending changes. It never goes back, so
+    /// undoing to earlier text still gives a new version.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     // ==================== Measurements ====================
 
     /// Returns true if the buffer is empty.
@@ -242,6 +370,9 @@ impl TextBuffer {
 
     /// Inserts text at a character index.
     ///
+    /// `\r\n` and `\r` in `text` are stored as `\n`, like the rest of the
+    /// buffer; the file's line ending is only applied when saving.
+    ///
     /// # Learning: `&mut self`
     ///
     /// This method requires exclusive (mutable) access to the buffer.
@@ -251,6 +382,7 @@ impl TextBuffer {
         if char_idx > self.len_chars() {
             return Err(BufferError::InvalidCharIndex(char_idx));
         }
+        let text = LineEnding::normalize(text);
 
         // Record edit for undo
         let edit = Edit {
@@ -261,8 +393,10 @@ impl TextBuffer {
         self.history.push(edit);
 
         // Perform the insertion
-        self.rope.insert(char_idx, text);
+        self.rope.insert(char_idx, &text);
+        self.anchors.insert(char_idx, text.chars().count());
         self.modified = true;
+        self.version += 1;
 
         Ok(())
     }
@@ -289,13 +423,17 @@ impl TextBuffer {
         self.history.push(edit);
 
         // Perform deletion
-        self.rope.remove(range);
+        self.rope.remove(range.clone());
+        self.anchors.delete(range);
         self.modified = true;
+        self.version += 1;
 
         Ok(deleted)
     }
 
     /// Replaces text in a range with new text.
+    ///
+    /// Line endings in `text` are normalized as by [`TextBuffer::insert`].
     pub fn replace(&mut self, range: Range<usize>, text: &str) -> BufferResult<String> {
         // This is a composite operation: delete + insert
         // For undo purposes, we treat it as two separate edits
@@ -304,50 +442,119 @@ impl TextBuffer {
         Ok(deleted)
     }
 
+    /// Applies a change set as a single undo step.
+    ///
+    /// Nothing is changed if the set is invalid. Returns the change set
+    /// that undoes this one.
+    pub fn apply_changes(&mut self, changes: &ChangeSet) -> BufferResult<ChangeSet> {
+        let inverse = changes.invert(self)?;
+
+        // Apply back to front so earlier ranges stay valid
+        self.history.begin_group();
+        for change in changes.changes().iter().rev() {
+            if !change.range.is_empty()
+                && let Err(e) = self.delete(change.range.clone())
+            {
+                self.history.end_group();
+                return Err(e);
+            }
+            if !change.text.is_empty()
+                && let Err(e) = self.insert(change.range.start, &change.text)
+            {
+                self.history.end_group();
+                return Err(e);
+            }
+        }
+        self.history.end_group();
+
+        Ok(inverse)
+    }
+
     // ==================== Undo/Redo ====================
 
-    /// Undoes the last edit.
+    /// Undoes the last edit group.
     ///
     /// # Learning: State Management
     ///
-    /// Each edit is stored in a history stack. Undo pops from the
-    /// undo stack and pushes to the redo stack. This is a classic
-    /// "command pattern" implementation.
-    pub fn undo(&mut self) -> BufferResult<()> {
-        let edit = self.history.undo().ok_or(BufferError::NothingToUndo)?;
-
-        // Apply inverse operation WITHOUT recording to history
-        match edit.kind {
-            EditKind::Insert => {
-                let end = edit.position + edit.content.chars().count();
-                self.rope.remove(edit.position..end);
-            }
-            EditKind::Delete => {
-                self.rope.insert(edit.position, &edit.content);
-            }
-        }
+    /// Each edit is stored in an undo tree. Undo moves to the parent
+    /// state and redo back down the most recently visited branch. This
+    /// is a classic "command pattern" implementation.
+    ///
+    /// The returned step carries the cursors recorded before the group
+    /// (see [`TextBuffer::record_cursors`]).
+    pub fn undo(&mut self) -> BufferResult<UndoStep> {
+        let step = self.history.undo().ok_or(BufferError::NothingToUndo)?;
+        self.apply_history_edits(&step.edits);
+        Ok(step)
+    }
 
-        self.modified = true;
-        Ok(())
+    /// Redoes the last undone edit group.
+    ///
+    /// The returned step carries the cursors recorded after the group
+    /// (see [`TextBuffer::record_cursors_after`]).
+    pub fn redo(&mut self) -> BufferResult<UndoStep> {
+        let step = self.history.redo().ok_or(BufferError::NothingToRedo)?;
+        self.apply_history_edits(&step.edits);
+        Ok(step)
+    }
+
+    /// Moves to another state of the undo tree.
+    ///
+    /// Returns `None` if the target is the current state or doesn't exist.
+    pub fn jump_history(&mut self, target: UndoTarget) -> Option<UndoStep> {
+        let step = self.history.jump(target)?;
+        self.apply_history_edits(&step.edits);
+        Some(step)
+    }
+
+    /// Starts an undo group: edits until the matching
+    /// [`TextBuffer::end_undo_group`] undo as one step.
+    ///
+    /// Groups nest, so grouped operations can call each other.
+    pub fn begin_undo_group(&mut self) {
+        self.history.begin_group();
     }
 
-    /// Redoes the last undone edit.
-    pub fn redo(&mut self) -> BufferResult<()> {
-        let edit = self.history.redo().ok_or(BufferError::NothingToRedo)?;
+    /// Ends the innermost undo group.
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
 
-        // Re-apply the operation WITHOUT recording to history
-        match edit.kind {
-            EditKind::Insert => {
-                self.rope.insert(edit.position, &edit.content);
-            }
-            EditKind::Delete => {
-                let end = edit.position + edit.content.chars().count();
-                self.rope.remove(edit.position..end);
+    /// Returns the tips of all undo branches, oldest first.
+    pub fn undo_branches(&self) -> Vec<UndoBranch> {
+        self.history.branches()
+    }
+
+    /// Applies edits from the history WITHOUT recording them.
+    fn apply_history_edits(&mut self, edits: &[Edit]) {
+        for edit in edits {
+            match edit.kind {
+                EditKind::Insert => {
+                    self.rope.insert(edit.position, &edit.content);
+                    self.anchors
+                        .insert(edit.position, edit.content.chars().count());
+                }
+                EditKind::Delete => {
+                    let end = edit.position + edit.content.chars().count();
+                    self.rope.remove(edit.position..end);
+                    self.anchors.delete(edit.position..end);
+                }
             }
         }
-
         self.modified = true;
-        Ok(())
+        self.version += 1;
+    }
+
+    /// Remembers the cursors to restore when the next edit is undone.
+    pub fn record_cursors(&mut self, cursors: &MultiCursor) {
+        self.history.set_cursors(cursors.clone());
+    }
+
+    /// Remembers the cursors after the latest edit, restored when it is
+    /// redone. Does nothing if nothing was edited since the last undo,
+    /// redo or history jump.
+    pub fn record_cursors_after(&mut self, cursors: &MultiCursor) {
+        self.history.set_cursors_after(cursors.clone());
     }
 
     /// Returns true if there are edits to undo.
@@ -403,6 +610,215 @@ impl TextBuffer {
         Ok(Position { line, column })
     }
 
+    /// Converts a byte offset, as parsers report them, to a character index.
+    pub fn byte_to_char_idx(&self, byte_idx: usize) -> BufferResult<usize> {
+        if byte_idx > self.len_bytes() {
+            return Err(BufferError::InvalidByteIndex(byte_idx));
+        }
+        Ok(self.rope.byte_to_char(byte_idx))
+    }
+
+    // ==================== Anchors ====================
+
+    /// Starts tracking a position through edits (see [`AnchorId`]).
+    pub fn add_anchor(&mut self, char_idx: usize, affinity: Affinity) -> BufferResult<AnchorId> {
+        if char_idx > self.len_chars() {
+            return Err(BufferError::InvalidCharIndex(char_idx));
+        }
+        Ok(self.anchors.add(char_idx, affinity))
+    }
+
+    /// Returns the char index an anchor has moved to.
+    pub fn anchor(&self, id: AnchorId) -> Option<usize> {
+        self.anchors.get(id)
+    }
+
+    /// Returns the position an anchor has moved to.
+    pub fn anchor_position(&self, id: AnchorId) -> Option<Position> {
+        self.char_idx_to_position(self.anchor(id)?).ok()
+    }
+
+    /// Stops tracking an anchor. Returns false if it didn't exist.
+    pub fn remove_anchor(&mut self, id: AnchorId) -> bool {
+        self.anchors.remove(id)
+    }
+
+    // ==================== Graphemes & Display Columns ====================
+
+    /// Returns the char index of the next grapheme boundary after `char_idx`.
+    ///
+    /// A line break is its own grapheme, so this steps onto the next
+    /// line from the end of a line.
+    pub fn next_grapheme_boundary(&self, char_idx: usize) -> usize {
+        if char_idx >= self.len_chars() {
+            return self.len_chars();
+        }
+        let line = self.rope.char_to_line(char_idx);
+        let line_start = self.rope.line_to_char(line);
+        let text: std::borrow::Cow<'_, str> = self.rope.line(line).into();
+        line_start + grapheme::next_boundary(&text, char_idx - line_start)
+    }
+
+    /// Returns the char index of the previous grapheme boundary before `char_idx`.
+    pub fn prev_grapheme_boundary(&self, char_idx: usize) -> usize {
+        let char_idx = char_idx.min(self.len_chars());
+        if char_idx == 0 {
+            return 0;
+        }
+        let line = self.rope.char_to_line(char_idx);
+        let line_start = self.rope.line_to_char(line);
+        if char_idx == line_start {
+            // Step back over the previous line's `\n`
+            return char_idx - 1;
+        }
+        let text: std::borrow::Cow<'_, str> = self.rope.line(line).into();
+        line_start + grapheme::prev_boundary(&text, char_idx - line_start)
+    }
+
+    /// Returns the display column of a position: the number of cells
+    /// before it, counting wide characters as two and expanding tabs.
+    pub fn display_column(&self, pos: Position, tab_width: usize) -> BufferResult<usize> {
+        let line = self.line(pos.line)?;
+        let end = line
+            .char_indices()
+            .nth(pos.column)
+            .map_or(line.len(), |(i, _)| i);
+        Ok(grapheme::display_width(&line[..end], tab_width))
+    }
+
+    /// Returns the char column on `line` at (or just before) a display column.
+    ///
+    /// Used for vertical movement, which keeps the visual column.
+    pub fn column_at_display(
+        &self,
+        line: usize,
+        display_column: usize,
+        tab_width: usize,
+    ) -> BufferResult<usize> {
+        let text = self.line(line)?;
+        Ok(grapheme::column_at_display(
+            &text,
+            display_column,
+            tab_width,
+        ))
+    }
+
+    // ==================== Words ====================
+
+    /// Returns the char index of the end of the next word after `char_idx`.
+    ///
+    /// Crosses line breaks; returns the end of the buffer if no word follows.
+    pub fn next_word_end(&self, char_idx: usize, kind: WordKind) -> usize {
+        let mut idx = char_idx.min(self.len_chars());
+        let mut line = self.rope.char_to_line(idx);
+        loop {
+            let line_start = self.rope.line_to_char(line);
+            let text = self.line_content(line);
+            if let Some(end) = word::next_word_end(&text, idx - line_start, kind) {
+                return line_start + end;
+            }
+            line += 1;
+            if line >= self.rope.len_lines() {
+                return self.len_chars();
+            }
+            idx = self.rope.line_to_char(line);
+        }
+    }
+
+    /// Returns the char index of the start of the previous word before `char_idx`.
+    ///
+    /// Crosses line breaks; returns 0 if no word precedes it.
+    pub fn prev_word_start(&self, char_idx: usize, kind: WordKind) -> usize {
+        let mut idx = char_idx.min(self.len_chars());
+        let mut line = self.rope.char_to_line(idx);
+        loop {
+            let line_start = self.rope.line_to_char(line);
+            let text = self.line_content(line);
+            if let Some(start) = word::prev_word_start(&text, idx - line_start, kind) {
+                return line_start + start;
+            }
+            if line == 0 {
+                return 0;
+            }
+            line -= 1;
+            idx = line_start - 1;
+        }
+    }
+
+    /// Returns the char range of the word at `char_idx`.
+    ///
+    /// Prefers a word touching `char_idx` over whitespace or punctuation.
+    /// Returns `None` on an empty line.
+    pub fn word_range(&self, char_idx: usize, kind: WordKind) -> Option<Range<usize>> {
+        let char_idx = char_idx.min(self.len_chars());
+        let line = self.rope.char_to_line(char_idx);
+        let line_start = self.rope.line_to_char(line);
+        let text = self.line_content(line);
+        let range = word::word_at(&text, char_idx - line_start, kind)?;
+        Some(line_start + range.start..line_start + range.end)
+    }
+
+    /// Returns a line without its line break.
+    pub(crate) fn line_content(&self, line: usize) -> std::borrow::Cow<'_, str> {
+        let slice = self.rope.line(line);
+        let len = slice.len_chars();
+        let end = if len > 0 && slice.char(len - 1) == '\n' {
+            len - 1
+        } else {
+            len
+        };
+        slice.slice(..end).into()
+    }
+
+    // ==================== Text Objects ====================
+
+    /// Finds a text object around `range` (an empty range is a cursor).
+    ///
+    /// If `range` already covers the object, bracket objects select the
+    /// next enclosing pair. Returns `None` if there is no such object,
+    /// e.g. no enclosing parentheses.
+    pub fn text_object(
+        &self,
+        range: Range<usize>,
+        object: TextObject,
+        extent: ObjectExtent,
+    ) -> Option<Range<usize>> {
+        if range.start > range.end || range.end > self.len_chars() {
+            return None;
+        }
+        text_object::find(self, range, object, extent)
+    }
+
+    // ==================== Brackets ====================
+
+    /// Finds the partner of the bracket at `char_idx`, or else of the
+    /// bracket just before it.
+    ///
+    /// Scans the plain text, so brackets in strings and comments count.
+    /// Use [`matching_bracket_with`](Self::matching_bracket_with) when
+    /// syntax information is available.
+    pub fn matching_bracket(&self, char_idx: usize) -> Option<BracketMatch> {
+        self.matching_bracket_with(char_idx, |_| true)
+    }
+
+    /// Like [`matching_bracket`](Self::matching_bracket), but skips every
+    /// char for which `is_code` returns false (strings and comments).
+    pub fn matching_bracket_with(
+        &self,
+        char_idx: usize,
+        is_code: impl Fn(usize) -> bool,
+    ) -> Option<BracketMatch> {
+        if char_idx > self.len_chars() {
+            return None;
+        }
+        bracket::matching_bracket(&self.rope, char_idx, &is_code)
+    }
+
+    /// Returns the underlying rope.
+    pub(crate) fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
     // ==================== State Queries ====================
 
     /// Returns true if the buffer has unsaved changes.
@@ -410,11 +826,61 @@ impl TextBuffer {
         self.modified
     }
 
+    /// Marks the buffer as having no unsaved changes.
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+    }
+
     /// Returns the associated file path, if any.
     pub fn file_path(&self) -> Option<&Path> {
         self.file_path.as_deref()
     }
 
+    /// Returns the encoding used when saving.
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Changes the encoding used when saving.
+    ///
+    /// The text itself is unchanged; the file is converted on the
+    /// next save, so this marks the buffer as modified.
+    pub fn set_encoding(&mut self, encoding: TextEncoding) {
+        if encoding != self.encoding {
+            self.encoding = encoding;
+            self.modified = true;
+            self.version += 1;
+        }
+    }
+
+    /// Returns the line ending used when saving.
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Changes the line ending used when saving.
+    ///
+    /// Like [`set_encoding`](Self::set_encoding), this only affects
+    /// how the file is written, so it marks the buffer as modified.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if line_ending != self.line_ending {
+            self.line_ending = line_ending;
+            self.modified = true;
+            self.version += 1;
+        }
+    }
+
+    /// Detects how the buffer's content is indented.
+    ///
+    /// Returns `None` when `BufferConfig::detect_indentation` is off or
+    /// the content doesn't give a clear answer.
+    pub fn detect_indentation(&self) -> Option<IndentStyle> {
+        if !self.config.detect_indentation {
+            return None;
+        }
+        IndentStyle::detect(&self.rope)
+    }
+
     /// Returns the buffer's configuration.
     pub fn config(&self) -> &BufferConfig {
         &self.config
@@ -422,6 +888,7 @@ impl TextBuffer {
 
     /// Sets a new configuration.
     pub fn set_config(&mut self, config: BufferConfig) {
+        self.history.set_coalescing(config.coalesce_edits);
         self.config = config;
     }
 
@@ -431,40 +898,141 @@ impl TextBuffer {
     ///
     /// Returns character indices of each match start.
     pub fn find_all(&self, pattern: &str) -> Vec<usize> {
-        let text: String = self.rope.slice(..).into();
-        text.match_indices(pattern)
-            .map(|(byte_idx, _)| {
-                // Convert byte index to char index
-                text[..byte_idx].chars().count()
-            })
-            .collect()
+        let mut matches = Vec::new();
+        search::for_each_literal(&self.rope, pattern, 0..self.rope.len_bytes(), |byte_idx| {
+            matches.push(self.rope.byte_to_char(byte_idx));
+            ControlFlow::Continue(())
+        });
+        matches
     }
 
     /// Finds the next occurrence starting from a position.
     pub fn find_next(&self, pattern: &str, from: usize) -> Option<usize> {
-        let text: String = self.rope.slice(..).into();
-        let byte_from = text.char_indices().nth(from).map(|(i, _)| i).unwrap_or(0);
-
-        text[byte_from..]
-            .find(pattern)
-            .map(|byte_offset| from + text[byte_from..byte_from + byte_offset].chars().count())
+        if from > self.len_chars() {
+            return None;
+        }
+        let mut found = None;
+        let bytes = self.rope.char_to_byte(from)..self.rope.len_bytes();
+        search::for_each_literal(&self.rope, pattern, bytes, |byte_idx| {
+            found = Some(self.rope.byte_to_char(byte_idx));
+            ControlFlow::Break(())
+        });
+        found
     }
 
     /// Replaces all occurrences of a pattern.
     pub fn replace_all(&mut self, pattern: &str, replacement: &str) -> usize {
         let matches = self.find_all(pattern);
         let pattern_len = pattern.chars().count();
-        let replacement_len = replacement.chars().count();
-        let mut offset: isize = 0;
 
-        for match_idx in &matches {
-            let adjusted_idx = (*match_idx as isize + offset) as usize;
-            let _ = self.replace(adjusted_idx..adjusted_idx + pattern_len, replacement);
-            offset += replacement_len as isize - pattern_len as isize;
+        // Apply back to front so earlier indices stay valid
+        self.history.begin_group();
+        for &match_idx in matches.iter().rev() {
+            let _ = self.replace(match_idx..match_idx + pattern_len, replacement);
         }
+        self.history.end_group();
 
         matches.len()
     }
+
+    /// Finds all matches of a compiled query.
+    ///
+    /// Returns the character range of each match, in document order.
+    pub fn search_all(&self, query: &SearchQuery) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        search::for_each_match(&self.rope, query, 0, |range| {
+            matches.push(range);
+            ControlFlow::Continue(())
+        });
+        matches
+    }
+
+    /// Finds the first match starting at or after `from`, wrapping
+    /// around to the start of the buffer if necessary.
+    ///
+    /// Stops at the first hit, so this never scans more than it must.
+    pub fn search_next(&self, query: &SearchQuery, from: usize) -> Option<Range<usize>> {
+        let from = from.min(self.len_chars());
+        let mut found = None;
+        search::for_each_match(&self.rope, query, from, |range| {
+            if range.start >= from {
+                found = Some(range);
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        });
+        if found.is_none() && from > 0 {
+            // Wrap around
+            search::for_each_match(&self.rope, query, 0, |range| {
+                found = Some(range);
+                ControlFlow::Break(())
+            });
+        }
+        found
+    }
+
+    /// Finds the last match starting before `before`, wrapping
+    /// around to the end of the buffer if necessary.
+    ///
+    /// Scans backwards from `before`, so this never scans more than it must.
+    pub fn search_prev(&self, query: &SearchQuery, before: usize) -> Option<Range<usize>> {
+        let before = before.min(self.len_chars());
+        search::last_match_before(&self.rope, query, before)
+            // Wrap around
+            .or_else(|| search::last_match_before(&self.rope, query, self.len_chars()))
+    }
+
+    /// Replaces every match of a query.
+    ///
+    /// For regex queries the replacement may refer to capture groups
+    /// with `$1`, `${name}` or `$0`; use `$$` for a literal dollar sign.
+    /// Literal queries insert the replacement verbatim.
+    ///
+    /// Returns the number of replacements made.
+    pub fn replace_all_matches(
+        &mut self,
+        query: &SearchQuery,
+        replacement: &str,
+    ) -> BufferResult<usize> {
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        let all = 0..self.rope.len_bytes();
+
+        if let Some(needle) = query.as_literal() {
+            search::for_each_literal(&self.rope, needle, all, |byte_idx| {
+                let range = self.rope.byte_to_char(byte_idx)
+                    ..self.rope.byte_to_char(byte_idx + needle.len());
+                edits.push((range, replacement.to_string()));
+                ControlFlow::Continue(())
+            });
+        } else {
+            let expand = query.options().regex;
+            search::for_each_regex_match(&self.rope, query, all, |bytes, haystack, base| {
+                let mut text = String::new();
+                if expand {
+                    if let Some(caps) = query.regex().captures_at(haystack, bytes.start - base) {
+                        caps.expand(replacement, &mut text);
+                    }
+                } else {
+                    text.push_str(replacement);
+                }
+                let range = self.rope.byte_to_char(bytes.start)..self.rope.byte_to_char(bytes.end);
+                edits.push((range, text));
+                ControlFlow::Continue(())
+            });
+        }
+
+        // Apply back to front so earlier ranges stay valid
+        self.history.begin_group();
+        for (range, text) in edits.iter().rev() {
+            if let Err(e) = self.replace(range.clone(), text) {
+                self.history.end_group();
+                return Err(e);
+            }
+        }
+        self.history.end_group();
+
+        Ok(edits.len())
+    }
 }
 
 impl Default for TextBuffer {
@@ -474,12 +1042,21 @@ impl Default for TextBuffer {
 }
 
 impl From<&str> for TextBuffer {
+    /// Creates a buffer from text, normalizing its line endings.
+    ///
+    /// `\r\n` and `\r` become `\n`, so `text()` may differ from `s`. The
+    /// most common ending in `s` is kept as the buffer's `line_ending`,
+    /// which `save` writes back.
     fn from(s: &str) -> Self {
         Self {
-            rope: Rope::from_str(s),
+            rope: Rope::from_str(&LineEnding::normalize(s)),
             history: History::new(1000),
             modified: false,
             file_path: None,
+            encoding: TextEncoding::default(),
+            line_ending: LineEnding::detect(s),
+            version: 0,
+            anchors: Anchors::default(),
             config: BufferConfig::default(),
         }
     }
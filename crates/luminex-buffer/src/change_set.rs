@@ -0,0 +1,299 @@
+//! Compound edits as values.
+//!
+//! ## Learning: Describe, Then Apply
+//!
+//! A `ChangeSet` is a list of replacements, all expressed in the
+//! coordinates of the text *before* any of them is applied. That makes
+//! it easy to build (a language server sends edits this way) and easy
+//! to reason about: the changes can't invalidate each other's ranges.
+//!
+//! Because the whole edit is one value, we can also ask where any
+//! position ends up after it. Text inserted exactly at a position
+//! pushes it forward or leaves it in place depending on its
+//! [`Affinity`]: `Backward` sticks to the text before, `Forward` to
+//! the text after.
+//!
+//! ```text
+//!   before:  let x = 1;        change: insert "mut " at 4
+//!                ^ 4
+//!   after:   let mut x = 1;    Backward -> 4, Forward -> 8
+//! ```
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::cursor::{Affinity, Position};
+use crate::line_ending::LineEnding;
+use crate::{BufferError, BufferResult, TextBuffer};
+
+/// One replacement: the chars in `range` become `text`.
+///
+/// Inserts have an empty range; deletes have empty text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Char range in the text before the change set
+    pub range: Range<usize>,
+    /// Replacement text
+    pub text: String,
+}
+
+/// Several changes applied together as one edit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+    /// Changes sorted by position; ties keep insertion order
+    changes: Vec<Change>,
+}
+
+impl ChangeSet {
+    /// Creates an empty change set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an insertion.
+    pub fn insert(self, char_idx: usize, text: impl Into<String>) -> Self {
+        self.replace(char_idx..char_idx, text)
+    }
+
+    /// Adds a deletion.
+    pub fn delete(self, range: Range<usize>) -> Self {
+        self.replace(range, String::new())
+    }
+
+    /// Adds a replacement.
+    pub fn replace(mut self, range: Range<usize>, text: impl Into<String>) -> Self {
+        self.push(Change {
+            range,
+            text: text.into(),
+        });
+        self
+    }
+
+    /// Adds a change, keeping the list sorted.
+    ///
+    /// Two inserts at the same index appear in the order they were added.
+    /// Line endings in the text are normalized to `\n`, as the buffer
+    /// stores them, so mapped positions match the applied text.
+    pub fn push(&mut self, mut change: Change) {
+        if let Cow::Owned(text) = LineEnding::normalize(&change.text) {
+            change.text = text;
+        }
+        let at = self.changes.partition_point(|c| {
+            (c.range.start, c.range.end) <= (change.range.start, change.range.end)
+        });
+        self.changes.insert(at, change);
+    }
+
+    /// Returns the changes, sorted by position.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Returns true if there are no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Checks that the changes fit a text of `len_chars` and don't overlap.
+    pub fn validate(&self, len_chars: usize) -> BufferResult<()> {
+        let mut prev_end = 0;
+        for change in &self.changes {
+            if change.range.start > change.range.end || change.range.end > len_chars {
+                return Err(BufferError::InvalidCharIndex(change.range.end));
+            }
+            if change.range.start < prev_end {
+                return Err(BufferError::OverlappingChanges(change.range.start));
+            }
+            prev_end = change.range.end;
+        }
+        Ok(())
+    }
+
+    /// Maps a char index in the text before the change to the text after it.
+    pub fn map(&self, char_idx: usize, affinity: Affinity) -> usize {
+        let mut delta = 0isize;
+        for change in &self.changes {
+            let start = change.range.start;
+            if char_idx < start {
+                break;
+            }
+            let inserted = change.text.chars().count();
+            let new_start = start.saturating_add_signed(delta);
+            if !Self::passes(change, char_idx, affinity) {
+                return match affinity {
+                    Affinity::Backward => new_start,
+                    Affinity::Forward => new_start + inserted,
+                };
+            }
+            delta += inserted as isize - change.range.len() as isize;
+        }
+        char_idx.saturating_add_signed(delta)
+    }
+
+    /// Maps a position in `before` (the text the change set applies to)
+    /// to the text after the change.
+    pub fn map_position(
+        &self,
+        pos: Position,
+        affinity: Affinity,
+        before: &TextBuffer,
+    ) -> BufferResult<Position> {
+        let char_idx = before.position_to_char_idx(pos)?;
+
+        // Track how the end of the last passed change moved, so points
+        // after it on the same line can be shifted by column.
+        let mut line_delta = 0isize;
+        let mut last_end: Option<(Position, Position)> = None;
+        let translate =
+            |p: Position, line_delta: isize, last_end: Option<(Position, Position)>| match last_end
+            {
+                Some((old_end, new_end)) if p.line == old_end.line => {
+                    Position::new(new_end.line, new_end.column + p.column - old_end.column)
+                }
+                _ => Position::new(p.line.saturating_add_signed(line_delta), p.column),
+            };
+
+        for change in &self.changes {
+            if char_idx < change.range.start {
+                break;
+            }
+            let old_start = before.char_idx_to_position(change.range.start)?;
+            let old_end = before.char_idx_to_position(change.range.end)?;
+            let new_start = translate(old_start, line_delta, last_end);
+            let new_end = Self::advance(new_start, &change.text);
+
+            if !Self::passes(change, char_idx, affinity) {
+                return Ok(match affinity {
+                    Affinity::Backward => new_start,
+                    Affinity::Forward => new_end,
+                });
+            }
+            line_delta +=
+                (new_end.line - new_start.line) as isize - (old_end.line - old_start.line) as isize;
+            last_end = Some((old_end, new_end));
+        }
+        Ok(translate(pos, line_delta, last_end))
+    }
+
+    /// Returns the change set that undoes this one.
+    ///
+    /// `before` is the text this change set applies to; the result
+    /// applies to the text after it.
+    pub fn invert(&self, before: &TextBuffer) -> BufferResult<ChangeSet> {
+        self.validate(before.len_chars())?;
+        let mut inverse = ChangeSet::new();
+        let mut delta = 0isize;
+        for change in &self.changes {
+            let start = change.range.start.saturating_add_signed(delta);
+            let inserted = change.text.chars().count();
+            inverse.push(Change {
+                range: start..start + inserted,
+                text: before.slice(change.range.clone())?.into_owned(),
+            });
+            delta += inserted as isize - change.range.len() as isize;
+        }
+        Ok(inverse)
+    }
+
+    /// True if `char_idx` ends up after `change` rather than at one of its edges.
+    ///
+    /// Points at or past the end of a change follow the text after it,
+    /// except a `Backward` point at the start of a change, which stays.
+    fn passes(change: &Change, char_idx: usize, affinity: Affinity) -> bool {
+        char_idx >= change.range.end
+            && !(char_idx == change.range.start && affinity == Affinity::Backward)
+    }
+
+    /// Returns the position just after `text` inserted at `pos`.
+    fn advance(pos: Position, text: &str) -> Position {
+        match text.rfind('\n') {
+            Some(last) => Position::new(
+                pos.line + text.matches('\n').count(),
+                text[last + 1..].chars().count(),
+            ),
+            None => Position::new(pos.line, pos.column + text.chars().count()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_with_affinity() {
+        // "let x = 1;" -> "let mut x 1;"
+        let changes = ChangeSet::new().insert(4, "mut ").delete(5..7);
+
+        assert_eq!(changes.map(4, Affinity::Backward), 4);
+        assert_eq!(changes.map(4, Affinity::Forward), 8);
+        assert_eq!(changes.map(2, Affinity::Forward), 2);
+        // Inside the deleted range collapses to where it was
+        assert_eq!(changes.map(6, Affinity::Backward), 9);
+        assert_eq!(changes.map(7, Affinity::Backward), 9);
+        assert_eq!(changes.map(8, Affinity::Forward), 10);
+    }
+
+    #[test]
+    fn test_map_position_across_lines() {
+        let before = TextBuffer::from("one\ntwo three\nfour");
+        // Replace "two" with two lines, and insert at the end
+        let changes = ChangeSet::new().replace(4..7, "2\n22").insert(18, "!");
+
+        let map = |line, column| {
+            changes
+                .map_position(Position::new(line, column), Affinity::Backward, &before)
+                .unwrap()
+        };
+        assert_eq!(map(0, 2), Position::new(0, 2));
+        // " three" moves to the new second line, after "22"
+        assert_eq!(map(1, 4), Position::new(2, 3));
+        assert_eq!(map(2, 2), Position::new(3, 2));
+
+        // Agrees with applying the change and mapping the char index
+        let mut after = before.clone();
+        after.apply_changes(&changes).unwrap();
+        assert_eq!(after.text(), "one\n2\n22 three\nfour!");
+        for idx in 0..=before.len_chars() {
+            let pos = before.char_idx_to_position(idx).unwrap();
+            for affinity in [Affinity::Backward, Affinity::Forward] {
+                let expected = after
+                    .char_idx_to_position(changes.map(idx, affinity))
+                    .unwrap();
+                assert_eq!(
+                    changes.map_position(pos, affinity, &before).unwrap(),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_and_invert() {
+        let mut buffer = TextBuffer::from("hello world");
+        let changes = ChangeSet::new().replace(0..5, "goodbye").insert(11, "!");
+
+        let inverse = buffer.apply_changes(&changes).unwrap();
+        assert_eq!(buffer.text(), "goodbye world!");
+
+        buffer.apply_changes(&inverse).unwrap();
+        assert_eq!(buffer.text(), "hello world");
+
+        // The whole change set is one undo step
+        buffer.undo().unwrap();
+        buffer.undo().unwrap();
+        assert_eq!(buffer.text(), "hello world");
+        assert!(!buffer.can_undo());
+    }
+
+    #[test]
+    fn test_overlapping_changes_rejected() {
+        let mut buffer = TextBuffer::from("hello");
+        let changes = ChangeSet::new().delete(0..3).delete(2..4);
+        assert!(matches!(
+            buffer.apply_changes(&changes),
+            Err(BufferError::OverlappingChanges(2))
+        ));
+        assert_eq!(buffer.text(), "hello");
+    }
+}
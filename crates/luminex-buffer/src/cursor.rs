@@ -88,7 +88,7 @@ impl std::fmt::Display for Position {
 /// When a cursor is at a line break, it could visually appear at the end
 /// of one line or the start of the next. `affinity` tracks the intended
 /// visual position.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Cursor {
     /// Current cursor position
     pub position: Position,
@@ -97,8 +97,9 @@ pub struct Cursor {
     /// When Some, text between anchor and position is selected.
     pub anchor: Option<Position>,
 
-    /// Preferred column for vertical movement
-    /// When moving up/down, cursor tries to maintain this column.
+    /// Preferred display column for vertical movement
+    /// When moving up/down, cursor tries to maintain this column
+    /// (in screen cells, so it lines up across tabs and wide text).
     pub preferred_column: Option<usize>,
 
     /// Visual affinity at line boundaries
@@ -106,7 +107,11 @@ pub struct Cursor {
 }
 
 /// Visual affinity for cursor at line boundaries.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+///
+/// Also decides which side a position sticks to when text is inserted
+/// exactly there (see [`crate::ChangeSet::map`]): `Backward` stays
+/// before the new text, `Forward` moves past it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum Affinity {
     /// Cursor prefers to appear at end of current line
     #[default]
@@ -168,6 +173,12 @@ impl Cursor {
         })
     }
 
+    /// Returns the selected range, or an empty range at the cursor.
+    pub fn range(&self) -> (Position, Position) {
+        self.selection_range()
+            .unwrap_or((self.position, self.position))
+    }
+
     /// Selects all text (sets anchor to start, cursor to given end).
     pub fn select_all(&mut self, end: Position) {
         self.anchor = Some(Position::ZERO);
@@ -198,6 +209,9 @@ impl Cursor {
     }
 
     /// Moves cursor left by the given number of columns.
+    ///
+    /// Steps by `char`. User-facing movement should step by grapheme
+    /// instead (see [`crate::TextBuffer::prev_grapheme_boundary`]).
     pub fn move_left(&mut self, cols: usize) {
         if self.position.column >= cols {
             self.position.column -= cols;
@@ -213,6 +227,8 @@ impl Cursor {
     }
 
     /// Moves cursor right by the given number of columns.
+    ///
+    /// Steps by `char` (see [`Cursor::move_left`]).
     pub fn move_right(&mut self, cols: usize, line_len: usize, is_last_line: bool) {
         let new_col = self.position.column + cols;
         if new_col <= line_len {
@@ -235,7 +251,7 @@ impl Cursor {
 ///
 /// Each cursor is independent, but operations must be applied
 /// in reverse position order to avoid invalidating indices.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MultiCursor {
     /// All cursors, kept sorted by position
     cursors: Vec<Cursor>,
@@ -281,6 +297,7 @@ impl MultiCursor {
     }
 
     /// Returns the number of cursors.
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.cursors.len()
     }
@@ -309,7 +326,35 @@ impl MultiCursor {
         true
     }
 
+    /// Adds a cursor selecting from `anchor` to `position`, which
+    /// becomes the primary one.
+    ///
+    /// Returns false if it overlapped an existing cursor and was merged
+    /// into it.
+    pub fn add_selection(&mut self, anchor: Position, position: Position) -> bool {
+        let before = self.cursors.len();
+        let mut cursor = Cursor::new(anchor);
+        cursor.select_to(position);
+        self.cursors.push(cursor);
+        self.primary = self.cursors.len() - 1;
+        self.merge_overlapping();
+        self.cursors.len() > before
+    }
+
+    /// Removes the primary cursor; the one before it becomes primary.
+    ///
+    /// Returns false (and does nothing) if it is the only cursor.
+    pub fn remove_primary(&mut self) -> bool {
+        if self.is_single() {
+            return false;
+        }
+        self.cursors.remove(self.primary);
+        self.primary = self.primary.saturating_sub(1);
+        true
+    }
+
     /// Removes all cursors except the primary one.
+    #[allow(clippy::clone_on_copy)]
     pub fn collapse_to_primary(&mut self) {
         let primary = self.cursors[self.primary].clone();
         self.cursors.clear();
@@ -318,6 +363,7 @@ impl MultiCursor {
     }
 
     /// Sorts cursors by position and removes duplicates.
+    #[allow(clippy::unnecessary_sort_by)]
     fn sort(&mut self) {
         let primary_pos = self.cursors[self.primary].position;
 
@@ -336,6 +382,60 @@ impl MultiCursor {
             .unwrap_or(0);
     }
 
+    /// Sorts cursors and merges any that overlap.
+    ///
+    /// Cursors at the same position merge, as does a cursor inside (or
+    /// at the edge of) another's selection. Selections that only touch
+    /// stay separate. A merged cursor keeps the direction of the primary
+    /// cursor if it took part, otherwise of the first one.
+    pub fn merge_overlapping(&mut self) {
+        let primary = self.cursors[self.primary];
+        self.cursors.sort_by_key(|c| c.range());
+
+        let mut merged: Vec<Cursor> = Vec::with_capacity(self.cursors.len());
+        let mut primary_idx = 0;
+        for cursor in self.cursors.drain(..) {
+            let (start, end) = cursor.range();
+            if let Some(last) = merged.last_mut() {
+                let (last_start, last_end) = last.range();
+                let overlaps = start < last_end
+                    || (start == last_end && (start == end || last_start == last_end));
+                if overlaps {
+                    let keep = if cursor == primary { cursor } else { *last };
+                    let end = end.max(last_end);
+                    let reversed = keep.anchor.is_some_and(|anchor| anchor > keep.position);
+                    *last = match (last_start == end, reversed) {
+                        (true, _) => Cursor {
+                            position: end,
+                            anchor: None,
+                            ..keep
+                        },
+                        (false, false) => Cursor {
+                            position: end,
+                            anchor: Some(last_start),
+                            ..keep
+                        },
+                        (false, true) => Cursor {
+                            position: last_start,
+                            anchor: Some(end),
+                            ..keep
+                        },
+                    };
+                    if cursor == primary {
+                        primary_idx = merged.len() - 1;
+                    }
+                    continue;
+                }
+            }
+            if cursor == primary {
+                primary_idx = merged.len();
+            }
+            merged.push(cursor);
+        }
+        self.cursors = merged;
+        self.primary = primary_idx;
+    }
+
     /// Returns cursors in reverse order (for applying edits).
     ///
     /// When editing, we must apply changes from end to start
@@ -382,4 +482,29 @@ mod tests {
 
         assert_eq!(mc.len(), 3); // Initial + 2 added
     }
+
+    #[test]
+    fn test_merge_overlapping() {
+        let mut mc = MultiCursor::from_cursor(Cursor::new(Position::new(0, 2)));
+        mc.primary_mut().select_to(Position::new(0, 6));
+        // Inside the selection
+        mc.add(Position::new(0, 4));
+        // Touching selections stay apart
+        let mut touching = Cursor::new(Position::new(0, 6));
+        touching.select_to(Position::new(0, 8));
+        mc.cursors.push(touching);
+        mc.add(Position::new(1, 0));
+        mc.add(Position::new(1, 0));
+
+        mc.merge_overlapping();
+        let ranges: Vec<_> = mc.all().iter().map(Cursor::range).collect();
+        assert_eq!(
+            ranges,
+            [
+                (Position::new(0, 2), Position::new(0, 6)),
+                (Position::new(0, 6), Position::new(0, 8)),
+                (Position::new(1, 0), Position::new(1, 0)),
+            ]
+        );
+    }
 }
@@ -0,0 +1,284 @@
+//! Character encoding detection and conversion.
+//!
+//! ## Learning: Bytes vs Text
+//!
+//! A file on disk is just bytes. The rope always holds UTF-8, so
+//! opening a file means *decoding* its bytes, and saving means
+//! *encoding* the text back. To round-trip a file unchanged we have
+//! to remember which encoding it used and whether it started with a
+//! byte order mark (BOM).
+//!
+//! Detection runs in this order:
+//! 1. A BOM wins outright (UTF-8, UTF-16LE, UTF-16BE).
+//! 2. Text with NUL bytes in every other position is BOM-less UTF-16
+//!    (checked first, since NUL is technically valid UTF-8).
+//! 3. Bytes that are valid UTF-8 are UTF-8.
+//! 4. Anything else falls back to the configured default, or to
+//!    Windows-1252 when the default is UTF-8 (every byte decodes).
+
+use encoding_rs::{Decoder, Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+use crate::{BufferError, BufferResult};
+
+/// The encoding of a file, plus whether it carries a BOM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEncoding {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+impl TextEncoding {
+    /// Plain UTF-8 without a BOM.
+    pub fn utf8() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+
+    /// Looks up an encoding by label (`"utf-8"`, `"latin1"`, `"utf-16le"`, ...).
+    ///
+    /// Labels follow the WHATWG Encoding Standard, so `"latin1"` and
+    /// `"iso-8859-1"` both resolve to Windows-1252. A `"-bom"` suffix
+    /// (e.g. `"utf-8-bom"`) requests a byte order mark.
+    pub fn for_label(label: &str) -> BufferResult<Self> {
+        let label = label.trim();
+        let (name, bom) = match label.to_ascii_lowercase().strip_suffix("-bom") {
+            Some(name) => (name.to_string(), true),
+            None => (label.to_string(), false),
+        };
+        let encoding = Encoding::for_label(name.as_bytes())
+            .ok_or_else(|| BufferError::UnknownEncoding(label.to_string()))?;
+        Ok(Self { encoding, bom }.normalized())
+    }
+
+    /// Detects the encoding of raw file content.
+    ///
+    /// `fallback` is used when the bytes are neither UTF-8 nor UTF-16.
+    pub fn detect(bytes: &[u8], fallback: Self) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return Self {
+                encoding,
+                bom: true,
+            };
+        }
+        if let Some(encoding) = Self::sniff_utf16(bytes) {
+            return Self {
+                encoding,
+                bom: false,
+            };
+        }
+        if std::str::from_utf8(bytes).is_ok() {
+            return Self::utf8();
+        }
+        Self::legacy(fallback)
+    }
+
+    /// Returns what non-UTF-8 content is decoded as: `fallback`, or
+    /// Windows-1252 when `fallback` is UTF-8.
+    pub(crate) fn legacy(fallback: Self) -> Self {
+        if fallback.encoding == UTF_8 {
+            Self {
+                encoding: WINDOWS_1252,
+                bom: false,
+            }
+        } else {
+            fallback.with_bom(false)
+        }
+    }
+
+    /// Guesses BOM-less UTF-16 from the position of NUL bytes.
+    ///
+    /// Mostly-ASCII UTF-16 text has a zero in every high byte.
+    fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+        if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+            return None;
+        }
+        let pairs = bytes.len() / 2;
+        let even_nuls = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+        let odd_nuls = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+
+        // At least 3/4 of the high bytes are zero, none of the low ones
+        if odd_nuls * 4 >= pairs * 3 && even_nuls == 0 {
+            Some(UTF_16LE)
+        } else if even_nuls * 4 >= pairs * 3 && odd_nuls == 0 {
+            Some(UTF_16BE)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the canonical name of the encoding (e.g. `"UTF-8"`).
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    /// Returns true if files in this encoding start with a BOM.
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Returns a copy with the BOM flag changed.
+    pub fn with_bom(self, bom: bool) -> Self {
+        Self { bom, ..self }.normalized()
+    }
+
+    /// Returns true if every Unicode character can be encoded.
+    pub fn is_unicode(&self) -> bool {
+        self.encoding == UTF_8 || self.encoding == UTF_16LE || self.encoding == UTF_16BE
+    }
+
+    /// Only Unicode encodings have a byte order mark.
+    fn normalized(self) -> Self {
+        Self {
+            bom: self.bom && self.is_unicode(),
+            ..self
+        }
+    }
+
+    /// Decodes file content, skipping the BOM if there is one.
+    ///
+    /// Fails instead of inserting replacement characters, so that
+    /// saving never silently corrupts the file.
+    pub fn decode(&self, bytes: &[u8]) -> BufferResult<String> {
+        let bytes = match Encoding::for_bom(bytes) {
+            Some((encoding, len)) if encoding == self.encoding => &bytes[len..],
+            _ => bytes,
+        };
+        self.encoding
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .map(|text| text.into_owned())
+            .ok_or_else(|| BufferError::Decode(self.name().to_string()))
+    }
+
+    /// Returns a streaming decoder that skips a matching BOM, for
+    /// content that is read in chunks (see [`decode`](Self::decode)).
+    pub(crate) fn new_decoder(&self) -> Decoder {
+        self.encoding.new_decoder_with_bom_removal()
+    }
+
+    /// Encodes text for writing to disk, prefixing the BOM if needed.
+    ///
+    /// Fails if the text contains characters the encoding can't represent.
+    pub fn encode(&self, text: &str) -> BufferResult<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+
+        // encoding_rs only encodes *to* UTF-8 for the UTF-16 family
+        // (per the WHATWG spec), so UTF-16 is written by hand.
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let le = self.encoding == UTF_16LE;
+            if self.bom {
+                bytes.extend_from_slice(if le { &[0xFF, 0xFE] } else { &[0xFE, 0xFF] });
+            }
+            for unit in text.encode_utf16() {
+                let pair = if le {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                };
+                bytes.extend_from_slice(&pair);
+            }
+            return Ok(bytes);
+        }
+
+        if self.bom {
+            bytes.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+        }
+        let (encoded, _, had_errors) = self.encoding.encode(text);
+        if had_errors {
+            return Err(BufferError::Encode(self.name().to_string()));
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self::utf8()
+    }
+}
+
+impl std::fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.bom {
+            write!(f, "{} with BOM", self.name())
+        } else {
+            write!(f, "{}", self.name())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(bytes: &[u8], fallback: TextEncoding) -> (TextEncoding, String) {
+        let encoding = TextEncoding::detect(bytes, fallback);
+        let text = encoding.decode(bytes).unwrap();
+        assert_eq!(encoding.encode(&text).unwrap(), bytes);
+        (encoding, text)
+    }
+
+    #[test]
+    fn test_detect_utf8() {
+        let (enc, text) = roundtrip("héllo".as_bytes(), TextEncoding::utf8());
+        assert_eq!(enc, TextEncoding::utf8());
+        assert_eq!(text, "héllo");
+    }
+
+    #[test]
+    fn test_detect_boms() {
+        let (enc, text) = roundtrip(b"\xEF\xBB\xBFhi", TextEncoding::utf8());
+        assert_eq!((enc.name(), enc.has_bom()), ("UTF-8", true));
+        assert_eq!(text, "hi");
+
+        let (enc, text) = roundtrip(b"\xFF\xFEh\0i\0", TextEncoding::utf8());
+        assert_eq!((enc.name(), enc.has_bom()), ("UTF-16LE", true));
+        assert_eq!(text, "hi");
+
+        let (enc, text) = roundtrip(b"\xFE\xFF\0h\0i", TextEncoding::utf8());
+        assert_eq!((enc.name(), enc.has_bom()), ("UTF-16BE", true));
+        assert_eq!(text, "hi");
+    }
+
+    #[test]
+    fn test_detect_bomless_utf16() {
+        let (enc, text) = roundtrip(b"a\0b\0\n\0", TextEncoding::utf8());
+        assert_eq!((enc.name(), enc.has_bom()), ("UTF-16LE", false));
+        assert_eq!(text, "ab\n");
+    }
+
+    #[test]
+    fn test_latin1_fallback() {
+        // "café" in Latin-1 is not valid UTF-8
+        let (enc, text) = roundtrip(b"caf\xE9", TextEncoding::utf8());
+        assert_eq!(enc.name(), "windows-1252");
+        assert_eq!(text, "café");
+
+        let sjis = TextEncoding::for_label("shift_jis").unwrap();
+        let (enc, _) = roundtrip(b"\x82\xA0", sjis);
+        assert_eq!(enc, sjis);
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            TextEncoding::for_label("latin1").unwrap().name(),
+            "windows-1252"
+        );
+        assert!(TextEncoding::for_label("UTF-8-BOM").unwrap().has_bom());
+        // Only Unicode encodings carry a BOM
+        assert!(!TextEncoding::for_label("latin1-bom").unwrap().has_bom());
+        assert!(matches!(
+            TextEncoding::for_label("klingon"),
+            Err(BufferError::UnknownEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_unencodable() {
+        let latin1 = TextEncoding::for_label("latin1").unwrap();
+        assert!(matches!(latin1.encode("日本"), Err(BufferError::Encode(_))));
+    }
+}
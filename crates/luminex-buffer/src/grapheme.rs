@@ -0,0 +1,134 @@
+//! Grapheme clusters and display width.
+//!
+//! ## Learning: Chars Aren't Characters
+//!
+//! A Rust `char` is one Unicode scalar value, but what a user sees as
+//! one character can be several of them:
+//! - `"é"` may be `e` followed by a combining accent (2 chars)
+//! - `"👍🏽"` is a thumbs-up plus a skin-tone modifier (2 chars)
+//! - `"👨‍👩‍👧"` is three emoji glued with zero-width joiners (5 chars)
+//!
+//! These *grapheme clusters* are what the cursor should step over and
+//! what backspace should delete. `unicode-segmentation` finds them.
+//!
+//! Width is a separate question: CJK characters and most emoji take
+//! two terminal cells, combining marks take none, and a tab stretches
+//! to the next tab stop. `unicode-width` answers it per grapheme.
+//!
+//! Columns in a [`Position`](crate::Position) stay in chars; "display
+//! columns" are the visual cells used for vertical movement and
+//! rendering.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Returns the char column of the first grapheme boundary after `column`.
+///
+/// Returns the line length if `column` is in the last grapheme.
+pub fn next_boundary(line: &str, column: usize) -> usize {
+    let byte = char_to_byte(line, column);
+    let end = line
+        .grapheme_indices(true)
+        .map(|(start, g)| start + g.len())
+        .find(|&end| end > byte)
+        .unwrap_or(line.len());
+    line[..end].chars().count()
+}
+
+/// Returns the char column of the last grapheme boundary before `column`.
+pub fn prev_boundary(line: &str, column: usize) -> usize {
+    let byte = char_to_byte(line, column);
+    let start = line
+        .grapheme_indices(true)
+        .map(|(start, _)| start)
+        .take_while(|&start| start < byte)
+        .last()
+        .unwrap_or(0);
+    line[..start].chars().count()
+}
+
+/// Returns the number of cells a grapheme occupies at `display_column`.
+pub fn grapheme_width(grapheme: &str, display_column: usize, tab_width: usize) -> usize {
+    match grapheme {
+        "\t" => {
+            let tab_width = tab_width.max(1);
+            tab_width - display_column % tab_width
+        }
+        "\n" => 0,
+        _ => grapheme.width(),
+    }
+}
+
+/// Returns the display width of `text` starting at display column 0.
+pub fn display_width(text: &str, tab_width: usize) -> usize {
+    text.graphemes(true)
+        .fold(0, |col, g| col + grapheme_width(g, col, tab_width))
+}
+
+/// Returns the char column whose display column is closest to
+/// `display_column` without passing it.
+///
+/// A wide grapheme straddling the target is not entered, and the
+/// result never goes past the end of the line's content.
+pub fn column_at_display(line: &str, display_column: usize, tab_width: usize) -> usize {
+    let mut col = 0;
+    let mut chars = 0;
+    for g in line.graphemes(true) {
+        if g == "\n" {
+            break;
+        }
+        let width = grapheme_width(g, col, tab_width);
+        if col + width > display_column {
+            break;
+        }
+        col += width;
+        chars += g.chars().count();
+    }
+    chars
+}
+
+fn char_to_byte(line: &str, column: usize) -> usize {
+    line.char_indices()
+        .nth(column)
+        .map_or(line.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boundaries() {
+        // "e" + combining acute, then a skin-toned emoji
+        let line = "ae\u{301}b👍🏽c";
+        assert_eq!(next_boundary(line, 1), 3);
+        assert_eq!(prev_boundary(line, 3), 1);
+        assert_eq!(next_boundary(line, 4), 6);
+        assert_eq!(prev_boundary(line, 6), 4);
+        // From inside a cluster, go to its edges
+        assert_eq!(next_boundary(line, 2), 3);
+        assert_eq!(prev_boundary(line, 2), 1);
+        assert_eq!(next_boundary(line, 7), 7);
+        assert_eq!(prev_boundary(line, 0), 0);
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("abc", 4), 3);
+        assert_eq!(display_width("日本", 4), 4);
+        assert_eq!(display_width("e\u{301}", 4), 1);
+        assert_eq!(display_width("a\tb", 4), 5);
+        assert_eq!(display_width("\t\t", 4), 8);
+    }
+
+    #[test]
+    fn test_column_at_display() {
+        assert_eq!(column_at_display("日本語", 2, 4), 1);
+        // Half-way into a wide char stays before it
+        assert_eq!(column_at_display("日本語", 3, 4), 1);
+        assert_eq!(column_at_display("\tx", 2, 4), 0);
+        assert_eq!(column_at_display("\tx", 4, 4), 1);
+        // Never past the end of the content
+        assert_eq!(column_at_display("ab\n", 10, 4), 2);
+    }
+}
@@ -11,10 +11,28 @@
 //! - Arbitrary undo depth
 //! - Edit grouping (multiple edits as one undo step)
 //! - Edit coalescing (combining rapid keystrokes)
+//!
+//! ## Learning: Undo Trees
+//!
+//! A linear undo stack throws away the redo stack as soon as you
+//! type after undoing. An undo *tree* keeps it: every state of the
+//! buffer is a node, and typing after an undo starts a new branch.
+//!
+//! ```text
+//!   root ── a ── b ── c        (undo twice, then type "x")
+//!                └── x  ← current
+//! ```
+//!
+//! `undo`/`redo` still walk up and down one path (the "linear view"),
+//! with redo following the most recently visited branch. `jump` can
+//! reach any other state: chronologically (`earlier 5m`), or by
+//! switching to the tip of another branch.
 
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cursor::MultiCursor;
 
 /// The type of edit operation.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -79,6 +97,7 @@ impl Edit {
     /// - They're the same kind
     /// - They're adjacent (next character for insert, same position for delete)
     /// - Neither is a newline
+    /// - An insert doesn't start a new word (whitespace after a non-space)
     pub fn can_coalesce(&self, other: &Edit) -> bool {
         if self.kind != other.kind {
             return false;
@@ -91,8 +110,11 @@ impl Edit {
 
         match self.kind {
             EditKind::Insert => {
+                // Typing a space after a word starts a new undo step
+                let word_break = other.content.starts_with(char::is_whitespace)
+                    && !self.content.ends_with(char::is_whitespace);
                 // Can coalesce if other is right after this insert
-                self.position + self.content.chars().count() == other.position
+                !word_break && self.position + self.content.chars().count() == other.position
             }
             EditKind::Delete => {
                 // For backspace: other position + its length == this position
@@ -124,12 +146,17 @@ impl Edit {
 }
 
 /// A group of edits that should be undone/redone together.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EditGroup {
     /// The edits in this group
     pub edits: Vec<Edit>,
-    /// When this group was created
-    pub timestamp: Option<Instant>,
+    /// When this group was last changed
+    pub timestamp: SystemTime,
+    /// Cursors as they were before the group, if recorded
+    pub cursors: Option<MultiCursor>,
+    /// Cursors as they were after the group, if recorded
+    #[serde(default)]
+    pub cursors_after: Option<MultiCursor>,
 }
 
 impl EditGroup {
@@ -137,13 +164,16 @@ impl EditGroup {
     pub fn new(edit: Edit) -> Self {
         Self {
             edits: vec![edit],
-            timestamp: Some(Instant::now()),
+            timestamp: SystemTime::now(),
+            cursors: None,
+            cursors_after: None,
         }
     }
 
     /// Adds an edit to this group.
     pub fn push(&mut self, edit: Edit) {
         self.edits.push(edit);
+        self.timestamp = SystemTime::now();
     }
 
     /// Returns the last edit in the group.
@@ -158,6 +188,110 @@ impl EditGroup {
     }
 }
 
+/// The result of undo, redo or a jump.
+#[derive(Debug, Clone)]
+pub struct UndoStep {
+    /// Edits to apply to the text, in order
+    pub edits: Vec<Edit>,
+    /// Cursors to restore, if they were recorded
+    pub cursors: Option<MultiCursor>,
+}
+
+impl UndoStep {
+    /// Returns the char index just after the last edit.
+    ///
+    /// A sensible cursor position when no cursors were recorded.
+    pub fn end_position(&self) -> Option<usize> {
+        let edit = self.edits.last()?;
+        Some(match edit.kind {
+            EditKind::Insert => edit.position + edit.content.chars().count(),
+            EditKind::Delete => edit.position,
+        })
+    }
+}
+
+/// A state in the undo tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UndoNode {
+    /// Edits leading from the parent state to this one (empty for the root)
+    group: EditGroup,
+    /// Parent state (the root is its own parent)
+    parent: usize,
+    /// Branches made from this state, oldest first
+    children: Vec<usize>,
+    /// The child `redo` follows
+    redo_child: Option<usize>,
+}
+
+/// The tip of one branch of the undo tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoBranch {
+    /// Id of the tip state, usable with [`UndoTarget::Branch`]
+    pub id: usize,
+    /// When the tip state was last changed
+    pub timestamp: SystemTime,
+    /// Number of undo steps from the tip back to the root
+    pub depth: usize,
+    /// True if the current state lies on this branch
+    pub is_current: bool,
+}
+
+/// A destination for [`History::jump`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoTarget {
+    /// N states back in time, across branches
+    Earlier(usize),
+    /// N states forward in time, across branches
+    Later(usize),
+    /// The state as it was this long before the current one
+    EarlierBy(Duration),
+    /// The state as it was this long after the current one
+    LaterBy(Duration),
+    /// The tip of a branch (see [`History::branches`])
+    Branch(usize),
+    /// The tip of the next newer branch
+    NextBranch,
+    /// The tip of the next older branch
+    PreviousBranch,
+}
+
+impl UndoTarget {
+    /// Parses an `earlier` argument: `"3"` (states) or `"10s"`, `"5m"`, `"2h"`, `"1d"`.
+    pub fn earlier(spec: &str) -> Option<Self> {
+        Some(match Self::parse_spec(spec)? {
+            Ok(steps) => UndoTarget::Earlier(steps),
+            Err(duration) => UndoTarget::EarlierBy(duration),
+        })
+    }
+
+    /// Parses a `later` argument, like [`UndoTarget::earlier`].
+    pub fn later(spec: &str) -> Option<Self> {
+        Some(match Self::parse_spec(spec)? {
+            Ok(steps) => UndoTarget::Later(steps),
+            Err(duration) => UndoTarget::LaterBy(duration),
+        })
+    }
+
+    /// Returns `Ok(steps)` for a bare number, `Err(duration)` for a time.
+    fn parse_spec(spec: &str) -> Option<Result<usize, Duration>> {
+        let spec = spec.trim();
+        let split = spec
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(spec.len());
+        let (number, unit) = spec.split_at(split);
+        let n: u64 = number.parse().ok()?;
+        let seconds = match unit.trim() {
+            "" => return Some(Ok(n as usize)),
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return None,
+        };
+        Some(Err(Duration::from_secs(n.checked_mul(seconds)?)))
+    }
+}
+
 /// Manages undo/redo history.
 ///
 /// ## Design Decisions
@@ -165,170 +299,448 @@ impl EditGroup {
 /// 1. **Bounded history**: Limits memory usage for long editing sessions
 /// 2. **Edit coalescing**: Combines rapid keystrokes into single undo steps
 /// 3. **Edit grouping**: Allows treating multiple operations as one
+/// 4. **Branching**: Undoing and then editing keeps the old redo path
 ///
-/// ## Learning: VecDeque
+/// ## Learning: BTreeMap
 ///
-/// We use `VecDeque` instead of `Vec` because we need efficient:
-/// - Push to back (new edits)
-/// - Pop from front (when at capacity)
-/// - Pop from back (for undo)
-#[derive(Debug, Clone)]
+/// Nodes are keyed by an ever-increasing id, so a `BTreeMap` iterates
+/// them in creation order (handy for "earlier"/"later") while still
+/// allowing old nodes to be pruned without shifting indices.
+///
+/// The tree serializes (see `undo_cache`); editing-session state such
+/// as open groups and coalescing timers does not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct History {
-    /// Stack of undoable edit groups
-    undo_stack: VecDeque<EditGroup>,
-    /// Stack of redoable edit groups
-    redo_stack: Vec<EditGroup>,
-    /// Maximum number of edit groups to keep
+    /// Every state in the tree, keyed by creation order
+    nodes: BTreeMap<usize, UndoNode>,
+    /// Id of the oldest state still kept
+    root: usize,
+    /// Id of the state the buffer is in
+    current: usize,
+    /// Id for the next node
+    next_id: usize,
+    /// Maximum number of states to keep (besides the root)
     max_size: usize,
     /// Time threshold for coalescing edits (ms)
+    #[serde(skip, default = "default_coalesce_threshold")]
     coalesce_threshold: Duration,
-    /// Whether we're in a group (for compound operations)
-    in_group: bool,
+    /// When the current node last received an edit (None after undo/redo)
+    #[serde(skip)]
+    last_push: Option<Instant>,
+    /// How many groups are open (groups nest; only the outermost counts)
+    #[serde(skip)]
+    group_depth: usize,
+    /// The node collecting the open group's edits
+    #[serde(skip)]
+    group_node: Option<usize>,
+    /// Cursors to attach to the next new group
+    #[serde(skip)]
+    pending_cursors: Option<MultiCursor>,
+    /// The current node was edited since the last undo, redo or jump
+    #[serde(skip)]
+    edited: bool,
+}
+
+fn default_coalesce_threshold() -> Duration {
+    Duration::from_millis(300)
 }
 
 impl History {
     /// Creates a new history with the given capacity.
     pub fn new(max_size: usize) -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(0, Self::root_node(0));
         Self {
-            undo_stack: VecDeque::with_capacity(max_size),
-            redo_stack: Vec::new(),
+            nodes,
+            root: 0,
+            current: 0,
+            next_id: 1,
             max_size,
-            coalesce_threshold: Duration::from_millis(300),
-            in_group: false,
+            coalesce_threshold: default_coalesce_threshold(),
+            last_push: None,
+            group_depth: 0,
+            group_node: None,
+            pending_cursors: None,
+            edited: false,
+        }
+    }
+
+    fn root_node(id: usize) -> UndoNode {
+        UndoNode {
+            group: EditGroup {
+                edits: Vec::new(),
+                timestamp: SystemTime::now(),
+                cursors: None,
+                cursors_after: None,
+            },
+            parent: id,
+            children: Vec::new(),
+            redo_child: None,
         }
     }
 
+    fn node(&self, id: usize) -> &UndoNode {
+        &self.nodes[&id]
+    }
+
+    fn node_mut(&mut self, id: usize) -> &mut UndoNode {
+        self.nodes.get_mut(&id).expect("undo node exists")
+    }
+
     /// Pushes an edit onto the history.
     ///
-    /// Clears the redo stack (can't redo after new edit).
-    /// May coalesce with the previous edit.
+    /// May coalesce with the previous edit. If the current state
+    /// already has redo branches, they are kept and a new branch starts.
     pub fn push(&mut self, edit: Edit) {
-        // Clear redo stack - branching history not supported
-        self.redo_stack.clear();
-
-        // Try to coalesce with the last edit
-        if let Some(last_group) = self.undo_stack.back_mut() {
-            if let Some(timestamp) = last_group.timestamp {
-                let elapsed = timestamp.elapsed();
-
-                if elapsed < self.coalesce_threshold {
-                    if let Some(last_edit) = last_group.last_mut() {
-                        if last_edit.can_coalesce(&edit) {
-                            last_edit.coalesce(edit);
-                            last_group.timestamp = Some(Instant::now());
-                            return;
-                        }
-                    }
-                }
-            }
+        let current = self.current;
+        let cursors = self.pending_cursors.take();
+        self.edited = true;
+
+        // If in a group, add to the group's node
+        if self.group_depth > 0 && self.group_node == Some(current) {
+            self.node_mut(current).group.push(edit);
+            self.last_push = Some(Instant::now());
+            return;
+        }
 
-            // If in a group, add to current group
-            if self.in_group {
-                last_group.push(edit);
-                last_group.timestamp = Some(Instant::now());
+        // Try to coalesce with the last edit (only at a leaf, so no
+        // other branch depends on the state being changed)
+        let recent = self
+            .last_push
+            .is_some_and(|t| t.elapsed() < self.coalesce_threshold);
+        if recent && self.group_depth == 0 && current != self.root {
+            let node = self.node_mut(current);
+            if node.children.is_empty()
+                && let Some(last_edit) = node.group.last_mut()
+                && last_edit.can_coalesce(&edit)
+            {
+                last_edit.coalesce(edit);
+                node.group.timestamp = SystemTime::now();
+                self.last_push = Some(Instant::now());
                 return;
             }
         }
 
-        // Create new group
-        let group = EditGroup::new(edit);
-        self.undo_stack.push_back(group);
+        // Create a new state as a child of the current one
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut group = EditGroup::new(edit);
+        group.cursors = cursors;
+        self.nodes.insert(
+            id,
+            UndoNode {
+                group,
+                parent: current,
+                children: Vec::new(),
+                redo_child: None,
+            },
+        );
+        let parent = self.node_mut(current);
+        parent.children.push(id);
+        parent.redo_child = Some(id);
+        self.current = id;
+        self.last_push = Some(Instant::now());
+        if self.group_depth > 0 {
+            self.group_node = Some(id);
+        }
+
+        self.prune();
+    }
+
+    /// Turns coalescing of rapid edits on or off.
+    ///
+    /// With it off, every edit outside a group is its own undo step.
+    pub fn set_coalescing(&mut self, enabled: bool) {
+        self.coalesce_threshold = if enabled {
+            default_coalesce_threshold()
+        } else {
+            Duration::ZERO
+        };
+    }
+
+    /// Remembers the cursors to store with the next new undo step.
+    ///
+    /// Edits that coalesce into an existing step keep its cursors.
+    pub fn set_cursors(&mut self, cursors: MultiCursor) {
+        self.pending_cursors = Some(cursors);
+    }
 
-        // Enforce capacity
-        while self.undo_stack.len() > self.max_size {
-            self.undo_stack.pop_front();
+    /// Stores the cursors as they are after the latest edit, for redo.
+    ///
+    /// Ignored unless there was an edit since the last undo, redo or
+    /// jump, so the cursors of a step reached that way stay as they were.
+    pub fn set_cursors_after(&mut self, cursors: MultiCursor) {
+        if !self.edited || self.current == self.root {
+            return;
         }
+        let current = self.current;
+        self.node_mut(current).group.cursors_after = Some(cursors);
     }
 
     /// Starts an edit group.
     ///
-    /// All edits until `end_group()` will be treated as one undo step.
+    /// All edits until the matching `end_group()` will be treated as
+    /// one undo step.
+    ///
+    /// Groups nest: an operation that groups its own edits (like
+    /// replace-all) can run inside a larger group (like a multi-cursor
+    /// edit), and everything still undoes in one step.
     pub fn begin_group(&mut self) {
-        self.in_group = true;
+        if self.group_depth == 0 {
+            self.group_node = None;
+        }
+        self.group_depth += 1;
     }
 
-    /// Ends the current edit group.
+    /// Ends the innermost edit group.
+    ///
+    /// An `end_group` without a matching `begin_group` is ignored.
     pub fn end_group(&mut self) {
-        self.in_group = false;
+        if self.group_depth == 0 {
+            return;
+        }
+        self.group_depth -= 1;
+        if self.group_depth == 0 {
+            self.group_node = None;
+            // The next keystroke shouldn't merge into the group
+            self.last_push = None;
+        }
     }
 
-    /// Undoes the last edit group.
+    /// Returns true while a group is open.
+    pub fn in_group(&self) -> bool {
+        self.group_depth > 0
+    }
+
+    /// Undoes the last edit group as a whole.
     ///
-    /// Returns the edits to reverse (in reverse order).
-    pub fn undo(&mut self) -> Option<Edit> {
-        // For simplicity, we return one edit at a time from the group
-        // A more sophisticated implementation would return the whole group
-        if let Some(group) = self.undo_stack.back_mut() {
-            if let Some(edit) = group.edits.pop() {
-                // Move to redo stack
-                let redo_group = EditGroup {
-                    edits: vec![edit.clone()],
-                    timestamp: None,
-                };
+    /// The step's edits are already inverted and in the order they must
+    /// be applied; its cursors are the ones from before the group.
+    pub fn undo(&mut self) -> Option<UndoStep> {
+        if self.current == self.root {
+            return None;
+        }
+        let from = self.current;
+        let parent = self.node(from).parent;
+        self.node_mut(parent).redo_child = Some(from);
+        self.current = parent;
+        self.last_push = None;
+        self.edited = false;
+        let group = &self.node(from).group;
+        Some(UndoStep {
+            edits: Self::inverted(group),
+            cursors: group.cursors.clone(),
+        })
+    }
 
-                // If group is now empty, remove it
-                if group.edits.is_empty() {
-                    self.undo_stack.pop_back();
-                }
+    /// Redoes the last undone edit group as a whole.
+    ///
+    /// Follows the most recently visited branch. The step's cursors are
+    /// the ones from after the group.
+    pub fn redo(&mut self) -> Option<UndoStep> {
+        let child = self.node(self.current).redo_child?;
+        self.current = child;
+        self.last_push = None;
+        self.edited = false;
+        let group = &self.node(child).group;
+        Some(UndoStep {
+            edits: group.edits.clone(),
+            cursors: group.cursors_after.clone(),
+        })
+    }
 
-                // Check if we should merge with existing redo group
-                if let Some(last_redo) = self.redo_stack.last_mut() {
-                    last_redo.edits.push(edit.clone());
-                } else {
-                    self.redo_stack.push(redo_group);
-                }
+    /// Moves to another state in the tree.
+    ///
+    /// Returns the edits that turn the current text into the target
+    /// state's text, or `None` if the target is the current state or
+    /// doesn't exist. The step's cursors are the ones before the last
+    /// group undone, or after the last one redone.
+    pub fn jump(&mut self, target: UndoTarget) -> Option<UndoStep> {
+        let id = self.resolve(target)?;
+        if id == self.current || !self.nodes.contains_key(&id) {
+            return None;
+        }
 
-                return Some(edit);
-            }
+        // Walk up from both ends to the common ancestor
+        let up = self.path_to_root(self.current);
+        let down = self.path_to_root(id);
+        let common = *up.iter().find(|n| down.contains(n))?;
+
+        let mut edits = Vec::new();
+        let mut cursors = None;
+        for &node in up.iter().take_while(|&&n| n != common) {
+            edits.extend(Self::inverted(&self.node(node).group));
+            cursors = self.node(node).group.cursors.clone();
+        }
+        let descend: Vec<usize> = down.iter().copied().take_while(|&n| n != common).collect();
+        if !descend.is_empty() {
+            cursors = self.node(id).group.cursors_after.clone();
         }
-        None
+        for &node in descend.iter().rev() {
+            edits.extend(self.node(node).group.edits.iter().cloned());
+            // Make plain redo follow the path we just took
+            let parent = self.node(node).parent;
+            self.node_mut(parent).redo_child = Some(node);
+        }
+
+        self.current = id;
+        self.last_push = None;
+        self.edited = false;
+        Some(UndoStep { edits, cursors })
     }
 
-    /// Redoes the last undone edit.
-    pub fn redo(&mut self) -> Option<Edit> {
-        if let Some(group) = self.redo_stack.last_mut() {
-            if let Some(edit) = group.edits.pop() {
-                // If group is now empty, remove it
-                if group.edits.is_empty() {
-                    self.redo_stack.pop();
+    /// Returns the tip of every branch, oldest first.
+    pub fn branches(&self) -> Vec<UndoBranch> {
+        let redo_tip = self.redo_tip();
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.children.is_empty())
+            .map(|(&id, node)| UndoBranch {
+                id,
+                timestamp: node.group.timestamp,
+                depth: self.path_to_root(id).len() - 1,
+                is_current: id == redo_tip,
+            })
+            .collect()
+    }
+
+    /// Turns a target into a node id.
+    fn resolve(&self, target: UndoTarget) -> Option<usize> {
+        let ids: Vec<usize> = self.nodes.keys().copied().collect();
+        let pos = ids.iter().position(|&id| id == self.current)?;
+        let now = self.node(self.current).group.timestamp;
+
+        match target {
+            UndoTarget::Earlier(n) => Some(ids[pos.saturating_sub(n)]),
+            UndoTarget::Later(n) => Some(ids[(pos + n).min(ids.len() - 1)]),
+            UndoTarget::EarlierBy(d) => {
+                let when = now.checked_sub(d)?;
+                Some(self.latest_at(when).unwrap_or(self.root))
+            }
+            UndoTarget::LaterBy(d) => {
+                let when = now.checked_add(d)?;
+                self.latest_at(when).filter(|&id| id > self.current)
+            }
+            UndoTarget::Branch(id) => Some(id),
+            UndoTarget::NextBranch | UndoTarget::PreviousBranch => {
+                let tips: Vec<usize> = self.branches().iter().map(|b| b.id).collect();
+                if tips.len() < 2 {
+                    return None;
                 }
+                let at = tips.iter().position(|&id| id == self.redo_tip())?;
+                let next = if target == UndoTarget::NextBranch {
+                    (at + 1) % tips.len()
+                } else {
+                    (at + tips.len() - 1) % tips.len()
+                };
+                Some(tips[next])
+            }
+        }
+    }
+
+    /// Returns the newest state that already existed at `when`.
+    fn latest_at(&self, when: SystemTime) -> Option<usize> {
+        self.nodes
+            .iter()
+            .rev()
+            .find(|(_, node)| node.group.timestamp <= when)
+            .map(|(&id, _)| id)
+    }
+
+    /// Returns the leaf reached by redoing from the current state.
+    fn redo_tip(&self) -> usize {
+        let mut id = self.current;
+        while let Some(child) = self.node(id).redo_child {
+            id = child;
+        }
+        id
+    }
+
+    /// Returns `id` followed by its ancestors, ending with the root.
+    fn path_to_root(&self, mut id: usize) -> Vec<usize> {
+        let mut path = vec![id];
+        while id != self.root {
+            id = self.node(id).parent;
+            path.push(id);
+        }
+        path
+    }
 
-                // Push back to undo stack (without coalescing)
-                self.undo_stack.push_back(EditGroup {
-                    edits: vec![edit.clone()],
-                    timestamp: None, // No timestamp prevents coalescing
-                });
+    /// Returns the edits that undo a group, in application order.
+    fn inverted(group: &EditGroup) -> Vec<Edit> {
+        group.edits.iter().rev().map(Edit::inverse).collect()
+    }
 
-                return Some(edit);
+    /// Drops the oldest states until the tree fits `max_size`.
+    fn prune(&mut self) {
+        while self.nodes.len() > self.max_size + 1 {
+            let children = self.node(self.root).children.clone();
+            let keep = self.path_to_root(self.current);
+
+            if children.len() == 1 && self.current != self.root {
+                // Linear start: the only child becomes the new root,
+                // and its edits can no longer be undone
+                let new_root = children[0];
+                self.nodes.remove(&self.root);
+                let node = self.node_mut(new_root);
+                node.group.edits.clear();
+                node.parent = new_root;
+                self.root = new_root;
+            } else if let Some(&oldest) = children.iter().find(|c| !keep.contains(c)) {
+                // Drop the oldest branch that doesn't lead to the current state
+                self.remove_subtree(oldest);
+                let root = self.root;
+                let node = self.node_mut(root);
+                node.children.retain(|&c| c != oldest);
+                if node.redo_child == Some(oldest) {
+                    node.redo_child = node.children.last().copied();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn remove_subtree(&mut self, id: usize) {
+        if let Some(node) = self.nodes.remove(&id) {
+            for child in node.children {
+                self.remove_subtree(child);
             }
         }
-        None
     }
 
     /// Returns true if there are edits to undo.
     pub fn can_undo(&self) -> bool {
-        !self.undo_stack.is_empty()
+        self.current != self.root
     }
 
     /// Returns true if there are edits to redo.
     pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+        self.node(self.current).redo_child.is_some()
     }
 
     /// Clears all history.
     pub fn clear(&mut self) {
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        *self = Self {
+            coalesce_threshold: self.coalesce_threshold,
+            ..Self::new(self.max_size)
+        };
     }
 
     /// Returns the number of undo steps available.
     pub fn undo_count(&self) -> usize {
-        self.undo_stack.len()
+        self.path_to_root(self.current).len() - 1
     }
 
     /// Returns the number of redo steps available.
     pub fn redo_count(&self) -> usize {
-        self.redo_stack.len()
+        let mut count = 0;
+        let mut id = self.current;
+        while let Some(child) = self.node(id).redo_child {
+            id = child;
+            count += 1;
+        }
+        count
     }
 }
 
@@ -355,12 +767,119 @@ mod tests {
         history.push(Edit::insert(1, "b"));
 
         assert!(history.can_undo());
-        let edit = history.undo().unwrap();
-        assert_eq!(edit.content, "b");
+        let step = history.undo().unwrap();
+        assert_eq!(step.edits[0].content, "b");
 
         assert!(history.can_redo());
-        let edit = history.redo().unwrap();
-        assert_eq!(edit.content, "b");
+        let step = history.redo().unwrap();
+        assert_eq!(step.edits[0].content, "b");
+    }
+
+    /// Pushes an edit that never coalesces with the previous one.
+    fn push_step(history: &mut History, edit: Edit) {
+        history.begin_group();
+        history.push(edit);
+        history.end_group();
+    }
+
+    #[test]
+    fn test_nested_groups() {
+        let mut history = History::new(100);
+        history.begin_group();
+        history.push(Edit::insert(0, "a"));
+        history.begin_group();
+        history.push(Edit::insert(5, "b"));
+        history.end_group();
+        // Still inside the outer group
+        assert!(history.in_group());
+        history.push(Edit::insert(9, "c"));
+        history.end_group();
+        // Unbalanced end_group is harmless
+        history.end_group();
+
+        assert_eq!(history.undo_count(), 1);
+        assert_eq!(history.undo().unwrap().edits.len(), 3);
+    }
+
+    #[test]
+    fn test_branching_keeps_redo_path() {
+        let mut history = History::new(100);
+        push_step(&mut history, Edit::insert(0, "a"));
+        push_step(&mut history, Edit::insert(1, "b"));
+
+        // Undo "b" and type "x" instead: "b" becomes another branch
+        history.undo().unwrap();
+        push_step(&mut history, Edit::insert(1, "x"));
+        assert!(!history.can_redo());
+
+        let branches = history.branches();
+        assert_eq!(branches.len(), 2);
+        assert!(branches[1].is_current);
+        assert_eq!(branches[0].depth, 2);
+
+        // Switching branches undoes "x" and redoes "b"
+        let edits = history.jump(UndoTarget::PreviousBranch).unwrap().edits;
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[0].kind, EditKind::Delete);
+        assert_eq!(edits[0].content, "x");
+        assert_eq!(edits[1].kind, EditKind::Insert);
+        assert_eq!(edits[1].content, "b");
+        assert!(history.branches()[0].is_current);
+
+        // Linear undo/redo still work along the active branch
+        assert_eq!(history.undo().unwrap().edits[0].content, "b");
+        assert_eq!(history.redo().unwrap().edits[0].content, "b");
+        assert_eq!(history.undo_count(), 2);
+    }
+
+    #[test]
+    fn test_earlier_later() {
+        let mut history = History::new(100);
+        push_step(&mut history, Edit::insert(0, "a"));
+        push_step(&mut history, Edit::insert(1, "b"));
+        history.undo().unwrap();
+        push_step(&mut history, Edit::insert(1, "x"));
+
+        // Chronologically, the state before "x" was "ab"
+        let edits = history.jump(UndoTarget::Earlier(1)).unwrap().edits;
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[1].content, "b");
+
+        // Back to the start, then forward through time
+        assert!(
+            history
+                .jump(UndoTarget::EarlierBy(Duration::from_secs(3600)))
+                .is_some()
+        );
+        assert!(!history.can_undo());
+        assert!(history.jump(UndoTarget::Later(3)).is_some());
+        assert!(history.jump(UndoTarget::Later(1)).is_none());
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        assert_eq!(UndoTarget::earlier("3"), Some(UndoTarget::Earlier(3)));
+        assert_eq!(
+            UndoTarget::earlier("5m"),
+            Some(UndoTarget::EarlierBy(Duration::from_secs(300)))
+        );
+        assert_eq!(
+            UndoTarget::later("2h"),
+            Some(UndoTarget::LaterBy(Duration::from_secs(7200)))
+        );
+        assert_eq!(UndoTarget::earlier("soon"), None);
+        assert_eq!(UndoTarget::later("5 parsecs"), None);
+    }
+
+    #[test]
+    fn test_pruning_keeps_current_branch() {
+        let mut history = History::new(3);
+        for i in 0..5 {
+            push_step(&mut history, Edit::insert(i, "a"));
+        }
+        assert_eq!(history.undo_count(), 3);
+        while history.undo().is_some() {}
+        assert_eq!(history.redo_count(), 3);
     }
 
     #[test]
@@ -372,4 +891,18 @@ mod tests {
         e1.coalesce(e2);
         assert_eq!(e1.content, "ab");
     }
+
+    #[test]
+    fn test_typing_undoes_word_by_word() {
+        let mut history = History::new(100);
+        for (i, c) in "hi  there".chars().enumerate() {
+            history.push(Edit::insert(i, c));
+        }
+
+        // A space after a word starts a new step, which the following
+        // spaces and word join
+        assert_eq!(history.undo().unwrap().edits[0].content, "  there");
+        assert_eq!(history.undo().unwrap().edits[0].content, "hi");
+        assert!(!history.can_undo());
+    }
 }
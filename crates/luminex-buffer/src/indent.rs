@@ -0,0 +1,138 @@
+//! Indentation style detection.
+//!
+//! ## Learning: Heuristics Over Histograms
+//!
+//! Files don't declare their indentation, so we infer it:
+//! - Count lines indented with tabs vs spaces.
+//! - For space-indented lines, record how much the indentation
+//!   changes from one non-blank line to the next. In a 4-space file
+//!   most of those changes are 4 (one level in or out).
+//! - The most common change is the indent width.
+//!
+//! Only the first [`SAMPLE_LINES`] lines are looked at, so opening a
+//! huge file stays cheap. When the evidence is thin or split evenly,
+//! detection reports "inconclusive" (`None`) and the caller falls back
+//! to configuration.
+
+use ropey::Rope;
+
+/// Maximum number of lines sampled when detecting indentation.
+pub const SAMPLE_LINES: usize = 10_000;
+
+/// Largest indent width considered by detection.
+const MAX_WIDTH: usize = 8;
+
+/// How a file is indented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndentStyle {
+    /// One tab per level.
+    Tabs,
+    /// The given number of spaces per level.
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    /// Detects the indentation style from the start of a rope.
+    ///
+    /// Returns `None` when the sample is inconclusive.
+    pub fn detect(rope: &Rope) -> Option<Self> {
+        let mut tab_lines = 0usize;
+        let mut space_lines = 0usize;
+        // deltas[n] = how often indentation changed by exactly n spaces
+        let mut deltas = [0usize; MAX_WIDTH + 1];
+        let mut prev_spaces = 0usize;
+
+        for line in rope.lines().take(SAMPLE_LINES) {
+            let mut chars = line.chars().peekable();
+            let starts_with_tab = chars.peek() == Some(&'\t');
+            let mut spaces = 0usize;
+            while chars.next_if(|&c| c == ' ' || c == '\t').is_some() {
+                spaces += 1;
+            }
+
+            match chars.peek() {
+                // Blank lines say nothing about indentation
+                None | Some('\n') | Some('\r') => continue,
+                // ` * ` continuation lines inside block comments
+                Some('*') if spaces % 2 == 1 => continue,
+                _ => {}
+            }
+
+            if starts_with_tab {
+                tab_lines += 1;
+                prev_spaces = 0;
+                continue;
+            }
+            if spaces > 0 {
+                space_lines += 1;
+            }
+
+            let delta = spaces.abs_diff(prev_spaces);
+            if (2..=MAX_WIDTH).contains(&delta) {
+                deltas[delta] += 1;
+            }
+            prev_spaces = spaces;
+        }
+
+        if tab_lines > space_lines {
+            return Some(IndentStyle::Tabs);
+        }
+        if space_lines == 0 || space_lines == tab_lines {
+            return None;
+        }
+
+        // Most frequent change wins; ties go to the smaller width
+        let (width, &count) = deltas
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|&(_, count)| count)?;
+        (count > 0).then_some(IndentStyle::Spaces(width))
+    }
+
+    /// Returns the text for one level of indentation.
+    pub fn unit(&self) -> String {
+        match self {
+            IndentStyle::Tabs => "\t".to_string(),
+            IndentStyle::Spaces(width) => " ".repeat(*width),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(text: &str) -> Option<IndentStyle> {
+        IndentStyle::detect(&Rope::from_str(text))
+    }
+
+    #[test]
+    fn test_detect_spaces() {
+        let four = "fn main() {\n    if x {\n        y();\n    }\n}\n";
+        assert_eq!(detect(four), Some(IndentStyle::Spaces(4)));
+
+        let two = "a:\n  b:\n    c: 1\n  d: 2\ne: 3\n";
+        assert_eq!(detect(two), Some(IndentStyle::Spaces(2)));
+    }
+
+    #[test]
+    fn test_detect_tabs() {
+        let text = "func main() {\n\tif x {\n\t\ty()\n\t}\n}\n";
+        assert_eq!(detect(text), Some(IndentStyle::Tabs));
+    }
+
+    #[test]
+    fn test_block_comments_ignored() {
+        let text = "/**\n * Docs\n */\nfn f() {\n    g();\n}\n";
+        assert_eq!(detect(text), Some(IndentStyle::Spaces(4)));
+    }
+
+    #[test]
+    fn test_inconclusive() {
+        assert_eq!(detect(""), None);
+        assert_eq!(detect("no\nindentation\nhere\n"), None);
+        // One tab line, one space line
+        assert_eq!(detect("a\n\tb\nc\n    d\n"), None);
+    }
+}
@@ -14,15 +14,47 @@
 //! - Rope handles internal memory efficiently
 //! - Cursor positions are validated to prevent out-of-bounds access
 
+mod anchor;
+mod atomic;
+mod binary;
+mod bracket;
 mod buffer;
+mod change_set;
 mod cursor;
+mod encoding;
+mod grapheme;
 mod history;
+mod indent;
+mod line_ending;
+mod lines;
+mod loader;
+mod merge;
+mod search;
 mod selection;
+mod snapshot;
+mod text_object;
+mod undo_cache;
+mod word;
 
-pub use buffer::TextBuffer;
-pub use cursor::{Cursor, MultiCursor, Position};
-pub use history::{Edit, EditKind, History};
-pub use selection::Selection;
+pub use anchor::AnchorId;
+pub use binary::{ByteBuffer, is_binary};
+pub use bracket::{BRACKET_PAIRS, BracketMatch};
+pub use buffer::{BufferConfig, TextBuffer};
+pub use change_set::{Change, ChangeSet};
+pub use cursor::{Affinity, Cursor, MultiCursor, Position};
+pub use encoding::TextEncoding;
+pub use history::{Edit, EditKind, History, UndoBranch, UndoStep, UndoTarget};
+pub use indent::IndentStyle;
+pub use line_ending::LineEnding;
+pub use lines::{LineSort, join_lines, natural_cmp, shuffle_lines, sort_lines, unique_lines};
+pub use loader::FileLoader;
+pub use merge::{Merge, line_changes, merge3};
+pub use search::{SearchOptions, SearchQuery};
+pub use selection::{BlockSelection, Selection};
+pub use snapshot::BufferSnapshot;
+pub use text_object::{ObjectExtent, TextObject};
+pub use undo_cache::UndoCache;
+pub use word::WordKind;
 
 /// Result type for buffer operations
 pub type BufferResult<T> = Result<T, BufferError>;
@@ -42,12 +74,66 @@ pub enum BufferError {
     #[error("Selection is invalid: start {start:?} is after end {end:?}")]
     InvalidSelection { start: Position, end: Position },
 
+    #[error("Invalid search pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("Unknown encoding: {0}")]
+    UnknownEncoding(String),
+
+    #[error("File is not valid {0}")]
+    Decode(String),
+
+    #[error("File appears to be binary: {0}")]
+    BinaryFile(String),
+
+    #[error("Text contains characters that cannot be encoded as {0}")]
+    Encode(String),
+
+    #[error("Changes overlap at character {0}")]
+    OverlappingChanges(usize),
+
     #[error("Nothing to undo")]
     NothingToUndo,
 
     #[error("Nothing to redo")]
     NothingToRedo,
 
+    #[error("Undo history cache error: {0}")]
+    UndoCache(String),
+
+    #[error("Symlinks loop or nest too deeply at {}", .0.display())]
+    SymlinkLoop(std::path::PathBuf),
+
+    #[error("Could not create a temporary file in {}: {source}", .dir.display())]
+    CreateTemp {
+        dir: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not write {}: {source}", .path.display())]
+    Write {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not flush {} to disk: {source}", .path.display())]
+    Sync {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not set permissions of {}: {source}", .path.display())]
+    Permissions {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not overwrite {} in place: {source}", .path.display())]
+    InPlaceWrite {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
@@ -98,6 +184,101 @@ mod tests {
         assert_eq!(buffer.text(), "Hello World");
     }
 
+    #[test]
+    fn test_undo_branches() {
+        let mut buffer = TextBuffer::new();
+        buffer.insert(0, "Hello").unwrap();
+        buffer.insert(5, " World").unwrap();
+        buffer.undo().unwrap();
+        buffer.insert(5, " There").unwrap();
+        assert_eq!(buffer.undo_branches().len(), 2);
+
+        // The undone " World" is still reachable
+        assert!(buffer.jump_history(UndoTarget::PreviousBranch).is_some());
+        assert_eq!(buffer.text(), "Hello World");
+        assert!(buffer.jump_history(UndoTarget::NextBranch).is_some());
+        assert_eq!(buffer.text(), "Hello There");
+    }
+
+    #[test]
+    fn test_nested_groups_undo_atomically() {
+        let mut buffer = TextBuffer::from("a a a");
+        buffer.begin_undo_group();
+        buffer.insert(0, "> ").unwrap();
+        // replace_all opens and closes its own group inside ours
+        assert_eq!(buffer.replace_all("a", "b"), 3);
+        buffer.insert(7, "!").unwrap();
+        buffer.end_undo_group();
+        assert_eq!(buffer.text(), "> b b b!");
+
+        let step = buffer.undo().unwrap();
+        assert_eq!(step.edits.len(), 8);
+        assert_eq!(buffer.text(), "a a a");
+        assert!(!buffer.can_undo());
+
+        buffer.redo().unwrap();
+        assert_eq!(buffer.text(), "> b b b!");
+    }
+
+    #[test]
+    fn test_undo_restores_cursors() {
+        let mut buffer = TextBuffer::from("hello");
+        let mut cursors = MultiCursor::from_cursor(Cursor::new(Position::new(0, 5)));
+        cursors.add(Position::new(0, 0));
+
+        // One edit per cursor, back to front
+        buffer.record_cursors(&cursors);
+        buffer.begin_undo_group();
+        buffer.insert(5, "!").unwrap();
+        buffer.insert(0, "!").unwrap();
+        buffer.end_undo_group();
+        let mut after = MultiCursor::from_cursor(Cursor::new(Position::new(0, 7)));
+        after.add(Position::new(0, 1));
+        buffer.record_cursors_after(&after);
+
+        let step = buffer.undo().unwrap();
+        assert_eq!(step.cursors.unwrap().len(), 2);
+        assert_eq!(buffer.text(), "hello");
+
+        // Redo puts back the cursors from after the edit
+        let step = buffer.redo().unwrap();
+        assert_eq!(buffer.text(), "!hello!");
+        assert_eq!(step.cursors, Some(after.clone()));
+
+        // Recording after a redo doesn't overwrite them
+        buffer.record_cursors_after(&MultiCursor::new());
+        buffer.undo().unwrap();
+        assert_eq!(buffer.redo().unwrap().cursors, Some(after));
+    }
+
+    #[test]
+    fn test_grapheme_navigation() {
+        let buffer = TextBuffer::from("日e\u{301}\n👍🏽");
+        assert_eq!(buffer.next_grapheme_boundary(1), 3);
+        assert_eq!(buffer.next_grapheme_boundary(3), 4);
+        assert_eq!(buffer.prev_grapheme_boundary(4), 3);
+        assert_eq!(buffer.prev_grapheme_boundary(6), 4);
+
+        assert_eq!(buffer.display_column(Position::new(0, 3), 4).unwrap(), 3);
+        assert_eq!(buffer.column_at_display(1, 1, 4).unwrap(), 0);
+        assert_eq!(buffer.column_at_display(1, 2, 4).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_word_navigation() {
+        let buffer = TextBuffer::from("fn parseLine(\n\n    line: &str)");
+        assert_eq!(buffer.next_word_end(2, WordKind::Word), 12);
+        assert_eq!(buffer.next_word_end(2, WordKind::Subword), 8);
+        // Crosses the blank line
+        assert_eq!(buffer.next_word_end(13, WordKind::Word), 23);
+        assert_eq!(buffer.prev_word_start(19, WordKind::Word), 12);
+        assert_eq!(buffer.prev_word_start(3, WordKind::Word), 0);
+        assert_eq!(buffer.next_word_end(30, WordKind::Word), buffer.len_chars());
+
+        assert_eq!(buffer.word_range(5, WordKind::Word), Some(3..12));
+        assert_eq!(buffer.word_range(14, WordKind::Word), None);
+    }
+
     #[test]
     fn test_line_operations() {
         let buffer = TextBuffer::from("Line 1\nLine 2\nLine 3");
@@ -106,4 +287,91 @@ mod tests {
         assert_eq!(buffer.line(1).unwrap(), "Line 2\n");
         assert_eq!(buffer.line(2).unwrap(), "Line 3");
     }
+
+    #[test]
+    fn test_file_encoding_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin1.txt");
+        std::fs::write(&path, b"\xEF\xBB\xBFcaf\xC3\xA9").unwrap();
+
+        let mut buffer = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buffer.text(), "café");
+        assert!(buffer.encoding().has_bom());
+
+        // Convert to Latin-1 and save
+        buffer.set_encoding(TextEncoding::for_label("latin1").unwrap());
+        assert!(buffer.is_modified());
+        buffer.save().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"caf\xE9");
+
+        let reopened = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(reopened.text(), "café");
+        assert_eq!(reopened.encoding().name(), "windows-1252");
+    }
+
+    #[test]
+    fn test_line_ending_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dos.txt");
+        std::fs::write(&path, "one\r\ntwo\r\n").unwrap();
+
+        let mut buffer = TextBuffer::from_file(&path).unwrap();
+        assert_eq!(buffer.text(), "one\ntwo\n");
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+        assert_eq!(buffer.line_len(0).unwrap(), 4);
+
+        buffer.insert(4, "three\n").unwrap();
+        buffer.save().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "one\r\nthree\r\ntwo\r\n"
+        );
+
+        buffer.set_line_ending(LineEnding::Lf);
+        buffer.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\nthree\ntwo\n");
+    }
+
+    #[test]
+    fn test_line_endings_normalized_on_input() {
+        let mut buffer = TextBuffer::from("one\r\ntwo\rthree");
+        assert_eq!(buffer.text(), "one\ntwo\nthree");
+        assert_eq!(buffer.len_lines(), 3);
+
+        buffer.insert(3, "\r\nmid\r").unwrap();
+        assert_eq!(buffer.text(), "one\nmid\n\ntwo\nthree");
+        buffer.replace(0..3, "1\r\n").unwrap();
+        assert_eq!(buffer.text(), "1\n\nmid\n\ntwo\nthree");
+        assert!(!buffer.text().contains('\r'));
+
+        let changes = ChangeSet::new().insert(0, "a\r\n");
+        assert_eq!(changes.map(0, Affinity::Forward), 2);
+        buffer.apply_changes(&changes).unwrap();
+        assert!(buffer.text().starts_with("a\n1\n"));
+    }
+
+    #[test]
+    fn test_undo_history_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "one\n").unwrap();
+        let config = BufferConfig {
+            undo_cache: Some(UndoCache::new(dir.path().join("undo"))),
+            ..BufferConfig::default()
+        };
+
+        let mut buffer = TextBuffer::from_file_with_config(&path, config.clone()).unwrap();
+        buffer.insert(4, "two\n").unwrap();
+        buffer.save().unwrap();
+
+        // A new session can undo the previous one's edit
+        let mut reopened = TextBuffer::from_file_with_config(&path, config.clone()).unwrap();
+        reopened.undo().unwrap();
+        assert_eq!(reopened.text(), "one\n");
+
+        // ...unless the file was changed behind our back
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let changed = TextBuffer::from_file_with_config(&path, config).unwrap();
+        assert!(!changed.can_undo());
+    }
 }
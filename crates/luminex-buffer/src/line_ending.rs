@@ -0,0 +1,162 @@
+//! Line ending detection and normalization.
+//!
+//! ## Learning: Normalize at the Edges
+//!
+//! Inside the buffer every line ends with a plain `\n`. The file's
+//! original style is detected on load, remembered, and restored on
+//! save. This keeps column math simple: a `\r` is never counted as
+//! part of a line, and `line_len` means the same thing on every OS.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Line ending style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// Unix-style: \n
+    #[default]
+    Lf,
+    /// Windows-style: \r\n
+    CrLf,
+    /// Classic Mac: \r
+    Cr,
+}
+
+impl LineEnding {
+    /// Returns the string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// Returns the short name shown in the status bar.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr => "CR",
+        }
+    }
+
+    /// Parses a config value such as `"lf"`, `"crlf"` or `"cr"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "lf" | "unix" | "\n" => Some(LineEnding::Lf),
+            "crlf" | "windows" | "dos" | "\r\n" => Some(LineEnding::CrLf),
+            "cr" | "mac" | "\r" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+
+    /// Detects the most common line ending in text.
+    ///
+    /// Text without any line breaks is reported as `Lf`.
+    pub fn detect(text: &str) -> Self {
+        let mut counts = LineEndingCounts::default();
+        counts.add(text);
+        counts.most_common()
+    }
+
+    /// Converts every `\r\n` and lone `\r` to `\n`.
+    ///
+    /// Borrows when there is nothing to convert.
+    pub fn normalize(text: &str) -> Cow<'_, str> {
+        if !text.contains('\r') {
+            return Cow::Borrowed(text);
+        }
+        Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+    }
+
+    /// Converts normalized (`\n`-only) text to this line ending.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            LineEnding::Lf => Cow::Borrowed(text),
+            _ if !text.contains('\n') => Cow::Borrowed(text),
+            _ => Cow::Owned(text.replace('\n', self.as_str())),
+        }
+    }
+}
+
+impl std::fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Running tally of line breaks, for text that arrives in pieces.
+///
+/// A `\r\n` split across two pieces is counted as a lone `\r` and a
+/// `\n`, so callers hold back a trailing `\r` until the next piece.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LineEndingCounts {
+    lf: usize,
+    crlf: usize,
+    cr: usize,
+}
+
+impl LineEndingCounts {
+    /// Counts the line breaks in `text`.
+    pub(crate) fn add(&mut self, text: &str) {
+        let mut bytes = text.bytes().peekable();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'\r' if bytes.peek() == Some(&b'\n') => {
+                    bytes.next();
+                    self.crlf += 1;
+                }
+                b'\r' => self.cr += 1,
+                b'\n' => self.lf += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the most common line ending seen so far (`Lf` if none).
+    pub(crate) fn most_common(&self) -> LineEnding {
+        let (lf, crlf, cr) = (self.lf, self.crlf, self.cr);
+        if crlf > lf && crlf >= cr {
+            LineEnding::CrLf
+        } else if cr > lf && cr > crlf {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a\rb\r"), LineEnding::Cr);
+        assert_eq!(LineEnding::detect("no breaks"), LineEnding::Lf);
+        // Majority wins in mixed files
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), LineEnding::CrLf);
+    }
+
+    #[test]
+    fn test_normalize_and_apply() {
+        let normalized = LineEnding::normalize("a\r\nb\rc\n");
+        assert_eq!(normalized, "a\nb\nc\n");
+        assert!(matches!(LineEnding::normalize("a\nb"), Cow::Borrowed(_)));
+
+        assert_eq!(LineEnding::CrLf.apply(&normalized), "a\r\nb\r\nc\r\n");
+        assert_eq!(LineEnding::Cr.apply(&normalized), "a\rb\rc\r");
+        assert_eq!(LineEnding::Lf.apply(&normalized), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(LineEnding::parse("CRLF"), Some(LineEnding::CrLf));
+        assert_eq!(LineEnding::parse("lf"), Some(LineEnding::Lf));
+        assert_eq!(LineEnding::parse("native"), None);
+    }
+}
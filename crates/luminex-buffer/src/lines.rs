@@ -0,0 +1,179 @@
+//! Reordering, filtering and joining whole lines.
+//!
+//! ## Learning: Natural Sort
+//!
+//! Plain string comparison goes char by char, so `"file10"` sorts
+//! before `"file2"` because `'1' < '2'`. A natural sort compares runs
+//! of digits by their value instead:
+//!
+//! ```text
+//!   ascending:  file1  file10  file2
+//!   natural:    file1  file2   file10
+//! ```
+//!
+//! Everything here works on line contents without their `\n`; the
+//! document decides which lines to take and puts the result back.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// How [`sort_lines`] orders lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LineSort {
+    /// By Unicode scalar value.
+    #[default]
+    Ascending,
+    /// By Unicode scalar value, largest first.
+    Descending,
+    /// With runs of digits compared by value.
+    Natural,
+    /// Ignoring case.
+    CaseInsensitive,
+}
+
+/// Sorts lines. The sort is stable, so equal lines keep their order.
+pub fn sort_lines(lines: &mut [String], sort: LineSort) {
+    match sort {
+        LineSort::Ascending => lines.sort(),
+        LineSort::Descending => lines.sort_by(|a, b| b.cmp(a)),
+        LineSort::Natural => lines.sort_by(|a, b| natural_cmp(a, b)),
+        LineSort::CaseInsensitive => lines.sort_by_cached_key(|line| line.to_lowercase()),
+    }
+}
+
+/// Compares strings with runs of ASCII digits compared by value.
+///
+/// Numbers that are equal in value but written differently (`7` and
+/// `007`) fall back to comparing the digits as text.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a);
+                let y = take_digits(&mut b);
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let order = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                if x != y {
+                    return x.cmp(&y);
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+/// Removes repeated lines, keeping the first of each.
+pub fn unique_lines(lines: &mut Vec<String>) {
+    let mut seen = HashSet::new();
+    lines.retain(|line| seen.insert(line.clone()));
+}
+
+/// Shuffles lines. The same seed always gives the same order.
+pub fn shuffle_lines(lines: &mut [String], seed: u64) {
+    // xorshift64; zero would stay zero forever
+    let mut state = seed.max(1);
+    for i in (1..lines.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        lines.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Joins lines into one, the way Vim's `J` does: the leading
+/// whitespace of each joined line becomes a single space, or nothing
+/// if either side of the join is empty or already ends in whitespace.
+///
+/// Returns the joined line and the column of the last join.
+pub fn join_lines(lines: &[String]) -> (String, usize) {
+    let mut joined = lines.first().cloned().unwrap_or_default();
+    let mut column = joined.chars().count();
+    for line in lines.iter().skip(1) {
+        let line = line.trim_start();
+        column = joined.chars().count();
+        if !joined.is_empty() && !line.is_empty() && !joined.ends_with(char::is_whitespace) {
+            joined.push(' ');
+        }
+        joined.push_str(line);
+    }
+    (joined, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sort_lines() {
+        let mut lines = strings(&["file10", "File2", "file2", "file1"]);
+        sort_lines(&mut lines, LineSort::Ascending);
+        assert_eq!(lines, ["File2", "file1", "file10", "file2"]);
+        sort_lines(&mut lines, LineSort::Descending);
+        assert_eq!(lines, ["file2", "file10", "file1", "File2"]);
+        sort_lines(&mut lines, LineSort::Natural);
+        assert_eq!(lines, ["File2", "file1", "file2", "file10"]);
+        sort_lines(&mut lines, LineSort::CaseInsensitive);
+        assert_eq!(lines, ["file1", "file10", "File2", "file2"]);
+
+        assert_eq!(natural_cmp("a7", "a007"), Ordering::Greater);
+        assert_eq!(natural_cmp("a7b", "a7c"), Ordering::Less);
+        assert_eq!(natural_cmp("v1.10", "v1.9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_unique_and_shuffle() {
+        let mut lines = strings(&["b", "a", "b", "c", "a"]);
+        unique_lines(&mut lines);
+        assert_eq!(lines, ["b", "a", "c"]);
+
+        let mut shuffled = strings(&["1", "2", "3", "4", "5", "6"]);
+        shuffle_lines(&mut shuffled, 42);
+        let mut again = strings(&["1", "2", "3", "4", "5", "6"]);
+        shuffle_lines(&mut again, 42);
+        assert_eq!(shuffled, again);
+        shuffled.sort();
+        assert_eq!(shuffled, ["1", "2", "3", "4", "5", "6"]);
+    }
+
+    #[test]
+    fn test_join_lines() {
+        assert_eq!(
+            join_lines(&strings(&["let x =", "    1;"])),
+            ("let x = 1;".to_string(), 7)
+        );
+        assert_eq!(
+            join_lines(&strings(&["a ", "b", "", "c"])),
+            ("a b c".to_string(), 3)
+        );
+        assert_eq!(join_lines(&strings(&["only"])), ("only".to_string(), 4));
+    }
+}
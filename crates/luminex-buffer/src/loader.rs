@@ -0,0 +1,347 @@
+//! Reading files into a buffer one chunk at a time.
+//!
+//! ## Learning: Don't Hold the File Twice
+//!
+//! The simple way to open a file is `fs::read`, decode the bytes into a
+//! `String`, then build a rope from it. At its peak that holds the file
+//! three times over (bytes, string, rope), and nothing can be reported
+//! until the whole thing is done.
+//!
+//! `FileLoader` reads a fixed-size chunk, decodes it with a streaming
+//! decoder (which carries a character split across two chunks over to
+//! the next one) and appends it to a `RopeBuilder`. Only one chunk is in
+//! flight at a time, and the caller gets control back after each one to
+//! report progress, or to stop by dropping the loader.
+//!
+//! ```rust,ignore
+//! let mut loader = FileLoader::open(path, TextEncoding::default())?;
+//! while loader.load_chunk()? {
+//!     report(loader.loaded_bytes(), loader.total_bytes());
+//! }
+//! let buffer = loader.finish(BufferConfig::default())?;
+//! ```
+//!
+//! Line endings need the same care: a `\r\n` may straddle two chunks,
+//! so a trailing `\r` is held back until the next chunk arrives.
+//!
+//! The encoding is detected from the first chunk. If a file that looked
+//! like UTF-8 turns out not to be further in, loading starts over with
+//! the legacy fallback, just as detecting over the whole file would.
+
+use encoding_rs::{Decoder, DecoderResult};
+use ropey::RopeBuilder;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::binary::is_binary;
+use crate::buffer::{BufferConfig, TextBuffer};
+use crate::encoding::TextEncoding;
+use crate::line_ending::{LineEnding, LineEndingCounts};
+use crate::undo_cache::{self, HASH_SEED};
+use crate::{BufferError, BufferResult};
+
+/// Bytes read per chunk.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How a loader picks its encoding.
+enum Detect {
+    /// From the content, with a fallback for legacy 8-bit files
+    Auto(TextEncoding),
+    /// As given, keeping a matching BOM
+    Explicit(TextEncoding),
+}
+
+/// Loads a file into a [`TextBuffer`] incrementally.
+pub struct FileLoader {
+    file: File,
+    path: PathBuf,
+    chunk_size: usize,
+    /// File size when it was opened
+    total: u64,
+    /// Bytes read so far
+    loaded: u64,
+    /// The chunk read while detecting the encoding, not yet decoded
+    first_chunk: Option<Vec<u8>>,
+    /// A short read was seen; the next decode is the last
+    eof: bool,
+    done: bool,
+    encoding: TextEncoding,
+    /// What to start over with if the detected UTF-8 turns out wrong
+    retry_encoding: Option<TextEncoding>,
+    decoder: Decoder,
+    builder: RopeBuilder,
+    line_endings: LineEndingCounts,
+    /// A `\r` at the end of the last chunk, waiting for a possible `\n`
+    pending_cr: bool,
+    content_hash: u64,
+}
+
+impl FileLoader {
+    /// Opens a file, detecting its encoding from the first chunk.
+    ///
+    /// `fallback` is used when the content is neither UTF-8 nor UTF-16
+    /// (see [`TextEncoding::detect`]). Fails with
+    /// [`BufferError::BinaryFile`] if the content doesn't look like text;
+    /// open it as a [`ByteBuffer`](crate::ByteBuffer) instead.
+    pub fn open(path: impl AsRef<Path>, fallback: TextEncoding) -> BufferResult<Self> {
+        Self::open_with(path.as_ref(), Detect::Auto(fallback), CHUNK_SIZE)
+    }
+
+    /// Opens a file that is decoded with an explicit encoding.
+    ///
+    /// A BOM matching the encoding is still recognized and kept.
+    pub fn open_with_encoding(
+        path: impl AsRef<Path>,
+        encoding: TextEncoding,
+    ) -> BufferResult<Self> {
+        Self::open_with(path.as_ref(), Detect::Explicit(encoding), CHUNK_SIZE)
+    }
+
+    fn open_with(path: &Path, detect: Detect, chunk_size: usize) -> BufferResult<Self> {
+        let mut file = File::open(path)?;
+        let total = file.metadata()?.len();
+
+        let mut first_chunk = read_chunk(&mut file, chunk_size)?;
+        let eof = first_chunk.len() < chunk_size;
+        if !eof
+            && let Err(e) = std::str::from_utf8(&first_chunk)
+            && e.error_len().is_none()
+        {
+            // Finish the UTF-8 sequence cut off by the chunk boundary, so
+            // a valid UTF-8 file isn't mistaken for a legacy encoding
+            file.by_ref().take(3).read_to_end(&mut first_chunk)?;
+        }
+        let (encoding, retry_encoding) = match detect {
+            Detect::Auto(_) if is_binary(&first_chunk) => {
+                return Err(BufferError::BinaryFile(path.display().to_string()));
+            }
+            Detect::Auto(fallback) => {
+                let encoding = TextEncoding::detect(&first_chunk, fallback);
+                (
+                    encoding,
+                    (encoding == TextEncoding::utf8()).then(|| TextEncoding::legacy(fallback)),
+                )
+            }
+            Detect::Explicit(encoding) => {
+                let detected = TextEncoding::detect(&first_chunk, encoding);
+                (
+                    encoding.with_bom(detected.has_bom() && detected.name() == encoding.name()),
+                    None,
+                )
+            }
+        };
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            chunk_size,
+            total,
+            loaded: 0,
+            first_chunk: Some(first_chunk),
+            eof,
+            done: false,
+            encoding,
+            retry_encoding,
+            decoder: encoding.new_decoder(),
+            builder: RopeBuilder::new(),
+            line_endings: LineEndingCounts::default(),
+            pending_cr: false,
+            content_hash: HASH_SEED,
+        })
+    }
+
+    /// Returns the size of the file in bytes, as of when it was opened.
+    pub fn total_bytes(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns how many bytes have been loaded so far.
+    pub fn loaded_bytes(&self) -> u64 {
+        self.loaded
+    }
+
+    /// Returns true once the whole file has been loaded.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Returns the encoding the file is being decoded with.
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Reads and decodes the next chunk.
+    ///
+    /// Returns `false` once the end of the file has been reached. If
+    /// loading has to start over in another encoding, `loaded_bytes`
+    /// drops back to the start.
+    pub fn load_chunk(&mut self) -> BufferResult<bool> {
+        if self.done {
+            return Ok(false);
+        }
+        let result = self.load_next();
+        if let Err(BufferError::Decode(_)) = result
+            && let Some(encoding) = self.retry_encoding.take()
+        {
+            self.restart(encoding)?;
+            return Ok(true);
+        }
+        result
+    }
+
+    fn load_next(&mut self) -> BufferResult<bool> {
+        let bytes = match self.first_chunk.take() {
+            Some(bytes) => bytes,
+            None => {
+                let bytes = read_chunk(&mut self.file, self.chunk_size)?;
+                self.eof = bytes.len() < self.chunk_size;
+                bytes
+            }
+        };
+        self.loaded += bytes.len() as u64;
+        self.content_hash = undo_cache::extend_hash(self.content_hash, &bytes);
+
+        let mut text = String::new();
+        if self.pending_cr {
+            text.push('\r');
+            self.pending_cr = false;
+        }
+        self.decode(&bytes, &mut text)?;
+        if !self.eof && text.ends_with('\r') {
+            text.pop();
+            self.pending_cr = true;
+        }
+
+        self.line_endings.add(&text);
+        self.builder.append(&LineEnding::normalize(&text));
+        self.done = self.eof;
+        Ok(!self.done)
+    }
+
+    /// Throws away what was loaded and starts over in `encoding`.
+    fn restart(&mut self, encoding: TextEncoding) -> BufferResult<()> {
+        self.file.rewind()?;
+        self.loaded = 0;
+        self.first_chunk = None;
+        self.eof = false;
+        self.encoding = encoding;
+        self.decoder = encoding.new_decoder();
+        self.builder = RopeBuilder::new();
+        self.line_endings = LineEndingCounts::default();
+        self.pending_cr = false;
+        self.content_hash = HASH_SEED;
+        Ok(())
+    }
+
+    /// Loads whatever is left and returns the finished buffer.
+    pub fn finish(mut self, config: BufferConfig) -> BufferResult<TextBuffer> {
+        while self.load_chunk()? {}
+        Ok(TextBuffer::from_loaded(
+            &self.path,
+            self.builder.finish(),
+            self.content_hash,
+            self.encoding,
+            self.line_endings.most_common(),
+            config,
+        ))
+    }
+
+    /// Stops loading and returns a buffer holding what was loaded so far.
+    ///
+    /// This is how the start of a file too big to open in full is
+    /// previewed. A character or `\r\n` split at the cut is left out.
+    pub fn finish_partial(self, config: BufferConfig) -> TextBuffer {
+        TextBuffer::from_loaded(
+            &self.path,
+            self.builder.finish(),
+            self.content_hash,
+            self.encoding,
+            self.line_endings.most_common(),
+            config,
+        )
+    }
+
+    /// Decodes one chunk, failing on malformed input like
+    /// [`TextEncoding::decode`].
+    fn decode(&mut self, mut bytes: &[u8], text: &mut String) -> BufferResult<()> {
+        loop {
+            let needed = self
+                .decoder
+                .max_utf8_buffer_length_without_replacement(bytes.len())
+                .unwrap_or(bytes.len() * 3);
+            text.reserve(needed);
+            let (result, read) = self
+                .decoder
+                .decode_to_string_without_replacement(bytes, text, self.eof);
+            bytes = &bytes[read..];
+            match result {
+                DecoderResult::InputEmpty => return Ok(()),
+                DecoderResult::OutputFull => continue,
+                DecoderResult::Malformed(..) => {
+                    return Err(BufferError::Decode(self.encoding.name().to_string()));
+                }
+            }
+        }
+    }
+}
+
+/// Reads up to `size` bytes; fewer only at the end of the file.
+fn read_chunk(file: &mut File, size: usize) -> BufferResult<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(size);
+    file.take(size as u64).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(bytes: &[u8], chunk_size: usize) -> BufferResult<TextBuffer> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, bytes).unwrap();
+        let mut loader =
+            FileLoader::open_with(&path, Detect::Auto(TextEncoding::default()), chunk_size)?;
+        assert_eq!(loader.total_bytes(), bytes.len() as u64);
+        while loader.load_chunk()? {}
+        assert_eq!(loader.loaded_bytes(), bytes.len() as u64);
+        loader.finish(BufferConfig::default())
+    }
+
+    #[test]
+    fn test_chunk_boundaries() {
+        // 'é' is split after the 4th byte, both "\r\n" after a `\r`
+        let buffer = load("abcé..\r\nxy\r\nz".as_bytes(), 4).unwrap();
+        assert_eq!(buffer.text(), "abcé..\nxy\nz");
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+        assert_eq!(buffer.encoding().name(), "UTF-8");
+
+        let lone_cr = load(b"ab\r\rcd\r", 3).unwrap();
+        assert_eq!(lone_cr.text(), "ab\n\ncd\n");
+        assert_eq!(lone_cr.line_ending(), LineEnding::Cr);
+    }
+
+    #[test]
+    fn test_chunked_encodings() {
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("hé\nyo".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let buffer = load(&utf16, 3).unwrap();
+        assert_eq!(buffer.text(), "hé\nyo");
+        assert!(buffer.encoding().has_bom());
+
+        // A legacy byte at the end of the first chunk isn't cut off
+        assert_eq!(load(b"caf\xE9 ok", 4).unwrap().text(), "café ok");
+        assert!(matches!(
+            load(b"\x7fELF\x02\x01\x01\x00", 4),
+            Err(BufferError::BinaryFile(_))
+        ));
+        // Not UTF-8 after all: starts over in the fallback
+        let latin1 = load(b"abcd\r\nef\xFF", 4).unwrap();
+        assert_eq!(latin1.text(), "abcd\nefÿ");
+        assert_eq!(latin1.encoding().name(), "windows-1252");
+        assert_eq!(latin1.line_ending(), LineEnding::CrLf);
+    }
+}
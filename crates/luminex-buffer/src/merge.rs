@@ -0,0 +1,278 @@
+//! Line diffs and three-way merges.
+//!
+//! ## Learning: Three-Way Merge
+//!
+//! When a file changes on disk while the buffer has unsaved edits, two
+//! versions have moved away from a common one: the text as last saved.
+//! Comparing each side against that *base* tells who changed what:
+//!
+//! ```text
+//!   base     ours     theirs     result
+//!   a        a        a          a          nobody touched it
+//!   b        B        b          B          only ours changed: take ours
+//!   c        c        C          C          only theirs changed: take theirs
+//!   d        D1       D2         <<<<<<<    both changed: a conflict,
+//!                                D1         marked for the user to pick
+//!                                =======
+//!                                D2
+//!                                >>>>>>>
+//! ```
+//!
+//! Both comparisons come from a line diff (Myers' algorithm, which
+//! finds the longest run of lines two texts share in order). Lines
+//! every version agrees on split the texts into chunks, and each chunk
+//! is resolved as in the table above.
+
+use crate::change_set::ChangeSet;
+
+/// The result of [`merge3`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    /// The merged text, with conflicts marked
+    pub text: String,
+    /// How many conflicts were marked
+    pub conflicts: usize,
+}
+
+/// Merges the changes `ours` and `theirs` each made to `base`.
+///
+/// Chunks both sides changed differently are kept with Git-style
+/// markers, labelled with `labels` (ours, then theirs).
+pub fn merge3(base: &str, ours: &str, theirs: &str, labels: [&str; 2]) -> Merge {
+    let (base, ours, theirs) = (split_lines(base), split_lines(ours), split_lines(theirs));
+    let to_ours = matches(&base, &ours);
+    let to_theirs = matches(&base, &theirs);
+
+    let mut merge = Merge {
+        text: String::new(),
+        conflicts: 0,
+    };
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // Lines all three agree on
+        while i < base.len() && to_ours[i] == Some(j) && to_theirs[i] == Some(k) {
+            merge.text.push_str(base[i]);
+            (i, j, k) = (i + 1, j + 1, k + 1);
+        }
+        if i == base.len() && j == ours.len() && k == theirs.len() {
+            return merge;
+        }
+
+        // The chunk runs up to the next line all three agree on
+        let (next_i, next_j, next_k) = (i..base.len())
+            .find_map(|i| Some((i, to_ours[i]?, to_theirs[i]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (base_chunk, ours_chunk, theirs_chunk) =
+            (&base[i..next_i], &ours[j..next_j], &theirs[k..next_k]);
+        if ours_chunk == base_chunk || ours_chunk == theirs_chunk {
+            merge.text.push_str(&theirs_chunk.concat());
+        } else if theirs_chunk == base_chunk {
+            merge.text.push_str(&ours_chunk.concat());
+        } else {
+            merge.conflicts += 1;
+            push_marked(
+                &mut merge.text,
+                &format!("<<<<<<< {}", labels[0]),
+                ours_chunk,
+            );
+            push_marked(&mut merge.text, "=======", theirs_chunk);
+            push_marked(&mut merge.text, &format!(">>>>>>> {}", labels[1]), &[]);
+        }
+        (i, j, k) = (next_i, next_j, next_k);
+    }
+}
+
+/// Returns the edits that turn `old` into `new`, replacing whole lines.
+///
+/// Lines both texts share are left alone, so positions in them (such
+/// as cursors) stay put when the changes are applied.
+pub fn line_changes(old: &str, new: &str) -> ChangeSet {
+    let (old, new) = (split_lines(old), split_lines(new));
+    let mut starts = Vec::with_capacity(old.len() + 1);
+    let mut offset = 0;
+    for line in &old {
+        starts.push(offset);
+        offset += line.chars().count();
+    }
+    starts.push(offset);
+
+    let mut changes = ChangeSet::new();
+    let (mut i, mut j) = (0, 0);
+    let matched = matches(&old, &new);
+    let pairs = matched
+        .iter()
+        .enumerate()
+        .filter_map(|(i, j)| Some((i, (*j)?)));
+    for (next_i, next_j) in pairs.chain([(old.len(), new.len())]) {
+        if next_i > i || next_j > j {
+            changes = changes.replace(starts[i]..starts[next_i], new[j..next_j].concat());
+        }
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+    changes
+}
+
+/// Splits text into lines, each keeping its `\n`.
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Appends a marker line, then `lines`, making sure the marker starts a
+/// line of its own.
+fn push_marked(text: &mut String, marker: &str, lines: &[&str]) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(marker);
+    text.push('\n');
+    text.push_str(&lines.concat());
+}
+
+/// Returns, for each line of `a`, the line of `b` it is matched with by
+/// a shortest line diff.
+fn matches(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    let mut matched = vec![None; a.len()];
+    // Most edits touch a small part of a file: skip what's unchanged
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    for (i, slot) in matched.iter_mut().enumerate().take(prefix) {
+        *slot = Some(i);
+    }
+    for s in 1..=suffix {
+        matched[a.len() - s] = Some(b.len() - s);
+    }
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    for (i, j) in myers(a_mid, b_mid) {
+        matched[prefix + i] = Some(prefix + j);
+    }
+    matched
+}
+
+/// Returns the `(a, b)` index pairs of the lines a shortest edit script
+/// keeps, in order.
+///
+/// Round `d` finds, for every diagonal `k = x - y`, the furthest point
+/// reachable with `d` insertions and deletions. Each round's furthest
+/// points are kept so the path can be walked back from the end.
+fn myers(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let index = |k: isize| (k + max) as usize;
+
+    let mut trace = Vec::new();
+    'rounds: for d in 0..=max {
+        // Only diagonals -d..=d can have been reached so far
+        trace.push(v[index(-d)..=index(d)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            // Moving down (an insertion) from diagonal k + 1 beats moving
+            // right (a deletion) from k - 1 if it got further
+            let down = k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]);
+            let mut x = if down {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                (x, y) = (x + 1, y + 1);
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'rounds;
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, reached) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| reached[(k + d) as usize];
+        let k = x - y;
+        // Round 0 starts at the origin; later ones after one edit
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            (at(prev_k), at(prev_k) - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            (x, y) = (x - 1, y - 1);
+            pairs.push((x as usize, y as usize));
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    pairs.reverse();
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextBuffer;
+
+    const LABELS: [&str; 2] = ["ours", "theirs"];
+
+    #[test]
+    fn test_merge3_takes_both_sides() {
+        let base = "a\nb\nc\nd\n";
+        let merge = merge3(base, "a\nB\nc\nd\n", "a\nb\nc\nD\ne\n", LABELS);
+        assert_eq!(
+            merge,
+            Merge {
+                text: "a\nB\nc\nD\ne\n".to_string(),
+                conflicts: 0,
+            }
+        );
+        // The same change on both sides isn't a conflict
+        assert_eq!(merge3(base, "x\n", "x\n", LABELS).text, "x\n");
+        assert_eq!(merge3("", "", "new", LABELS).text, "new");
+    }
+
+    #[test]
+    fn test_merge3_marks_conflicts() {
+        let merge = merge3("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n", LABELS);
+        assert_eq!(merge.conflicts, 1);
+        assert_eq!(
+            merge.text,
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+
+        // Markers always start their own line
+        let merge = merge3("x", "y", "z", LABELS);
+        assert_eq!(merge.text, "<<<<<<< ours\ny\n=======\nz\n>>>>>>> theirs\n");
+    }
+
+    #[test]
+    fn test_line_changes() {
+        for (old, new) in [
+            ("a\nb\nc\n", "a\nx\nc\n"),
+            ("a\nb\nc\n", "c\nb\na\n"),
+            ("", "a\nb"),
+            ("a\nb", ""),
+            ("one\ntwo\nthree", "zero\none\nthree\nfour"),
+        ] {
+            let mut buffer = TextBuffer::from(old);
+            buffer.apply_changes(&line_changes(old, new)).unwrap();
+            assert_eq!(buffer.text(), new);
+        }
+
+        let changes = line_changes("keep\nold\nkeep\n", "keep\nnew\nkeep\n");
+        assert_eq!(changes.changes().len(), 1);
+        assert_eq!(changes.changes()[0].range, 5..9);
+        assert!(line_changes("same\n", "same\n").is_empty());
+    }
+}
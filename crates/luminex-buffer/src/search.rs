@@ -0,0 +1,602 @@
+//! Regex-backed search and replace.
+//!
+//! ## Learning: Compile Once, Search Many
+//!
+//! Building a `Regex` parses the pattern and compiles an automaton,
+//! which is far more expensive than running it. `SearchQuery` does
+//! the compilation up front so "find next" can be called on every
+//! keystroke without re-parsing the pattern.
+//!
+//! All ranges returned by the search API are **character** ranges,
+//! matching the rest of the buffer API. Byte offsets from the regex
+//! engine are converted through the rope's own indexes.
+//!
+//! ## Streaming Over the Rope
+//!
+//! A rope stores text as a tree of small chunks, so copying it into
+//! one `String` just to search it defeats the point. Instead:
+//!
+//! - **Literal patterns** are matched chunk by chunk, carrying the
+//!   last `needle.len() - 1` bytes forward so matches that straddle
+//!   a chunk boundary are still found.
+//! - **Single-line regexes** run over one line at a time. Lines that
+//!   live inside one chunk are borrowed, not copied.
+//! - **Multiline regexes** run over overlapping windows of whole lines.
+//!   A match must fit inside half a window (`MULTILINE_WINDOW / 2`).
+//!
+//! Searching backwards runs the same forward scan over one block of
+//! text at a time (`BACKWARD_STEP`, or half a window for multiline
+//! regexes), moving towards the start of the buffer until a block
+//! holds a match. A window never reaches further past its block than
+//! a match starting in it can, so each block's text is scanned about
+//! twice, not once per window.
+
+use regex::{Regex, RegexBuilder};
+use ropey::Rope;
+use std::borrow::Cow;
+use std::ops::{ControlFlow, Range};
+
+use crate::{BufferError, BufferResult};
+
+/// Target size of the text handed to a multiline regex at once.
+const MULTILINE_WINDOW: usize = 1 << 20;
+
+/// Size of the blocks a backward search scans, nearest first.
+const BACKWARD_STEP: usize = 1 << 16;
+
+/// Options controlling how a pattern is matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchOptions {
+    /// Treat the pattern as a regular expression instead of literal text
+    pub regex: bool,
+    /// Ignore letter case when matching
+    pub case_insensitive: bool,
+    /// Only match at word boundaries
+    pub whole_word: bool,
+    /// Allow matches to span line breaks
+    pub multiline: bool,
+}
+
+/// A compiled search pattern.
+///
+/// ## Example
+///
+/// ```
+/// use luminex_buffer::{SearchOptions, SearchQuery, TextBuffer};
+///
+/// let options = SearchOptions { regex: true, ..Default::default() };
+/// let query = SearchQuery::new(r"(\w+)@(\w+)", options).unwrap();
+///
+/// let mut buffer = TextBuffer::from("alice@home bob@work");
+/// buffer.replace_all_matches(&query, "$2:$1").unwrap();
+/// assert_eq!(buffer.text(), "home:alice work:bob");
+/// ```
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    /// The pattern as typed by the user
+    pattern: String,
+    /// Options the pattern was compiled with
+    options: SearchOptions,
+    /// The compiled regex
+    regex: Regex,
+}
+
+impl SearchQuery {
+    /// Compiles a pattern with the given options.
+    ///
+    /// Literal patterns are escaped, so `a.b` only matches `a.b`.
+    pub fn new(pattern: &str, options: SearchOptions) -> BufferResult<Self> {
+        if pattern.is_empty() {
+            return Err(BufferError::InvalidPattern("pattern is empty".to_string()));
+        }
+
+        let mut source = if options.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+
+        if options.whole_word {
+            source = format!(r"\b(?:{})\b", source);
+        }
+
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(options.case_insensitive)
+            // `^` and `$` should always mean line start/end in an editor
+            .multi_line(true)
+            .build()
+            .map_err(|e| BufferError::InvalidPattern(e.to_string()))?;
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            options,
+            regex,
+        })
+    }
+
+    /// Returns the pattern as originally given.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns the options this query was compiled with.
+    pub fn options(&self) -> SearchOptions {
+        self.options
+    }
+
+    /// Returns the compiled regex.
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    /// Returns the needle if this query is a plain case-sensitive
+    /// literal that can skip the regex engine entirely.
+    pub(crate) fn as_literal(&self) -> Option<&str> {
+        let o = self.options;
+        let plain = !o.regex && !o.case_insensitive && !o.whole_word;
+        // Without `multiline`, a literal newline must never match
+        (plain && (o.multiline || !self.pattern.contains(['\n', '\r']))).then_some(&self.pattern)
+    }
+}
+
+/// Streams the char range of every match at or after `from` to `f`.
+///
+/// Plain literals walk the rope's chunks directly; everything else
+/// goes through the regex engine one line (or window) at a time.
+pub(crate) fn for_each_match(
+    rope: &Rope,
+    query: &SearchQuery,
+    from: usize,
+    mut f: impl FnMut(Range<usize>) -> ControlFlow<()>,
+) {
+    let bytes = rope.char_to_byte(from)..rope.len_bytes();
+    for_each_match_in(rope, query, bytes, &mut f);
+}
+
+/// Like [`for_each_match`], but only for matches starting in `bytes`.
+fn for_each_match_in(
+    rope: &Rope,
+    query: &SearchQuery,
+    bytes: Range<usize>,
+    mut f: impl FnMut(Range<usize>) -> ControlFlow<()>,
+) {
+    if let Some(needle) = query.as_literal() {
+        for_each_literal(rope, needle, bytes, |byte_idx| {
+            f(rope.byte_to_char(byte_idx)..rope.byte_to_char(byte_idx + needle.len()))
+        });
+    } else {
+        for_each_regex_match(rope, query, bytes, |bytes, _, _| {
+            f(rope.byte_to_char(bytes.start)..rope.byte_to_char(bytes.end))
+        });
+    }
+}
+
+/// Returns the char range of the last match starting before `before`.
+///
+/// Scans back from `before` one block at a time, so a match near the
+/// cursor is found without reading the rest of the buffer.
+pub(crate) fn last_match_before(
+    rope: &Rope,
+    query: &SearchQuery,
+    before: usize,
+) -> Option<Range<usize>> {
+    let mut end = rope.char_to_byte(before);
+    while end > 0 {
+        // Blocks start on a line so `^` and single-line regexes see
+        // whole lines; literals only need a char boundary
+        let step = if query.options().multiline {
+            MULTILINE_WINDOW / 2
+        } else {
+            BACKWARD_STEP
+        };
+        let start = end.saturating_sub(step);
+        let start = if query.as_literal().is_some() {
+            rope.char_to_byte(rope.byte_to_char(start))
+        } else {
+            rope.line_to_byte(rope.byte_to_line(start))
+        };
+
+        let mut last = None;
+        for_each_match_in(rope, query, start..end, |range| {
+            last = Some(range);
+            ControlFlow::Continue(())
+        });
+        if last.is_some() {
+            return last;
+        }
+        end = start;
+    }
+    None
+}
+
+/// Calls `f` with the byte offset of every non-overlapping occurrence
+/// of `needle` starting in `bytes`, walking the rope's chunks.
+///
+/// Stops early when `f` returns `ControlFlow::Break`.
+pub(crate) fn for_each_literal(
+    rope: &Rope,
+    needle: &str,
+    bytes: Range<usize>,
+    mut f: impl FnMut(usize) -> ControlFlow<()>,
+) {
+    if needle.is_empty() {
+        return;
+    }
+    let (from_byte, until) = (bytes.start, bytes.end);
+
+    let needle_bytes = needle.as_bytes();
+    let keep = needle.len() - 1;
+    let (chunks, first_chunk_start, _, _) = rope.chunks_at_byte(from_byte);
+
+    let mut chunk_start = first_chunk_start;
+    // Tail of the text seen so far, for matches crossing a boundary
+    let mut carry: Vec<u8> = Vec::with_capacity(keep * 2);
+    let mut carry_start = from_byte;
+    // Matches are non-overlapping: nothing may start before this
+    let mut next_allowed = from_byte;
+
+    for chunk in chunks {
+        // Everything from here on starts at or after `until`
+        if chunk_start >= until.saturating_add(keep) {
+            return;
+        }
+        let mut text = chunk;
+        if chunk_start < from_byte {
+            text = &chunk[from_byte - chunk_start..];
+            chunk_start = from_byte;
+        }
+        let bytes = text.as_bytes();
+
+        // 1. Matches that start in the carry and end in this chunk
+        if !carry.is_empty() {
+            let take = keep.min(bytes.len());
+            let mut window = carry.clone();
+            window.extend_from_slice(&bytes[..take]);
+
+            let mut i = 0;
+            while i < carry.len() && i + needle_bytes.len() <= window.len() {
+                let abs = carry_start + i;
+                if abs >= until {
+                    return;
+                }
+                if abs >= next_allowed && window[i..].starts_with(needle_bytes) {
+                    if f(abs).is_break() {
+                        return;
+                    }
+                    next_allowed = abs + needle_bytes.len();
+                    i += needle_bytes.len();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        // 2. Matches entirely inside this chunk
+        let skip = next_allowed.saturating_sub(chunk_start).min(text.len());
+        for (pos, _) in text[skip..].match_indices(needle) {
+            let abs = chunk_start + skip + pos;
+            if abs >= until || f(abs).is_break() {
+                return;
+            }
+            next_allowed = abs + needle_bytes.len();
+        }
+
+        // 3. Keep the last `keep` bytes for the next boundary
+        carry.extend_from_slice(bytes);
+        let excess = carry.len().saturating_sub(keep);
+        carry.drain(..excess);
+        chunk_start += bytes.len();
+        carry_start = chunk_start - carry.len();
+    }
+}
+
+/// Calls `f` for every regex match starting in `bytes`.
+///
+/// `f` receives the absolute byte range of the match, the haystack it
+/// was found in and the haystack's absolute byte offset, so callers can
+/// re-run the regex for captures. Stops early on `ControlFlow::Break`.
+pub(crate) fn for_each_regex_match(
+    rope: &Rope,
+    query: &SearchQuery,
+    bytes: Range<usize>,
+    mut f: impl FnMut(Range<usize>, &str, usize) -> ControlFlow<()>,
+) {
+    let regex = query.regex();
+    let (from_byte, until) = (bytes.start, bytes.end);
+    let mut next_allowed = from_byte;
+
+    let mut run = |haystack: &str, base: usize, accept_until: usize| {
+        let start = next_allowed.saturating_sub(base).min(haystack.len());
+        // `find_at` keeps the text before `start` as context for `\b` and `^`
+        let mut at = start;
+        while let Some(m) = regex.find_at(haystack, at) {
+            let range = base + m.start()..base + m.end();
+            if range.start >= until {
+                return ControlFlow::Break(());
+            }
+            if range.start >= accept_until {
+                break;
+            }
+            if f(range.clone(), haystack, base).is_break() {
+                return ControlFlow::Break(());
+            }
+            next_allowed = range.end;
+            at = if m.is_empty() {
+                // Step over the empty match without splitting a character
+                match haystack[m.end()..].chars().next() {
+                    Some(c) => m.end() + c.len_utf8(),
+                    None => break,
+                }
+            } else {
+                m.end()
+            };
+        }
+        ControlFlow::Continue(())
+    };
+
+    let first_line = rope.byte_to_line(from_byte);
+
+    if !query.options().multiline {
+        let mut line_start = rope.line_to_byte(first_line);
+        for line in rope.lines_at(first_line) {
+            if line_start >= until {
+                return;
+            }
+            let len = line.len_bytes();
+            let text: Cow<'_, str> = line.into();
+            let content = text.trim_end_matches(['\n', '\r']);
+            if run(content, line_start, usize::MAX).is_break() {
+                return;
+            }
+            line_start += len;
+        }
+        return;
+    }
+
+    // Multiline: overlapping windows of whole lines. Matches are only
+    // accepted from the first half of each window (except the last),
+    // so every accepted match had at least half a window to grow into.
+    // Past `until` a window only needs room for a match to end in.
+    let total_lines = rope.len_lines();
+    let limit = until.saturating_add(MULTILINE_WINDOW / 2);
+    let mut start_line = first_line;
+    loop {
+        let window_start = rope.line_to_byte(start_line);
+        if window_start >= until {
+            return;
+        }
+        let mut end_line = start_line;
+        let mut mid_line = start_line + 1;
+        while end_line < total_lines {
+            end_line += 1;
+            let size = rope.line_to_byte(end_line) - window_start;
+            if size <= MULTILINE_WINDOW / 2 {
+                mid_line = end_line;
+            }
+            if size >= MULTILINE_WINDOW || window_start + size >= limit {
+                break;
+            }
+        }
+
+        let window_end = rope.line_to_byte(end_line);
+        let is_last = end_line >= total_lines || window_end >= limit;
+        let text: Cow<'_, str> = rope.byte_slice(window_start..window_end).into();
+        let accept_until = if is_last {
+            usize::MAX
+        } else {
+            rope.line_to_byte(mid_line)
+        };
+
+        if run(&text, window_start, accept_until).is_break() || is_last {
+            return;
+        }
+        start_line = mid_line;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextBuffer;
+
+    fn query(pattern: &str, options: SearchOptions) -> SearchQuery {
+        SearchQuery::new(pattern, options).unwrap()
+    }
+
+    #[test]
+    fn test_literal_is_escaped() {
+        let buffer = TextBuffer::from("a.b axb");
+        let q = query("a.b", SearchOptions::default());
+        assert_eq!(buffer.search_all(&q), vec![0..3]);
+    }
+
+    #[test]
+    fn test_regex_char_ranges() {
+        let buffer = TextBuffer::from("héllo wörld\nnext wörd");
+        let q = query(
+            r"w\w+",
+            SearchOptions {
+                regex: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(buffer.search_all(&q), vec![6..11, 17..21]);
+    }
+
+    #[test]
+    fn test_case_insensitive_and_whole_word() {
+        let buffer = TextBuffer::from("Foo food foo");
+        let q = query(
+            "foo",
+            SearchOptions {
+                case_insensitive: true,
+                whole_word: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(buffer.search_all(&q), vec![0..3, 9..12]);
+    }
+
+    #[test]
+    fn test_multiline() {
+        let buffer = TextBuffer::from("one\ntwo\n");
+        let single = query(
+            r"one\ntwo",
+            SearchOptions {
+                regex: true,
+                ..Default::default()
+            },
+        );
+        assert!(buffer.search_all(&single).is_empty());
+
+        let multi = query(
+            r"one\ntwo",
+            SearchOptions {
+                regex: true,
+                multiline: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(buffer.search_all(&multi), vec![0..7]);
+    }
+
+    #[test]
+    fn test_search_next_and_prev_wrap() {
+        let buffer = TextBuffer::from("ab ab ab");
+        let q = query("ab", SearchOptions::default());
+        assert_eq!(buffer.search_next(&q, 1), Some(3..5));
+        assert_eq!(buffer.search_next(&q, 7), Some(0..2));
+        assert_eq!(buffer.search_prev(&q, 3), Some(0..2));
+        assert_eq!(buffer.search_prev(&q, 0), Some(6..8));
+    }
+
+    #[test]
+    fn test_search_prev_across_blocks() {
+        // Matches far apart, so the backward scan crosses several blocks
+        let filler = "filler line\n".repeat(BACKWARD_STEP / 8);
+        let text = format!("one x\n{filler}two x\n{filler}three");
+        let buffer = TextBuffer::from(text.as_str());
+        let second = text.find("two x").unwrap() + 4;
+        let end = buffer.len_chars();
+        let regex = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+
+        for options in [
+            SearchOptions::default(),
+            regex,
+            SearchOptions {
+                multiline: true,
+                ..regex
+            },
+        ] {
+            let q = query("x", options);
+            assert_eq!(buffer.search_prev(&q, end), Some(second..second + 1));
+            assert_eq!(buffer.search_prev(&q, second), Some(4..5));
+            assert_eq!(buffer.search_prev(&q, 4), Some(second..second + 1));
+        }
+
+        // `^` must still see the start of the line it matches on
+        let q = query("^two", regex);
+        assert_eq!(buffer.search_prev(&q, end), Some(second - 4..second - 1));
+    }
+
+    #[test]
+    fn test_replace_with_captures() {
+        let mut buffer = TextBuffer::from("let x = 1;\nlet y = 2;");
+        let q = query(
+            r"let (\w) = (\d)",
+            SearchOptions {
+                regex: true,
+                ..Default::default()
+            },
+        );
+        let count = buffer
+            .replace_all_matches(&q, "const ${1}: i32 = $2")
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(buffer.text(), "const x: i32 = 1;\nconst y: i32 = 2;");
+    }
+
+    #[test]
+    fn test_literal_across_chunk_boundaries() {
+        // Large enough that ropey splits it into many chunks
+        let mut text = String::new();
+        for i in 0..2000 {
+            text.push_str(if i % 7 == 0 { "xxneédlexx" } else { "né" });
+            text.push_str(&"a".repeat(i % 13));
+        }
+        let buffer = TextBuffer::from(text.as_str());
+
+        let expected: Vec<usize> = text
+            .match_indices("neédle")
+            .map(|(b, _)| text[..b].chars().count())
+            .collect();
+        assert!(expected.len() > 100);
+        assert_eq!(buffer.find_all("neédle"), expected);
+        assert_eq!(
+            buffer.find_next("neédle", expected[50] + 1),
+            Some(expected[51])
+        );
+    }
+
+    #[test]
+    fn test_literal_matches_do_not_overlap() {
+        let buffer = TextBuffer::from("aaaaa");
+        assert_eq!(buffer.find_all("aa"), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_multiline_across_windows() {
+        // More than one window's worth of text
+        let mut text = String::new();
+        for i in 0..120_000 {
+            if i % 997 == 0 {
+                text.push_str("begin\nend\n");
+            }
+            text.push_str("filler line of text\n");
+        }
+        let buffer = TextBuffer::from(text.as_str());
+        assert!(buffer.len_bytes() > MULTILINE_WINDOW);
+
+        let q = query(
+            r"begin\nend",
+            SearchOptions {
+                regex: true,
+                multiline: true,
+                ..Default::default()
+            },
+        );
+        let expected: Vec<_> = q
+            .regex()
+            .find_iter(&text)
+            .map(|m| text[..m.start()].chars().count()..text[..m.end()].chars().count())
+            .collect();
+        assert_eq!(buffer.search_all(&q), expected);
+
+        // Backwards, block by block
+        let last = expected.len() - 1;
+        let before = expected[last].start;
+        assert_eq!(
+            buffer.search_prev(&q, buffer.len_chars()),
+            Some(expected[last].clone())
+        );
+        assert_eq!(
+            buffer.search_prev(&q, before),
+            Some(expected[last - 1].clone())
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let err = SearchQuery::new(
+            "(",
+            SearchOptions {
+                regex: true,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(err, Err(BufferError::InvalidPattern(_))));
+    }
+}
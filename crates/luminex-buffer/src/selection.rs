@@ -9,8 +9,9 @@
 //! - Easier arithmetic (length = end - start)
 //! - Consistent with slice semantics
 
-use crate::Position;
+use crate::{BufferResult, Cursor, MultiCursor, Position, TextBuffer};
 use serde::{Deserialize, Serialize};
+use std::ops::{Range, RangeInclusive};
 
 /// A selection of text in the buffer.
 ///
@@ -180,6 +181,7 @@ impl DirectedSelection {
     }
 
     /// Returns a normalized (start <= end) selection.
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_selection(&self) -> Selection {
         Selection::new(self.anchor, self.active)
     }
@@ -205,6 +207,88 @@ impl DirectedSelection {
     }
 }
 
+/// A rectangular (column) selection.
+///
+/// ## Learning: Display Columns, Not Chars
+///
+/// A block covers the same *visual* columns on every line, so its
+/// corners are stored as (line, display column) rather than char
+/// positions: a tab or a wide CJK character takes more than one cell,
+/// and the rectangle must still line up on screen.
+///
+/// Editing happens through [`BlockSelection::to_cursors`], which turns
+/// the rectangle into one cursor per line. Lines too short to reach
+/// the block get a cursor at their end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockSelection {
+    /// Corner where the selection started (column is a display column)
+    pub anchor: Position,
+    /// Corner that moves as the selection is extended
+    pub active: Position,
+}
+
+impl BlockSelection {
+    /// Creates a block between two (line, display column) corners.
+    pub fn new(anchor: Position, active: Position) -> Self {
+        Self { anchor, active }
+    }
+
+    /// Returns the lines the block spans.
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.anchor.line.min(self.active.line)..=self.anchor.line.max(self.active.line)
+    }
+
+    /// Returns the display columns the block spans.
+    pub fn columns(&self) -> Range<usize> {
+        self.anchor.column.min(self.active.column)..self.anchor.column.max(self.active.column)
+    }
+
+    /// Returns true if the block is zero columns wide.
+    pub fn is_empty(&self) -> bool {
+        self.anchor.column == self.active.column
+    }
+
+    /// Returns the same block moved to another display column, zero wide.
+    pub fn collapse_to(&self, column: usize) -> Self {
+        Self {
+            anchor: Position::new(self.anchor.line, column),
+            active: Position::new(self.active.line, column),
+        }
+    }
+
+    /// Returns one cursor per line of the block, selecting its columns.
+    ///
+    /// Selections point the same way as the block, and the cursor on
+    /// the active line is the primary one. Lines past the end of the
+    /// buffer are left out.
+    pub fn to_cursors(&self, buffer: &TextBuffer, tab_width: usize) -> BufferResult<MultiCursor> {
+        let last_line = buffer.len_lines().saturating_sub(1);
+        let lines = *self.lines().start()..=(*self.lines().end()).min(last_line);
+        let backward = self.active.column < self.anchor.column;
+
+        let mut cursors: Option<MultiCursor> = None;
+        // The active line goes last so it ends up primary
+        let active_line = self.active.line.min(last_line);
+        for line in lines.filter(|&l| l != active_line).chain([active_line]) {
+            let start = buffer.column_at_display(line, self.columns().start, tab_width)?;
+            let end = buffer.column_at_display(line, self.columns().end, tab_width)?;
+            let (anchor, position) = if backward { (end, start) } else { (start, end) };
+            let (anchor, position) = (Position::new(line, anchor), Position::new(line, position));
+            match cursors.as_mut() {
+                Some(cursors) => {
+                    cursors.add_selection(anchor, position);
+                }
+                None => {
+                    let mut cursor = Cursor::new(anchor);
+                    cursor.select_to(position);
+                    cursors = Some(MultiCursor::from_cursor(cursor));
+                }
+            }
+        }
+        Ok(cursors.unwrap_or_default())
+    }
+}
+
 /// A collection of selections for multi-cursor editing.
 #[allow(dead_code)]
 #[derive(Debug, Clone, Default)]
@@ -264,6 +348,7 @@ impl SelectionSet {
     }
 
     /// Removes all selections except the primary one.
+    #[allow(clippy::clone_on_copy)]
     pub fn collapse_to_primary(&mut self) {
         let primary = self.selections[self.primary_index].clone();
         self.selections.clear();
@@ -272,6 +357,7 @@ impl SelectionSet {
     }
 
     /// Normalizes selections: sorts and merges overlapping ones.
+    #[allow(clippy::unnecessary_sort_by)]
     fn normalize(&mut self) {
         if self.selections.is_empty() {
             return;
@@ -340,6 +426,30 @@ mod tests {
         assert_eq!(merged.end, Position::new(0, 15));
     }
 
+    #[test]
+    fn test_block_to_cursors() {
+        let buffer = TextBuffer::from("abcdef\nab\n\tx\n日本語");
+        // Columns 1..4 on all four lines, drawn bottom-up
+        let block = BlockSelection::new(Position::new(3, 4), Position::new(0, 1));
+        let cursors = block.to_cursors(&buffer, 4).unwrap();
+
+        let ranges: Vec<_> = cursors.all().iter().map(|c| c.range()).collect();
+        assert_eq!(
+            ranges,
+            [
+                (Position::new(0, 1), Position::new(0, 4)),
+                // A short line gets what it has
+                (Position::new(1, 1), Position::new(1, 2)),
+                // The tab covers columns 0..4
+                (Position::new(2, 0), Position::new(2, 1)),
+                // 本 starts at column 2
+                (Position::new(3, 0), Position::new(3, 2)),
+            ]
+        );
+        assert_eq!(cursors.primary().range(), ranges[0]);
+        assert_eq!(cursors.primary().anchor, Some(Position::new(0, 4)));
+    }
+
     #[test]
     fn test_directed_selection() {
         let forward = DirectedSelection::new(Position::new(0, 0), Position::new(0, 10));
@@ -0,0 +1,209 @@
+//! Read-only views of a buffer for background work.
+//!
+//! ## Learning: Sharing Without Locking
+//!
+//! Syntax parsing, LSP sync, search and autosave all want to read the
+//! text while the user keeps typing. Handing them `&TextBuffer` would
+//! freeze editing until they finish, and cloning the buffer copies the
+//! whole undo history.
+//!
+//! A rope is a tree of reference-counted chunks, so cloning one only
+//! bumps a counter. An edit copies the few nodes on the path it
+//! touches and leaves the old tree alone, which means a clone taken
+//! before the edit keeps seeing the old text:
+//!
+//! ```text
+//!   buffer.snapshot()  ──► v7: "fn main() {}"   (sent to a parser task)
+//!   buffer.insert(..)  ──► v8: "fn main() { }"
+//!   parser finishes v7 ──► 7 != buffer.version(), result dropped
+//! ```
+//!
+//! Every snapshot carries the version it was taken at. Versions only
+//! grow, so a result computed from an older snapshot is always stale,
+//! even if undo has brought back the same text.
+
+use ropey::Rope;
+use std::borrow::Cow;
+use std::ops::{ControlFlow, Range};
+
+use crate::encoding::TextEncoding;
+use crate::line_ending::LineEnding;
+use crate::search::{self, SearchQuery};
+use crate::{BufferError, BufferResult, Position};
+
+/// An immutable copy of a buffer's text at one version.
+///
+/// Cloning is O(1), and the snapshot is `Send + Sync`, so it can be
+/// shared freely between tasks.
+#[derive(Debug, Clone)]
+pub struct BufferSnapshot {
+    rope: Rope,
+    version: u64,
+    encoding: TextEncoding,
+    line_ending: LineEnding,
+}
+
+impl BufferSnapshot {
+    pub(crate) fn new(
+        rope: Rope,
+        version: u64,
+        encoding: TextEncoding,
+        line_ending: LineEnding,
+    ) -> Self {
+        Self {
+            rope,
+            version,
+            encoding,
+            line_ending,
+        }
+    }
+
+    /// Returns the buffer version this snapshot was taken at.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns true if the buffer has changed since this snapshot.
+    pub fn is_stale(&self, current_version: u64) -> bool {
+        self.version != current_version
+    }
+
+    /// Returns the underlying rope, for consumers that read it in chunks.
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    /// Returns the entire text content.
+    pub fn text(&self) -> Cow<'_, str> {
+        self.rope.slice(..).into()
+    }
+
+    /// Returns a specific line (0-indexed), with its trailing newline.
+    pub fn line(&self, line_idx: usize) -> BufferResult<Cow<'_, str>> {
+        if line_idx >= self.len_lines() {
+            return Err(BufferError::PositionOutOfBounds {
+                line: line_idx,
+                column: 0,
+            });
+        }
+        Ok(self.rope.line(line_idx).into())
+    }
+
+    /// Returns a slice of text by character range.
+    pub fn slice(&self, range: Range<usize>) -> BufferResult<Cow<'_, str>> {
+        if range.start > range.end || range.end > self.len_chars() {
+            return Err(BufferError::InvalidCharIndex(range.end));
+        }
+        Ok(self.rope.slice(range).into())
+    }
+
+    /// Returns the number of characters.
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    /// Returns the number of bytes.
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    /// Returns the number of lines.
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Converts a character index to a Position (line, column).
+    pub fn char_idx_to_position(&self, char_idx: usize) -> BufferResult<Position> {
+        if char_idx > self.len_chars() {
+            return Err(BufferError::InvalidCharIndex(char_idx));
+        }
+        let line = self.rope.char_to_line(char_idx);
+        Ok(Position::new(line, char_idx - self.rope.line_to_char(line)))
+    }
+
+    /// Converts a Position (line, column) to a character index.
+    pub fn position_to_char_idx(&self, pos: Position) -> BufferResult<usize> {
+        if pos.line >= self.len_lines() || pos.column > self.rope.line(pos.line).len_chars() {
+            return Err(BufferError::PositionOutOfBounds {
+                line: pos.line,
+                column: pos.column,
+            });
+        }
+        Ok(self.rope.line_to_char(pos.line) + pos.column)
+    }
+
+    /// Finds all matches of a compiled query, in document order.
+    pub fn search_all(&self, query: &SearchQuery) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        search::for_each_match(&self.rope, query, 0, |range| {
+            matches.push(range);
+            ControlFlow::Continue(())
+        });
+        matches
+    }
+
+    /// Returns the encoding the buffer saves with.
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Returns the line ending the buffer saves with.
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Returns the bytes a save of this version would write.
+    pub fn encode(&self) -> BufferResult<Vec<u8>> {
+        self.encoding.encode(&self.line_ending.apply(&self.text()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SearchOptions, TextBuffer};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_snapshot_is_isolated() {
+        assert_send_sync::<BufferSnapshot>();
+
+        let mut buffer = TextBuffer::from("one\ntwo\n");
+        let snapshot = buffer.snapshot();
+        buffer.insert(0, "zero\n").unwrap();
+
+        assert_eq!(snapshot.text(), "one\ntwo\n");
+        assert_eq!(snapshot.line(1).unwrap(), "two\n");
+        assert_eq!(
+            snapshot.char_idx_to_position(5).unwrap(),
+            Position::new(1, 1)
+        );
+        assert_eq!(buffer.snapshot().line(1).unwrap(), "one\n");
+
+        let query = SearchQuery::new("o", SearchOptions::default()).unwrap();
+        assert_eq!(snapshot.search_all(&query), [0..1, 6..7]);
+    }
+
+    #[test]
+    fn test_versions_only_grow() {
+        let mut buffer = TextBuffer::from("a\n");
+        let first = buffer.snapshot();
+        assert_eq!(first.version(), 0);
+
+        buffer.insert(2, "b").unwrap();
+        buffer.undo().unwrap();
+        // Same text as `first`, but results computed from it are stale
+        assert_eq!(buffer.text(), first.text());
+        assert!(first.is_stale(buffer.version()));
+        assert_eq!(buffer.version(), 2);
+
+        // Changing how the file is written also counts
+        let before = buffer.version();
+        buffer.set_line_ending(LineEnding::CrLf);
+        let snapshot = buffer.snapshot();
+        assert!(snapshot.version() > before);
+        assert_eq!(snapshot.encode().unwrap(), b"a\r\n");
+        assert!(!snapshot.is_stale(buffer.version()));
+    }
+}
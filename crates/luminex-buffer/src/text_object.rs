@@ -0,0 +1,486 @@
+//! Text objects: "the thing around the cursor".
+//!
+//! ## Learning: Selecting by Structure
+//!
+//! Vim's `ci(` ("change inside parentheses") and `da"` ("delete a
+//! quoted string") split into an operator and a *text object*. The
+//! object is a range found from the cursor by looking at the text's
+//! structure, in one of two extents:
+//!
+//! ```text
+//!   call(foo, "bar baz")        cursor on the `a` of `bar`
+//!   inner quote:       [bar baz]
+//!   around quote:     ["bar baz"]
+//!   inner parens:  [foo, "bar baz"]
+//!   around parens: (foo, "bar baz")  (the parens included)
+//! ```
+//!
+//! - Brackets nest, so finding the enclosing `(` means walking back
+//!   and skipping every `(...)` pair that is already closed. A bracket
+//!   preceded by an odd number of backslashes is escaped and ignored.
+//! - Quotes can't nest, and the same character opens and closes them,
+//!   so they're paired up left to right within the cursor's line.
+//! - Asking again for an inner or around bracket object that is
+//!   already selected selects the next one out.
+//!
+//! Ranges are char ranges; "around" a word, quote or paragraph takes
+//! the whitespace (or blank lines) after it, or before it if there is
+//! none after.
+
+use ropey::Rope;
+use std::ops::Range;
+
+use crate::bracket;
+use crate::buffer::TextBuffer;
+use crate::grapheme;
+use crate::word::{self, WordKind};
+
+/// What kind of structure to select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextObject {
+    /// A word of the given kind (or the whitespace between words)
+    Word(WordKind),
+    /// `( ... )`
+    Parens,
+    /// `[ ... ]`
+    Brackets,
+    /// `{ ... }`
+    Braces,
+    /// `< ... >`
+    AngleBrackets,
+    /// A string quoted with the given character, e.g. `"` or `'`
+    Quote(char),
+    /// Lines up to the next blank line
+    Paragraph,
+    /// The cursor's line
+    Line,
+    /// Lines indented at least as deep as the cursor's
+    IndentBlock,
+}
+
+/// How much of a text object to take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ObjectExtent {
+    /// Just the contents: no brackets, quotes or surrounding space
+    #[default]
+    Inner,
+    /// The contents plus delimiters and surrounding whitespace
+    Around,
+}
+
+/// Finds a text object around `range` (an empty range is a cursor).
+pub(crate) fn find(
+    buffer: &TextBuffer,
+    range: Range<usize>,
+    object: TextObject,
+    extent: ObjectExtent,
+) -> Option<Range<usize>> {
+    match object {
+        TextObject::Word(kind) => word_object(buffer, range.start, kind, extent),
+        TextObject::Parens => pair_object(buffer.rope(), range, '(', ')', extent),
+        TextObject::Brackets => pair_object(buffer.rope(), range, '[', ']', extent),
+        TextObject::Braces => pair_object(buffer.rope(), range, '{', '}', extent),
+        TextObject::AngleBrackets => pair_object(buffer.rope(), range, '<', '>', extent),
+        TextObject::Quote(quote) => quote_object(buffer, range.start, quote, extent),
+        TextObject::Paragraph => paragraph_object(buffer, range.start, extent),
+        TextObject::Line => line_object(buffer, range.start, extent),
+        TextObject::IndentBlock => indent_object(buffer, range.start, extent),
+    }
+}
+
+// ==================== Words ====================
+
+fn word_object(
+    buffer: &TextBuffer,
+    char_idx: usize,
+    kind: WordKind,
+    extent: ObjectExtent,
+) -> Option<Range<usize>> {
+    let pos = buffer.char_idx_to_position(char_idx).ok()?;
+    let line_start = char_idx - pos.column;
+    let chars: Vec<char> = buffer.line_content(pos.line).chars().collect();
+    let content: String = chars.iter().collect();
+    let inner = word::word_at(&content, pos.column, kind)?;
+
+    let range = match extent {
+        ObjectExtent::Inner => inner,
+        ObjectExtent::Around if chars[inner.start].is_whitespace() => {
+            // Whitespace plus the word after it
+            let end = word::next_word_end(&content, inner.end, kind).unwrap_or(inner.end);
+            inner.start..end
+        }
+        ObjectExtent::Around => with_surrounding_space(&chars, inner),
+    };
+    Some(line_start + range.start..line_start + range.end)
+}
+
+/// Extends `range` over the whitespace after it, or before it if there
+/// is none after (leaving indentation alone).
+fn with_surrounding_space(chars: &[char], range: Range<usize>) -> Range<usize> {
+    let after = chars[range.end..]
+        .iter()
+        .take_while(|c| c.is_whitespace())
+        .count();
+    if after > 0 {
+        return range.start..range.end + after;
+    }
+    let before = chars[..range.start]
+        .iter()
+        .rev()
+        .take_while(|c| c.is_whitespace())
+        .count();
+    if before == range.start {
+        range
+    } else {
+        range.start - before..range.end
+    }
+}
+
+// ==================== Brackets ====================
+
+fn pair_object(
+    rope: &Rope,
+    range: Range<usize>,
+    open: char,
+    close: char,
+    extent: ObjectExtent,
+) -> Option<Range<usize>> {
+    // A cursor on the opening bracket is inside its pair
+    let on_open =
+        range.is_empty() && range.start < rope.len_chars() && is_delimiter(rope, range.start, open);
+    let mut open_idx = if on_open {
+        range.start
+    } else {
+        bracket::enclosing_open(rope, range.start, open, close, &|_| true)?
+    };
+
+    loop {
+        let close_idx = bracket::matching_close(rope, open_idx, open, close, &|_| true)?;
+        let found = match extent {
+            ObjectExtent::Inner => inner_pair(rope, open_idx, close_idx),
+            ObjectExtent::Around => open_idx..close_idx + 1,
+        };
+        let contains = found.start <= range.start && range.end <= found.end;
+        if contains && (range.is_empty() || found != range) {
+            return Some(found);
+        }
+        // Already selected, or the selection reaches past it: go out a level
+        open_idx = bracket::enclosing_open(rope, open_idx, open, close, &|_| true)?;
+    }
+}
+
+/// The contents of a pair. When the brackets are on lines of their
+/// own (a block), the line breaks and the closing line's indentation
+/// are left out.
+fn inner_pair(rope: &Rope, open_idx: usize, close_idx: usize) -> Range<usize> {
+    let mut start = open_idx + 1;
+    let mut end = close_idx;
+    if rope.char(start) == '\n' {
+        start += 1;
+        let close_line_start = rope.line_to_char(rope.char_to_line(close_idx));
+        if rope
+            .slice(close_line_start..close_idx)
+            .chars()
+            .all(char::is_whitespace)
+        {
+            end = close_line_start.max(start);
+        }
+    }
+    start.min(end)..end
+}
+
+fn is_delimiter(rope: &Rope, char_idx: usize, delimiter: char) -> bool {
+    rope.char(char_idx) == delimiter && !bracket::is_escaped(rope, char_idx)
+}
+
+// ==================== Quotes ====================
+
+fn quote_object(
+    buffer: &TextBuffer,
+    char_idx: usize,
+    quote: char,
+    extent: ObjectExtent,
+) -> Option<Range<usize>> {
+    let pos = buffer.char_idx_to_position(char_idx).ok()?;
+    let line_start = char_idx - pos.column;
+    let chars: Vec<char> = buffer.line_content(pos.line).chars().collect();
+
+    let quotes: Vec<usize> = (0..chars.len())
+        .filter(|&i| {
+            chars[i] == quote
+                && chars[..i].iter().rev().take_while(|&&c| c == '\\').count() % 2 == 0
+        })
+        .collect();
+    // The string the cursor is in, or else the next one on the line
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| pos.column <= close)?;
+
+    let range = match extent {
+        ObjectExtent::Inner => open + 1..close,
+        ObjectExtent::Around => with_surrounding_space(&chars, open..close + 1),
+    };
+    Some(line_start + range.start..line_start + range.end)
+}
+
+// ==================== Lines ====================
+
+fn paragraph_object(
+    buffer: &TextBuffer,
+    char_idx: usize,
+    extent: ObjectExtent,
+) -> Option<Range<usize>> {
+    let line = buffer.char_idx_to_position(char_idx).ok()?.line;
+    let last = buffer.len_lines() - 1;
+    let blank = |l: usize| buffer.line_content(l).trim().is_empty();
+
+    // The run of lines that are all blank or all not blank
+    let kind = blank(line);
+    let run_start = |mut l: usize, kind: bool| {
+        while l > 0 && blank(l - 1) == kind {
+            l -= 1;
+        }
+        l
+    };
+    let run_end = |mut l: usize, kind: bool| {
+        while l < last && blank(l + 1) == kind {
+            l += 1;
+        }
+        l
+    };
+    let mut start = run_start(line, kind);
+    let mut end = run_end(line, kind);
+
+    if extent == ObjectExtent::Around {
+        if end < last {
+            // Take the following run (blank lines, or the next paragraph)
+            end = run_end(end + 1, !kind);
+        } else if !kind && start > 0 {
+            start = run_start(start - 1, true);
+        }
+    }
+    Some(lines_range(buffer, start, end))
+}
+
+fn line_object(buffer: &TextBuffer, char_idx: usize, extent: ObjectExtent) -> Option<Range<usize>> {
+    let line = buffer.char_idx_to_position(char_idx).ok()?.line;
+    match extent {
+        ObjectExtent::Around => Some(lines_range(buffer, line, line)),
+        ObjectExtent::Inner => {
+            let line_start = buffer.rope().line_to_char(line);
+            let content = buffer.line_content(line);
+            let len = content.chars().count();
+            let indent = content.chars().take_while(|c| c.is_whitespace()).count();
+            let trailing = content
+                .chars()
+                .rev()
+                .take_while(|c| c.is_whitespace())
+                .count();
+            let start = indent.min(len);
+            Some(line_start + start..line_start + (len - trailing).max(start))
+        }
+    }
+}
+
+fn indent_object(
+    buffer: &TextBuffer,
+    char_idx: usize,
+    extent: ObjectExtent,
+) -> Option<Range<usize>> {
+    let line = buffer.char_idx_to_position(char_idx).ok()?.line;
+    let last = buffer.len_lines() - 1;
+    let tab_width = buffer.config().tab_width;
+    let blank = |l: usize| buffer.line_content(l).trim().is_empty();
+    let indent = |l: usize| {
+        let content = buffer.line_content(l);
+        let leading: String = content.chars().take_while(|c| c.is_whitespace()).collect();
+        grapheme::display_width(&leading, tab_width)
+    };
+
+    // On a blank line, go by the next line with text (or the previous)
+    let anchor = (line..=last).chain((0..line).rev()).find(|&l| !blank(l))?;
+    let level = indent(anchor);
+    let in_block = |l: usize| blank(l) || indent(l) >= level;
+
+    let mut start = anchor;
+    while start > 0 && in_block(start - 1) {
+        start -= 1;
+    }
+    let mut end = anchor;
+    while end < last && in_block(end + 1) {
+        end += 1;
+    }
+    // Blank lines at the edges don't belong to the block
+    while blank(start) {
+        start += 1;
+    }
+    while blank(end) {
+        end -= 1;
+    }
+
+    if extent == ObjectExtent::Around && start > 0 {
+        // The line that opens the block, and a bracket that closes it
+        let header = start - 1;
+        start = header;
+        if let Some(next) = (end + 1..=last).find(|&l| !blank(l))
+            && indent(next) == indent(header)
+            && buffer
+                .line_content(next)
+                .trim_start()
+                .starts_with(['}', ')', ']'])
+        {
+            end = next;
+        }
+    }
+    Some(lines_range(buffer, start, end))
+}
+
+/// The char range of whole lines, including the last line's break.
+fn lines_range(buffer: &TextBuffer, start: usize, end: usize) -> Range<usize> {
+    let rope = buffer.rope();
+    let end = if end + 1 < rope.len_lines() {
+        rope.line_to_char(end + 1)
+    } else {
+        rope.len_chars()
+    };
+    rope.line_to_char(start)..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Finds the object at the `|` in `text` and returns its text.
+    fn select(text: &str, object: TextObject, extent: ObjectExtent) -> Option<String> {
+        let cursor = text.chars().position(|c| c == '|').unwrap();
+        let buffer = TextBuffer::from(text.replacen('|', "", 1));
+        let range = buffer.text_object(cursor..cursor, object, extent)?;
+        Some(buffer.slice(range).unwrap().into_owned())
+    }
+
+    use ObjectExtent::{Around, Inner};
+
+    #[test]
+    fn test_words() {
+        let word = TextObject::Word(WordKind::Word);
+        assert_eq!(select("let fo|o = 1;", word, Inner).unwrap(), "foo");
+        assert_eq!(select("let fo|o = 1;", word, Around).unwrap(), "foo ");
+        // No space after: takes the space before
+        assert_eq!(select("call(a, b|c)", word, Around).unwrap(), " bc");
+        assert_eq!(select("x = fo|o", word, Around).unwrap(), " foo");
+        assert_eq!(select("a |  b", word, Around).unwrap(), "   b");
+    }
+
+    #[test]
+    fn test_brackets_nest_and_skip_escapes() {
+        let text = "f(a, g(b|), \"\\)\" c)";
+        assert_eq!(select(text, TextObject::Parens, Inner).unwrap(), "b");
+        assert_eq!(select(text, TextObject::Parens, Around).unwrap(), "(b)");
+        let text = "f(a, g(b), \\) c|)";
+        assert_eq!(
+            select(text, TextObject::Parens, Inner).unwrap(),
+            "a, g(b), \\) c"
+        );
+        // On the bracket itself
+        assert_eq!(
+            select("x[|0][1]", TextObject::Brackets, Inner).unwrap(),
+            "0"
+        );
+        assert_eq!(
+            select("x[0|][1]", TextObject::Brackets, Around).unwrap(),
+            "[0]"
+        );
+        assert_eq!(
+            select("Vec<Option<|T>>", TextObject::AngleBrackets, Inner).unwrap(),
+            "T"
+        );
+        assert_eq!(select("no | pair", TextObject::Parens, Inner), None);
+    }
+
+    #[test]
+    fn test_block_braces() {
+        let text = "fn f() {\n    let x = 1;|\n    x\n}\n";
+        assert_eq!(
+            select(text, TextObject::Braces, Inner).unwrap(),
+            "    let x = 1;\n    x\n"
+        );
+        assert_eq!(
+            select(text, TextObject::Braces, Around).unwrap(),
+            "{\n    let x = 1;\n    x\n}"
+        );
+        assert_eq!(select("{|}", TextObject::Braces, Inner).unwrap(), "");
+    }
+
+    #[test]
+    fn test_repeat_selects_outer_pair() {
+        let buffer = TextBuffer::from("((a) b)");
+        let inner = buffer.text_object(2..2, TextObject::Parens, Inner).unwrap();
+        assert_eq!(inner, 2..3);
+        let outer = buffer
+            .text_object(inner, TextObject::Parens, Inner)
+            .unwrap();
+        assert_eq!(outer, 1..6);
+        assert_eq!(buffer.text_object(outer, TextObject::Parens, Inner), None);
+    }
+
+    #[test]
+    fn test_quotes() {
+        let quote = TextObject::Quote('"');
+        let text = r#"say("hi \"you\"", "b|ye") now"#;
+        assert_eq!(select(text, quote, Inner).unwrap(), "bye");
+        assert_eq!(select(text, quote, Around).unwrap(), " \"bye\"");
+        let text = r#"x = "a |b" + 1"#;
+        assert_eq!(select(text, quote, Around).unwrap(), "\"a b\" ");
+        assert_eq!(
+            select(r#"s|ay("hi \"you\"")"#, quote, Inner).unwrap(),
+            r#"hi \"you\""#
+        );
+        assert_eq!(select(r#"s|ay('hi')"#, quote, Inner), None);
+    }
+
+    #[test]
+    fn test_paragraphs_and_lines() {
+        let text = "one\ntw|o\n\n\nthree\n";
+        assert_eq!(
+            select(text, TextObject::Paragraph, Inner).unwrap(),
+            "one\ntwo\n"
+        );
+        assert_eq!(
+            select(text, TextObject::Paragraph, Around).unwrap(),
+            "one\ntwo\n\n\n"
+        );
+        // The last paragraph takes the blank lines before it
+        assert_eq!(
+            select("a\n\nb|", TextObject::Paragraph, Around).unwrap(),
+            "\nb"
+        );
+
+        assert_eq!(
+            select("  let |x = 1;  \nnext", TextObject::Line, Inner).unwrap(),
+            "let x = 1;"
+        );
+        assert_eq!(
+            select("  let |x = 1;  \nnext", TextObject::Line, Around).unwrap(),
+            "  let x = 1;  \n"
+        );
+    }
+
+    #[test]
+    fn test_indent_blocks() {
+        let text = "fn f() {\n    if x {\n        a();\n\n        b|();\n    }\n}\n";
+        assert_eq!(
+            select(text, TextObject::IndentBlock, Inner).unwrap(),
+            "        a();\n\n        b();\n"
+        );
+        assert_eq!(
+            select(text, TextObject::IndentBlock, Around).unwrap(),
+            "    if x {\n        a();\n\n        b();\n    }\n"
+        );
+        let python = "def f():\n    retur|n 1\n\nx = 2\n";
+        assert_eq!(
+            select(python, TextObject::IndentBlock, Around).unwrap(),
+            "def f():\n    return 1\n"
+        );
+    }
+}
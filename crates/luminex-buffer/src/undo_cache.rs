@@ -0,0 +1,255 @@
+//! Persistent undo history.
+//!
+//! ## Learning: Caches Keyed by Content
+//!
+//! Undo steps are positions into one exact text. If the file changed
+//! while the editor was closed (a `git checkout`, another editor),
+//! replaying old steps would corrupt it. So each entry stores a hash
+//! of the file's bytes as they were saved, and is only used when the
+//! file on disk still hashes the same.
+//!
+//! Entries live in one directory, one JSON file per path. The file
+//! name is a hash of the path, so nothing about the path has to be
+//! escaped. Old entries are pruned by age and by total size.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::history::History;
+use crate::{BufferError, BufferResult};
+
+/// Where undo history is kept between sessions, and how much of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+}
+
+/// One cached history, as stored on disk.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// The file the history belongs to (guards against hash collisions)
+    path: PathBuf,
+    /// Hash of the file's bytes when the history was stored
+    content_hash: u64,
+    history: History,
+}
+
+impl UndoCache {
+    /// Default cap on the total size of the cache directory.
+    pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+    /// Default age after which entries are dropped.
+    pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+    /// Creates a cache in the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: Self::DEFAULT_MAX_BYTES,
+            max_age: Self::DEFAULT_MAX_AGE,
+        }
+    }
+
+    /// The standard location, `<config dir>/luminex/undo`.
+    ///
+    /// Returns `None` if the platform has no config directory.
+    pub fn default_location() -> Option<Self> {
+        let config_dir = dirs::config_dir()?;
+        Some(Self::new(config_dir.join("luminex").join("undo")))
+    }
+
+    /// Sets the cap on the total size of all entries.
+    pub fn with_max_bytes(self, max_bytes: u64) -> Self {
+        Self { max_bytes, ..self }
+    }
+
+    /// Sets the age after which entries are dropped.
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        Self { max_age, ..self }
+    }
+
+    /// Returns the cache directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Loads the history stored for `path`.
+    ///
+    /// `content` is the file as just read from disk. Returns `None` if
+    /// there is no entry, or if it was stored for different content.
+    pub fn load(&self, path: &Path, content: &[u8]) -> Option<History> {
+        self.load_hashed(path, content_hash(content))
+    }
+
+    /// Like [`load`](Self::load), for content hashed while it was read
+    /// (see [`extend_hash`]).
+    pub(crate) fn load_hashed(&self, path: &Path, content_hash: u64) -> Option<History> {
+        let path = Self::normalize(path);
+        let json = std::fs::read(self.entry_path(&path)).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&json).ok()?;
+        (entry.path == path && entry.content_hash == content_hash).then_some(entry.history)
+    }
+
+    /// Stores the history for `path`, whose file now holds `content`.
+    ///
+    /// An empty history removes the entry instead.
+    pub fn store(&self, path: &Path, content: &[u8], history: &History) -> BufferResult<()> {
+        let path = Self::normalize(path);
+        let entry_path = self.entry_path(&path);
+        if !history.can_undo() && !history.can_redo() {
+            return match std::fs::remove_file(&entry_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+
+        let entry = CacheEntry {
+            content_hash: content_hash(content),
+            path,
+            history: history.clone(),
+        };
+        let json = serde_json::to_vec(&entry).map_err(|e| BufferError::UndoCache(e.to_string()))?;
+        if json.len() as u64 > self.max_bytes {
+            // Would be pruned right away
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&self.dir)?;
+        let temp_path = entry_path.with_extension("tmp");
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, &entry_path)?;
+
+        self.prune()
+    }
+
+    /// Removes entries past the maximum age, then the oldest entries
+    /// until the cache fits the size cap.
+    pub fn prune(&self) -> BufferResult<()> {
+        let now = SystemTime::now();
+        let mut entries = Vec::new();
+        for dir_entry in std::fs::read_dir(&self.dir)? {
+            let dir_entry = dir_entry?;
+            let path = dir_entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let metadata = dir_entry.metadata()?;
+            let modified = metadata.modified().unwrap_or(now);
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age > self.max_age {
+                std::fs::remove_file(&path)?;
+            } else {
+                entries.push((modified, metadata.len(), path));
+            }
+        }
+
+        // Keep the newest entries that fit
+        entries.sort_by_key(|&(modified, _, _)| std::cmp::Reverse(modified));
+        let mut total = 0u64;
+        for (_, len, path) in entries {
+            total += len;
+            if total > self.max_bytes {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        let key = content_hash(path.to_string_lossy().as_bytes());
+        self.dir.join(format!("{:016x}.json", key))
+    }
+
+    /// Makes the same file map to the same entry however it was opened.
+    fn normalize(path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+/// 64-bit FNV-1a hash.
+///
+/// Unlike `std`'s `DefaultHasher`, the result is stable across Rust
+/// versions, which matters for values written to disk.
+fn content_hash(bytes: &[u8]) -> u64 {
+    extend_hash(HASH_SEED, bytes)
+}
+
+/// Starting value for [`extend_hash`].
+pub(crate) const HASH_SEED: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Feeds more bytes into a content hash, so content read in chunks
+/// hashes the same as when read at once.
+pub(crate) fn extend_hash(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Edit;
+
+    fn history_with_edit() -> History {
+        let mut history = History::new(100);
+        history.push(Edit::insert(0, "hello"));
+        history
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "hello").unwrap();
+        let cache = UndoCache::new(dir.path().join("undo"));
+
+        cache.store(&file, b"hello", &history_with_edit()).unwrap();
+        let history = cache.load(&file, b"hello").unwrap();
+        assert_eq!(history.undo_count(), 1);
+
+        // Different content on disk: the history no longer applies
+        assert!(cache.load(&file, b"hello!").is_none());
+
+        // Storing an empty history drops the entry
+        cache.store(&file, b"hello", &History::new(100)).unwrap();
+        assert!(cache.load(&file, b"hello").is_none());
+    }
+
+    #[test]
+    fn test_prune_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = UndoCache::new(dir.path()).with_max_bytes(600);
+        let history = history_with_edit();
+
+        for name in ["a", "b", "c", "d"] {
+            let file = dir.path().join(name);
+            std::fs::write(&file, "hello").unwrap();
+            cache.store(&file, b"hello", &history).unwrap();
+            // Make sure modification times differ
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let entries = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .path()
+                    .extension()
+                    .is_some_and(|ext| ext == "json")
+            })
+            .count();
+        assert!(entries < 4);
+        // The newest entry survives
+        assert!(cache.load(&dir.path().join("d"), b"hello").is_some());
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
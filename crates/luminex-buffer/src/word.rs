@@ -0,0 +1,269 @@
+//! Word boundaries for word-wise movement and deletion.
+//!
+//! ## Learning: Three Kinds of Words
+//!
+//! "Move by word" means different things depending on how far you
+//! want to go:
+//! - a **word** is a run of letters, digits and `_`, or a run of
+//!   punctuation. Unicode's word-break rules (UAX #29, via
+//!   `unicode-segmentation`) decide where words split, so each CJK
+//!   ideograph is its own word and combining marks stay attached.
+//! - a **WORD** (as in Vim) is anything between whitespace:
+//!   `self.buffer.len()` is one WORD.
+//! - a **subword** is a piece of an identifier: `parseHttpRequest`
+//!   has `parse`, `Http` and `Request`; `line_to_char` has `line`,
+//!   `to` and `char`. Underscores between them are skipped like
+//!   spaces.
+//!
+//! ```text
+//!   HTTPServer::new_with_config
+//!   word:    [HTTPServer][::][new_with_config]
+//!   subword: [HTTP][Server][::][new] [with] [config]
+//! ```
+//!
+//! Everything here works on one line's content, in char columns.
+
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Which notion of "word" a motion uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WordKind {
+    /// Letters, digits and `_`, or a run of punctuation.
+    #[default]
+    Word,
+    /// Anything between whitespace.
+    BigWord,
+    /// Parts of camelCase and snake_case identifiers.
+    Subword,
+}
+
+/// What a run of text is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Space,
+    Word,
+    Punct,
+}
+
+impl Class {
+    fn of(c: char, kind: WordKind) -> Self {
+        if c.is_whitespace() {
+            Class::Space
+        } else if kind == WordKind::BigWord {
+            Class::Word
+        } else if c == '_' && kind == WordKind::Subword {
+            // Separates subwords like a space
+            Class::Space
+        } else if c.is_alphanumeric() || c == '_' {
+            Class::Word
+        } else {
+            Class::Punct
+        }
+    }
+}
+
+/// One grapheme, reduced to what boundary detection needs.
+struct Unit {
+    /// Char column of the grapheme
+    column: usize,
+    /// Length in chars
+    len: usize,
+    /// First char; combining marks after it don't matter
+    base: char,
+    /// A UAX #29 word boundary comes right before this grapheme
+    word_start: bool,
+}
+
+/// Returns the end column of the first word ending after `column`.
+pub fn next_word_end(line: &str, column: usize, kind: WordKind) -> Option<usize> {
+    runs(line, kind)
+        .into_iter()
+        .filter(|(_, class)| *class != Class::Space)
+        .map(|(range, _)| range.end)
+        .find(|&end| end > column)
+}
+
+/// Returns the start column of the last word starting before `column`.
+pub fn prev_word_start(line: &str, column: usize, kind: WordKind) -> Option<usize> {
+    runs(line, kind)
+        .into_iter()
+        .filter(|(_, class)| *class != Class::Space)
+        .map(|(range, _)| range.start)
+        .take_while(|&start| start < column)
+        .last()
+}
+
+/// Returns the word at `column`.
+///
+/// At the edge between a word and something else the word wins, so a
+/// cursor just after `foo` in `foo(` selects `foo`. Whitespace and
+/// punctuation runs are returned when there is no word to prefer.
+pub fn word_at(line: &str, column: usize, kind: WordKind) -> Option<Range<usize>> {
+    let runs = runs(line, kind);
+    let after = runs.iter().find(|(range, _)| range.contains(&column));
+    let before = runs.iter().find(|(range, _)| range.end == column);
+    let run = match (after, before) {
+        (Some(after), _) if after.1 == Class::Word => after,
+        (_, Some(before)) if before.1 == Class::Word => before,
+        (Some(after), _) => after,
+        (None, Some(before)) => before,
+        (None, None) => return None,
+    };
+    Some(run.0.clone())
+}
+
+/// Splits a line into runs of one class each.
+fn runs(line: &str, kind: WordKind) -> Vec<(Range<usize>, Class)> {
+    let mut units = Vec::new();
+    let mut column = 0;
+    for segment in line.split_word_bounds() {
+        for (i, grapheme) in segment.graphemes(true).enumerate() {
+            let len = grapheme.chars().count();
+            units.push(Unit {
+                column,
+                len,
+                base: grapheme.chars().next().unwrap_or(' '),
+                word_start: i == 0,
+            });
+            column += len;
+        }
+    }
+
+    let mut runs: Vec<(Range<usize>, Class)> = Vec::new();
+    for (i, unit) in units.iter().enumerate() {
+        let class = Class::of(unit.base, kind);
+        let extends = match runs.last() {
+            Some((_, last)) if *last == class => match (class, kind) {
+                (Class::Space | Class::Punct, _) | (Class::Word, WordKind::BigWord) => true,
+                (Class::Word, WordKind::Word) => !unit.word_start,
+                (Class::Word, WordKind::Subword) => {
+                    let prev = units[i - 1].base;
+                    let next = units.get(i + 1).map(|u| u.base);
+                    !unit.word_start && !is_case_boundary(prev, unit.base, next)
+                }
+            },
+            _ => false,
+        };
+        match runs.last_mut() {
+            Some((range, _)) if extends => range.end += unit.len,
+            _ => runs.push((unit.column..unit.column + unit.len, class)),
+        }
+    }
+    runs
+}
+
+/// True if a new subword starts at `c`.
+///
+/// `fooBar` splits before the `B`; `HTTPServer` splits before the `S`,
+/// the last capital of an acronym that is followed by a lowercase letter.
+fn is_case_boundary(prev: char, c: char, next: Option<char>) -> bool {
+    if !c.is_uppercase() {
+        return false;
+    }
+    if prev.is_lowercase() || prev.is_numeric() {
+        return true;
+    }
+    prev.is_uppercase() && next.is_some_and(char::is_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str, kind: WordKind) -> Vec<&str> {
+        runs(line, kind)
+            .into_iter()
+            .filter(|(_, class)| *class != Class::Space)
+            .map(|(range, _)| {
+                let start = line
+                    .char_indices()
+                    .nth(range.start)
+                    .map_or(line.len(), |(i, _)| i);
+                let end = line
+                    .char_indices()
+                    .nth(range.end)
+                    .map_or(line.len(), |(i, _)| i);
+                &line[start..end]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_word_kinds() {
+        let line = "let x = self.buffer.len_chars();";
+        assert_eq!(
+            words(line, WordKind::Word),
+            [
+                "let",
+                "x",
+                "=",
+                "self",
+                ".",
+                "buffer",
+                ".",
+                "len_chars",
+                "();"
+            ]
+        );
+        assert_eq!(
+            words(line, WordKind::BigWord),
+            ["let", "x", "=", "self.buffer.len_chars();"]
+        );
+        assert_eq!(
+            words(line, WordKind::Subword),
+            [
+                "let", "x", "=", "self", ".", "buffer", ".", "len", "chars", "();"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subwords() {
+        assert_eq!(
+            words("parseHttpRequest", WordKind::Subword),
+            ["parse", "Http", "Request"]
+        );
+        assert_eq!(words("HTTPServer", WordKind::Subword), ["HTTP", "Server"]);
+        assert_eq!(words("utf8Decoder", WordKind::Subword), ["utf8", "Decoder"]);
+        assert_eq!(words("__init__", WordKind::Subword), ["init"]);
+        assert_eq!(words("MAX_SIZE", WordKind::Subword), ["MAX", "SIZE"]);
+    }
+
+    #[test]
+    fn test_unicode_words() {
+        // Combining accent stays in its word; ideographs split
+        assert_eq!(
+            words("cafe\u{301} 日本", WordKind::Word),
+            ["cafe\u{301}", "日", "本"]
+        );
+        assert_eq!(words("naïveCafé", WordKind::Subword), ["naïve", "Café"]);
+    }
+
+    #[test]
+    fn test_motions() {
+        let line = "  foo.bar  baz";
+        assert_eq!(next_word_end(line, 0, WordKind::Word), Some(5));
+        assert_eq!(next_word_end(line, 5, WordKind::Word), Some(6));
+        assert_eq!(next_word_end(line, 9, WordKind::Word), Some(14));
+        assert_eq!(next_word_end(line, 14, WordKind::Word), None);
+        assert_eq!(next_word_end(line, 0, WordKind::BigWord), Some(9));
+
+        assert_eq!(prev_word_start(line, 14, WordKind::Word), Some(11));
+        assert_eq!(prev_word_start(line, 11, WordKind::Word), Some(6));
+        assert_eq!(prev_word_start(line, 2, WordKind::Word), None);
+    }
+
+    #[test]
+    fn test_word_at() {
+        let line = "foo(bar_baz)";
+        assert_eq!(word_at(line, 1, WordKind::Word), Some(0..3));
+        // Just after a word still selects it
+        assert_eq!(word_at(line, 3, WordKind::Word), Some(0..3));
+        assert_eq!(word_at(line, 6, WordKind::Word), Some(4..11));
+        assert_eq!(word_at(line, 6, WordKind::Subword), Some(4..7));
+        assert_eq!(word_at("a  b", 2, WordKind::Word), Some(1..3));
+        assert_eq!(word_at("", 0, WordKind::Word), None);
+    }
+}
@@ -12,8 +12,11 @@
 //! We use an enum for built-in commands (exhaustive, no allocation)
 //! and trait objects for plugin commands (extensible, heap allocated).
 
-use crate::editor::Editor;
 use crate::CoreResult;
+use crate::document::{
+    BlockSelection, LineEnding, LineSort, ObjectExtent, TextObject, UndoTarget, WordKind,
+};
+use crate::editor::Editor;
 use std::collections::HashMap;
 
 /// Built-in editor commands.
@@ -27,52 +30,142 @@ use std::collections::HashMap;
 pub enum Command {
     // File commands
     NewFile,
-    OpenFile { path: Option<String> },
+    OpenFile {
+        path: Option<String>,
+    },
     Save,
-    SaveAs { path: Option<String> },
+    SaveAs {
+        path: Option<String>,
+    },
     CloseFile,
     CloseAll,
     Quit,
+    ReopenWithEncoding {
+        encoding: Option<String>,
+    },
+    Reload,
+    ConvertEncoding {
+        encoding: Option<String>,
+    },
+    ConvertLineEnding {
+        line_ending: LineEnding,
+    },
 
     // Edit commands
     Undo,
     Redo,
+    UndoEarlier {
+        spec: String,
+    },
+    UndoLater {
+        spec: String,
+    },
+    NextUndoBranch,
+    PreviousUndoBranch,
+    SwitchUndoBranch {
+        branch: Option<usize>,
+    },
     Cut,
     Copy,
     Paste,
     SelectAll,
+    AddCursorAbove,
+    AddCursorBelow,
+    CollapseCursors,
     Delete,
+    DeleteWordBackward,
+    DeleteWordForward,
     DeleteLine,
     DuplicateLine,
+    MoveLinesUp,
+    MoveLinesDown,
+    JoinLines,
+    SortLines {
+        sort: LineSort,
+    },
+    UniqueLines,
+    ReverseLines,
+    ShuffleLines,
+    InsertTab,
+    Indent,
+    Outdent,
 
     // Cursor movement
-    MoveUp { count: usize },
-    MoveDown { count: usize },
-    MoveLeft { count: usize },
-    MoveRight { count: usize },
+    MoveUp {
+        count: usize,
+    },
+    MoveDown {
+        count: usize,
+    },
+    MoveLeft {
+        count: usize,
+    },
+    MoveRight {
+        count: usize,
+    },
     MoveToLineStart,
     MoveToLineEnd,
     MoveToFileStart,
     MoveToFileEnd,
     MoveWordLeft,
     MoveWordRight,
+    MoveSubwordLeft,
+    MoveSubwordRight,
     PageUp,
     PageDown,
 
     // Selection
-    SelectUp { count: usize },
-    SelectDown { count: usize },
-    SelectLeft { count: usize },
-    SelectRight { count: usize },
+    SelectUp {
+        count: usize,
+    },
+    SelectDown {
+        count: usize,
+    },
+    SelectLeft {
+        count: usize,
+    },
+    SelectRight {
+        count: usize,
+    },
     SelectLine,
     SelectWord,
+    SelectTextObject {
+        object: TextObject,
+        extent: ObjectExtent,
+    },
+    JumpToMatchingBracket,
+    SelectToMatchingBracket,
+    SetMark {
+        name: char,
+    },
+    GotoMark {
+        name: char,
+    },
+    JumpBack,
+    JumpForward,
+    SelectBlock {
+        block: BlockSelection,
+    },
+    BlockSelectUp,
+    BlockSelectDown,
+    BlockSelectLeft,
+    BlockSelectRight,
 
     // Search
-    Find,
+    Find {
+        pattern: Option<String>,
+    },
     FindNext,
     FindPrevious,
-    Replace,
-    GotoLine,
+    Replace {
+        replacement: Option<String>,
+    },
+    GotoLine {
+        line: Option<usize>,
+    },
+    AddNextOccurrence,
+    SkipOccurrence,
+    SelectAllOccurrences,
 
     // View
     ZoomIn,
@@ -90,7 +183,10 @@ pub enum Command {
     EnterCommandMode,
 
     // Custom command (name, arguments)
-    Custom { name: String, args: Vec<String> },
+    Custom {
+        name: String,
+        args: Vec<String>,
+    },
 }
 
 impl Command {
@@ -104,15 +200,39 @@ impl Command {
             Command::CloseFile => "Close File",
             Command::CloseAll => "Close All",
             Command::Quit => "Quit",
+            Command::ReopenWithEncoding { .. } => "Reopen with Encoding",
+            Command::Reload => "Reload from Disk",
+            Command::ConvertEncoding { .. } => "Save with Encoding",
+            Command::ConvertLineEnding { .. } => "Change Line Ending",
             Command::Undo => "Undo",
             Command::Redo => "Redo",
+            Command::UndoEarlier { .. } => "Go Back in Undo History",
+            Command::UndoLater { .. } => "Go Forward in Undo History",
+            Command::NextUndoBranch => "Next Undo Branch",
+            Command::PreviousUndoBranch => "Previous Undo Branch",
+            Command::SwitchUndoBranch { .. } => "Switch Undo Branch",
             Command::Cut => "Cut",
             Command::Copy => "Copy",
             Command::Paste => "Paste",
             Command::SelectAll => "Select All",
+            Command::AddCursorAbove => "Add Cursor Above",
+            Command::AddCursorBelow => "Add Cursor Below",
+            Command::CollapseCursors => "Collapse Cursors",
             Command::Delete => "Delete",
+            Command::DeleteWordBackward => "Delete Word Backward",
+            Command::DeleteWordForward => "Delete Word Forward",
             Command::DeleteLine => "Delete Line",
             Command::DuplicateLine => "Duplicate Line",
+            Command::MoveLinesUp => "Move Lines Up",
+            Command::MoveLinesDown => "Move Lines Down",
+            Command::JoinLines => "Join Lines",
+            Command::SortLines { .. } => "Sort Lines",
+            Command::UniqueLines => "Unique Lines",
+            Command::ReverseLines => "Reverse Lines",
+            Command::ShuffleLines => "Shuffle Lines",
+            Command::InsertTab => "Insert Tab",
+            Command::Indent => "Indent",
+            Command::Outdent => "Outdent",
             Command::MoveUp { .. } => "Move Up",
             Command::MoveDown { .. } => "Move Down",
             Command::MoveLeft { .. } => "Move Left",
@@ -123,6 +243,8 @@ impl Command {
             Command::MoveToFileEnd => "Move to File End",
             Command::MoveWordLeft => "Move Word Left",
             Command::MoveWordRight => "Move Word Right",
+            Command::MoveSubwordLeft => "Move Subword Left",
+            Command::MoveSubwordRight => "Move Subword Right",
             Command::PageUp => "Page Up",
             Command::PageDown => "Page Down",
             Command::SelectUp { .. } => "Select Up",
@@ -131,11 +253,26 @@ impl Command {
             Command::SelectRight { .. } => "Select Right",
             Command::SelectLine => "Select Line",
             Command::SelectWord => "Select Word",
-            Command::Find => "Find",
+            Command::SelectTextObject { .. } => "Select Text Object",
+            Command::JumpToMatchingBracket => "Go to Matching Bracket",
+            Command::SelectToMatchingBracket => "Select to Matching Bracket",
+            Command::SetMark { .. } => "Set Mark",
+            Command::GotoMark { .. } => "Go to Mark",
+            Command::JumpBack => "Go Back",
+            Command::JumpForward => "Go Forward",
+            Command::SelectBlock { .. } => "Select Block",
+            Command::BlockSelectUp => "Block Select Up",
+            Command::BlockSelectDown => "Block Select Down",
+            Command::BlockSelectLeft => "Block Select Left",
+            Command::BlockSelectRight => "Block Select Right",
+            Command::Find { .. } => "Find",
             Command::FindNext => "Find Next",
             Command::FindPrevious => "Find Previous",
-            Command::Replace => "Replace",
-            Command::GotoLine => "Go to Line",
+            Command::Replace { .. } => "Replace",
+            Command::GotoLine { .. } => "Go to Line",
+            Command::AddNextOccurrence => "Add Next Occurrence",
+            Command::SkipOccurrence => "Skip Occurrence",
+            Command::SelectAllOccurrences => "Select All Occurrences",
             Command::ZoomIn => "Zoom In",
             Command::ZoomOut => "Zoom Out",
             Command::ZoomReset => "Zoom Reset",
@@ -232,14 +369,88 @@ impl CommandRegistry {
                 ctx.editor.quit();
                 Ok(())
             }
+            Command::ReopenWithEncoding { encoding } => {
+                if let Some(e) = encoding {
+                    ctx.editor.reopen_with_encoding(e)?;
+                }
+                // If no encoding, UI should show the encoding picker
+                Ok(())
+            }
+            Command::Reload => ctx.editor.reload_current().map(|_| ()),
+            Command::ConvertEncoding { encoding } => {
+                if let Some(e) = encoding {
+                    ctx.editor.convert_encoding(e)?;
+                }
+                Ok(())
+            }
+            Command::ConvertLineEnding { line_ending } => {
+                ctx.editor.convert_line_ending(*line_ending)
+            }
 
             // Edit commands
             Command::Undo => ctx.editor.undo(),
             Command::Redo => ctx.editor.redo(),
+            Command::UndoEarlier { spec } => {
+                let target = UndoTarget::earlier(spec).ok_or_else(|| {
+                    crate::CoreError::InvalidOperation(format!("Invalid undo step: {}", spec))
+                })?;
+                ctx.editor.undo_jump(target)
+            }
+            Command::UndoLater { spec } => {
+                let target = UndoTarget::later(spec).ok_or_else(|| {
+                    crate::CoreError::InvalidOperation(format!("Invalid undo step: {}", spec))
+                })?;
+                ctx.editor.undo_jump(target)
+            }
+            Command::NextUndoBranch => ctx.editor.undo_jump(UndoTarget::NextBranch),
+            Command::PreviousUndoBranch => ctx.editor.undo_jump(UndoTarget::PreviousBranch),
+            Command::SwitchUndoBranch { branch } => {
+                if let Some(id) = branch {
+                    ctx.editor.undo_jump(UndoTarget::Branch(*id))?;
+                }
+                // If no branch, UI should list Editor::undo_branches
+                Ok(())
+            }
             Command::Cut => ctx.editor.cut(),
             Command::Copy => ctx.editor.copy(),
             Command::Paste => ctx.editor.paste(),
             Command::SelectAll => ctx.editor.select_all(),
+            Command::SelectLine => ctx
+                .editor
+                .select_text_object(TextObject::Line, ObjectExtent::Around)
+                .map(|_| ()),
+            Command::SelectWord => ctx.editor.select_word(),
+            Command::SelectTextObject { object, extent } => {
+                ctx.editor.select_text_object(*object, *extent).map(|_| ())
+            }
+            Command::JumpToMatchingBracket => ctx.editor.jump_to_matching_bracket().map(|_| ()),
+            Command::SelectToMatchingBracket => ctx.editor.select_to_matching_bracket().map(|_| ()),
+            Command::SetMark { name } => ctx.editor.set_mark(*name),
+            Command::GotoMark { name } => ctx.editor.goto_mark(*name).map(|_| ()),
+            Command::JumpBack => ctx.editor.jump_back().map(|_| ()),
+            Command::JumpForward => ctx.editor.jump_forward().map(|_| ()),
+            Command::SelectBlock { block } => ctx.editor.select_block(*block),
+            Command::BlockSelectUp => ctx.editor.extend_block(-1, 0),
+            Command::BlockSelectDown => ctx.editor.extend_block(1, 0),
+            Command::BlockSelectLeft => ctx.editor.extend_block(0, -1),
+            Command::BlockSelectRight => ctx.editor.extend_block(0, 1),
+            Command::AddCursorAbove => ctx.editor.add_cursor_above(),
+            Command::AddCursorBelow => ctx.editor.add_cursor_below(),
+            Command::CollapseCursors => ctx.editor.collapse_cursors(),
+            Command::DeleteWordBackward => ctx.editor.delete_word_backward(WordKind::Word),
+            Command::DeleteWordForward => ctx.editor.delete_word_forward(WordKind::Word),
+            Command::InsertTab => ctx.editor.insert_tab(),
+            Command::DeleteLine => ctx.editor.delete_lines(),
+            Command::DuplicateLine => ctx.editor.duplicate_lines(),
+            Command::MoveLinesUp => ctx.editor.move_lines_up(),
+            Command::MoveLinesDown => ctx.editor.move_lines_down(),
+            Command::JoinLines => ctx.editor.join_lines(),
+            Command::SortLines { sort } => ctx.editor.sort_lines(*sort),
+            Command::UniqueLines => ctx.editor.unique_lines(),
+            Command::ReverseLines => ctx.editor.reverse_lines(),
+            Command::ShuffleLines => ctx.editor.shuffle_lines(),
+            Command::Indent => ctx.editor.indent(),
+            Command::Outdent => ctx.editor.outdent(),
 
             // Movement commands
             Command::MoveUp { count } => ctx.editor.move_up(*count),
@@ -248,6 +459,37 @@ impl CommandRegistry {
             Command::MoveRight { count } => ctx.editor.move_right(*count),
             Command::MoveToLineStart => ctx.editor.move_to_line_start(),
             Command::MoveToLineEnd => ctx.editor.move_to_line_end(),
+            Command::MoveWordLeft => ctx.editor.move_word_left(WordKind::Word),
+            Command::MoveWordRight => ctx.editor.move_word_right(WordKind::Word),
+            Command::MoveSubwordLeft => ctx.editor.move_word_left(WordKind::Subword),
+            Command::MoveSubwordRight => ctx.editor.move_word_right(WordKind::Subword),
+
+            // Search commands
+            Command::Find { pattern } => {
+                if let Some(p) = pattern {
+                    ctx.editor.find(p)?;
+                }
+                // If no pattern, UI should show the find bar
+                Ok(())
+            }
+            Command::FindNext => ctx.editor.find_next(),
+            Command::FindPrevious => ctx.editor.find_previous(),
+            Command::GotoLine { line } => {
+                if let Some(line) = line {
+                    ctx.editor.goto_line(*line)?;
+                }
+                // If no line, UI should show the go to line prompt
+                Ok(())
+            }
+            Command::AddNextOccurrence => ctx.editor.add_next_occurrence(),
+            Command::SkipOccurrence => ctx.editor.skip_occurrence(),
+            Command::SelectAllOccurrences => ctx.editor.select_all_occurrences().map(|_| ()),
+            Command::Replace { replacement } => {
+                if let Some(r) = replacement {
+                    ctx.editor.replace_all(r)?;
+                }
+                Ok(())
+            }
 
             // Mode commands
             Command::EnterInsertMode => {
@@ -291,6 +533,9 @@ impl Default for CommandRegistry {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::document::Reload;
+    use crate::event::EditorEvent;
+    use luminex_buffer::Position;
 
     #[test]
     fn test_command_display_name() {
@@ -304,4 +549,139 @@ mod tests {
             "my_cmd"
         );
     }
+
+    #[test]
+    fn test_find_and_replace() {
+        let registry = CommandRegistry::new();
+        let mut editor = Editor::new();
+        editor.new_document();
+        editor.insert_text("foo bar foo").unwrap();
+        editor.move_to_line_start().unwrap();
+
+        let find = Command::Find {
+            pattern: Some("foo".to_string()),
+        };
+        registry.execute(&find, &mut editor).unwrap();
+        let doc = editor.active_document().unwrap();
+        assert_eq!(doc.selected_text().as_deref(), Some("foo"));
+
+        let replace = Command::Replace {
+            replacement: Some("baz".to_string()),
+        };
+        registry.execute(&replace, &mut editor).unwrap();
+        assert_eq!(editor.active_document().unwrap().text(), "baz bar baz");
+    }
+
+    #[test]
+    fn test_goto_line_records_jumps() {
+        let registry = CommandRegistry::new();
+        let mut editor = Editor::new();
+        editor.new_document();
+        editor.insert_text("a\nb\nc\nd").unwrap();
+        editor
+            .active_document_mut()
+            .unwrap()
+            .move_cursor_to(Position::new(0, 0));
+
+        registry
+            .execute(&Command::GotoLine { line: Some(2) }, &mut editor)
+            .unwrap();
+        registry
+            .execute(&Command::GotoLine { line: Some(99) }, &mut editor)
+            .unwrap();
+        let line = |editor: &Editor| editor.active_document().unwrap().cursor_position().line;
+        assert_eq!(line(&editor), 3);
+
+        // Jumps are anchored, so an edit above shifts them
+        editor
+            .active_document_mut()
+            .unwrap()
+            .move_cursor_to(Position::new(0, 0));
+        editor.insert_text("x\n").unwrap();
+        editor.goto_line(4).unwrap();
+
+        assert!(editor.jump_back().unwrap());
+        assert_eq!(line(&editor), 1);
+        assert!(editor.jump_back().unwrap());
+        assert_eq!(line(&editor), 3);
+        assert!(editor.jump_back().unwrap());
+        assert_eq!(line(&editor), 0);
+        assert!(!editor.jump_back().unwrap());
+        assert!(editor.jump_forward().unwrap());
+        assert_eq!(line(&editor), 3);
+    }
+
+    #[test]
+    fn test_global_marks_survive_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+
+        let mut editor = Editor::new();
+        let id = editor.open_file(&path).unwrap();
+        editor
+            .active_document_mut()
+            .unwrap()
+            .move_cursor_to(Position::new(1, 2));
+        editor.set_mark('A').unwrap();
+        editor.close_document(id).unwrap();
+
+        let session = editor.session();
+        assert_eq!(session.global_marks[&'A'].position, Position::new(1, 2));
+
+        let mut editor = Editor::new();
+        editor.restore_session(session);
+        editor.new_document();
+        assert!(editor.goto_mark('A').unwrap());
+        let doc = editor.active_document().unwrap();
+        assert_eq!(doc.path(), Some(path.as_path()));
+        assert_eq!(doc.cursor_position(), Position::new(1, 2));
+        assert!(!editor.goto_mark('B').unwrap());
+    }
+
+    #[test]
+    fn test_reload_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        std::fs::write(&a, "a\n").unwrap();
+        std::fs::write(&b, "b\n").unwrap();
+        let touch = |path: &std::path::Path, text: &str| {
+            std::fs::write(path, text).unwrap();
+            // Don't depend on the filesystem's timestamp resolution
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap();
+        };
+
+        let mut editor = Editor::new();
+        let mut events = editor.subscribe();
+        let a_id = editor.open_file(&a).unwrap();
+        editor.open_file(&b).unwrap();
+        assert!(editor.check_disk_changes().is_empty());
+
+        touch(&a, "a\nmore\n");
+        assert_eq!(editor.check_disk_changes(), [(a_id, Reload::Replaced)]);
+        assert!(editor.check_disk_changes().is_empty());
+        let changed = std::iter::from_fn(|| events.try_recv().ok())
+            .any(|event| matches!(event, EditorEvent::FileChangedOnDisk(path) if path == a));
+        assert!(changed);
+        let doc = editor.document(a_id).unwrap();
+        assert_eq!(
+            (doc.text().as_ref(), doc.is_modified()),
+            ("a\nmore\n", false)
+        );
+
+        // The command reloads even when the timestamp looks unchanged
+        std::fs::write(&b, "b\nchanged\n").unwrap();
+        let registry = CommandRegistry::new();
+        registry.execute(&Command::Reload, &mut editor).unwrap();
+        assert_eq!(editor.active_document().unwrap().text(), "b\nchanged\n");
+
+        // Conflicts with unsaved changes are reported to the caller
+        let b_id = editor.active_document().unwrap().id();
+        editor.insert_text("x").unwrap();
+        touch(&b, "y\nchanged\n");
+        let conflict = Reload::Merged { conflicts: 1 };
+        assert_eq!(editor.check_disk_changes(), [(b_id, conflict)]);
+    }
 }
@@ -83,6 +83,7 @@ impl Config {
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -134,6 +135,9 @@ pub struct EditorConfig {
 
     /// Undo history limit
     pub undo_limit: usize,
+
+    /// Keep undo history between sessions, in `<config dir>/luminex/undo`
+    pub persistent_undo: bool,
 }
 
 impl Default for EditorConfig {
@@ -151,6 +155,7 @@ impl Default for EditorConfig {
             scroll_offset: 3,
             vim_mode: false,
             undo_limit: 1000,
+            persistent_undo: false,
         }
     }
 }
@@ -164,6 +169,7 @@ pub enum CursorStyle {
     Underline,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for CursorStyle {
     fn default() -> Self {
         Self::Line
@@ -244,7 +250,7 @@ pub struct FileConfig {
     /// Default line ending
     pub line_ending: String,
 
-    /// Auto-save interval (0 to disable)
+    /// Milliseconds after the last edit before a file is saved (0 to disable)
     pub auto_save_delay: u32,
 
     /// Create backup on save
@@ -259,7 +265,10 @@ pub struct FileConfig {
     /// Watch files for external changes
     pub watch_files: bool,
 
-    /// Max file size to load (MB)
+    /// Files larger than this open in large-file mode (MB, 0 = never)
+    pub large_file_threshold: usize,
+
+    /// Files larger than this open read-only (MB, 0 = no limit)
     pub max_file_size: usize,
 
     /// Patterns to exclude from explorer
@@ -276,6 +285,7 @@ impl Default for FileConfig {
             trim_trailing_whitespace: true,
             final_newline: true,
             watch_files: true,
+            large_file_threshold: 10,
             max_file_size: 100,
             exclude_patterns: vec![
                 "**/.git/**".to_string(),
@@ -334,8 +344,18 @@ pub struct LanguageConfig {
 
     /// Comment string
     pub comment: Option<String>,
+
+    /// Overrides `FileConfig::trim_trailing_whitespace`
+    pub trim_trailing_whitespace: Option<bool>,
+
+    /// Overrides `FileConfig::final_newline`
+    pub final_newline: Option<bool>,
+
+    /// Overrides `FileConfig::backup_on_save`
+    pub backup_on_save: Option<bool>,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for LanguageConfig {
     fn default() -> Self {
         Self {
@@ -344,6 +364,9 @@ impl Default for LanguageConfig {
             formatter: None,
             lsp: None,
             comment: None,
+            trim_trailing_whitespace: None,
+            final_newline: None,
+            backup_on_save: None,
         }
     }
 }
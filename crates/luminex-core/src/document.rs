@@ -7,12 +7,26 @@
 //! - Encapsulation: Can change the underlying type without breaking APIs
 //! - Documentation: The type name explains its purpose
 
-use luminex_buffer::{MultiCursor, Position, TextBuffer};
+use luminex_buffer::{
+    Affinity, AnchorId, BufferConfig, BufferError, ByteBuffer, Change, ChangeSet, Cursor,
+    FileLoader, IndentStyle, MultiCursor, Position, SearchOptions, SearchQuery, TextBuffer,
+    TextEncoding, UndoCache, join_lines, line_changes, merge3, shuffle_lines, sort_lines,
+    unique_lines,
+};
+pub use luminex_buffer::{
+    BlockSelection, BracketMatch, BufferSnapshot, LineEnding, LineSort, ObjectExtent, TextObject,
+    UndoBranch, UndoStep, UndoTarget, WordKind,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
+use crate::config::Config;
+use crate::save::{self, SaveOptions};
 use crate::{CoreError, CoreResult};
 
 /// Unique identifier for a document.
@@ -64,52 +78,95 @@ pub struct Document {
     /// Document language (for syntax highlighting)
     language: Option<String>,
 
-    /// Line ending style
-    line_ending: LineEnding,
-
-    /// Encoding
-    #[allow(dead_code)]
-    encoding: String,
-
     /// Tab settings
-    #[allow(dead_code)]
     tab_config: TabConfig,
+
+    /// Whether more than one cursor may be added
+    multi_cursor: bool,
+
+    /// The block selection and the cursors it produced; stale once the
+    /// cursors are changed any other way
+    block: Option<(BlockSelection, MultiCursor)>,
+
+    /// Too big for the expensive extras (see `FileConfig::large_file_threshold`)
+    large_file: bool,
+
+    /// Edits and saves are refused (see `FileConfig::max_file_size`)
+    read_only: bool,
+
+    /// The content of a binary file, edited as bytes; the text buffer
+    /// stays empty
+    bytes: Option<ByteBuffer>,
+
+    /// Marks `a`–`z`, anchored in the buffer
+    marks: HashMap<char, AnchorId>,
+
+    /// Which steps of the save pipeline run
+    save_options: SaveOptions,
+
+    /// The text as last read from or written to the file: the base a
+    /// reload merges against
+    saved_text: BufferSnapshot,
+
+    /// The file as of the last read or write
+    disk_stamp: Option<DiskStamp>,
 }
 
-/// Line ending style.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum LineEnding {
-    /// Unix-style: \n
-    #[default]
-    Lf,
-    /// Windows-style: \r\n
-    CrLf,
-    /// Classic Mac: \r
-    Cr,
+/// How far apart two writes must be for the filesystem to give them
+/// different modification times. Some round to whole seconds; FAT to two.
+const MTIME_RESOLUTION: Duration = Duration::from_secs(2);
+
+/// What a file looked like when it was last read or written.
+#[derive(Debug, Clone, Copy)]
+struct DiskStamp {
+    modified: SystemTime,
+    len: u64,
+    /// When the file was looked at
+    taken: SystemTime,
 }
 
-impl LineEnding {
-    /// Returns the string representation.
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            LineEnding::Lf => "\n",
-            LineEnding::CrLf => "\r\n",
-            LineEnding::Cr => "\r",
-        }
+impl DiskStamp {
+    /// Looks at the file at `path`, if it exists.
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+            taken: SystemTime::now(),
+        })
     }
 
-    /// Detects line ending from text.
-    pub fn detect(text: &str) -> Self {
-        if text.contains("\r\n") {
-            LineEnding::CrLf
-        } else if text.contains('\r') {
-            LineEnding::Cr
-        } else {
-            LineEnding::Lf
-        }
+    /// True if the file may have been written again since, without its
+    /// modification time or size changing.
+    ///
+    /// A write soon after the file was looked at can get the same
+    /// timestamp; a same-size one then leaves no trace in the metadata.
+    fn is_racy(&self) -> bool {
+        self.taken < self.modified + MTIME_RESOLUTION
     }
 }
 
+/// What [`Document::reload`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reload {
+    /// The buffer already matched the merge result, encoding and line
+    /// ending included
+    Unchanged,
+    /// The buffer had no unsaved changes and took the file's text
+    Replaced,
+    /// Unsaved changes were merged with the file's; `conflicts` chunks
+    /// are marked for the user to resolve
+    Merged { conflicts: usize },
+}
+
+/// A run of lines replaced by a line operation.
+struct LineBlock {
+    /// The lines replaced, numbered as before the edit
+    old: Range<usize>,
+    /// The lines that replaced them, numbered as after the edit
+    new: Range<usize>,
+}
+
 /// Tab configuration.
 #[derive(Debug, Clone, Copy)]
 pub struct TabConfig {
@@ -119,6 +176,38 @@ pub struct TabConfig {
     pub use_spaces: bool,
 }
 
+impl TabConfig {
+    /// Resolves tab settings for a document.
+    ///
+    /// Detected indentation wins. Per-language settings only fill in
+    /// what detection couldn't tell, then the editor-wide defaults.
+    pub fn resolve(detected: Option<IndentStyle>, language: Option<&str>, config: &Config) -> Self {
+        let lang = language.map(|l| config.language(l)).unwrap_or_default();
+        let mut tab_config = Self {
+            width: lang.tab_size.unwrap_or(config.editor.tab_size),
+            use_spaces: lang.use_spaces.unwrap_or(config.editor.use_spaces),
+        };
+        match detected {
+            Some(IndentStyle::Tabs) => tab_config.use_spaces = false,
+            Some(IndentStyle::Spaces(width)) => {
+                tab_config.use_spaces = true;
+                tab_config.width = width;
+            }
+            None => {}
+        }
+        tab_config
+    }
+
+    /// Returns the text for one level of indentation.
+    pub fn indent_unit(&self) -> String {
+        if self.use_spaces {
+            " ".repeat(self.width)
+        } else {
+            "\t".to_string()
+        }
+    }
+}
+
 impl Default for TabConfig {
     fn default() -> Self {
         Self {
@@ -131,27 +220,151 @@ impl Default for TabConfig {
 impl Document {
     /// Creates a new empty document.
     pub fn new() -> Self {
+        let buffer = TextBuffer::new();
         Self {
             id: DocumentId::new(),
-            buffer: TextBuffer::new(),
+            saved_text: buffer.snapshot(),
+            buffer,
             cursors: MultiCursor::new(),
             path: None,
             name: "Untitled".to_string(),
             language: None,
-            line_ending: LineEnding::default(),
-            encoding: "utf-8".to_string(),
             tab_config: TabConfig::default(),
+            multi_cursor: true,
+            block: None,
+            large_file: false,
+            read_only: false,
+            bytes: None,
+            marks: HashMap::new(),
+            save_options: SaveOptions::default(),
+            disk_stamp: None,
         }
     }
 
+    /// Creates a new empty document using the configured defaults.
+    pub fn with_config(config: &Config) -> Self {
+        let mut doc = Self::new();
+        doc.buffer.set_encoding(Self::default_encoding(config));
+        doc.buffer
+            .set_line_ending(Self::default_line_ending(config));
+        doc.set_tab_config(TabConfig::resolve(None, None, config));
+        doc.save_options = SaveOptions::resolve(None, config);
+        doc.multi_cursor = config.editor.multi_cursor;
+        // A fresh document has nothing to save yet
+        doc.buffer.mark_saved();
+        doc
+    }
+
     /// Opens a document from a file.
     pub fn from_file(path: impl AsRef<Path>) -> CoreResult<Self> {
+        Self::from_file_with_config(path, &Config::default())
+    }
+
+    /// Opens a document from a file using the configured defaults.
+    ///
+    /// The encoding is detected from the content; `FileConfig::encoding`
+    /// is used when detection can't tell (e.g. legacy 8-bit files).
+    pub fn from_file_with_config(path: impl AsRef<Path>, config: &Config) -> CoreResult<Self> {
+        Self::load(path, config, |_, _| {})
+    }
+
+    /// Opens a document from a file, reporting progress as it loads.
+    ///
+    /// The file is read in chunks, and `progress` is called with the
+    /// bytes loaded so far and the file size after each one. Files over
+    /// `FileConfig::large_file_threshold` open in large-file mode
+    /// (no edit coalescing, syntax highlighting or word wrap); of files
+    /// over `FileConfig::max_file_size`, only the first `max_file_size`
+    /// MB are loaded, read-only. Binary files are opened as bytes (see
+    /// [`Document::bytes_mut`]).
+    pub fn load(
+        path: impl AsRef<Path>,
+        config: &Config,
+        progress: impl FnMut(u64, u64),
+    ) -> CoreResult<Self> {
+        let path = path.as_ref();
+        let stamp = DiskStamp::of(path);
+        let loader = match FileLoader::open(path, Self::default_encoding(config)) {
+            Err(BufferError::BinaryFile(_)) => return Self::open_binary(path, config),
+            loader => loader?,
+        };
+        Self::load_from(path, stamp, loader, config, progress)
+    }
+
+    /// Opens a binary file as a [`ByteBuffer`], for hex editing.
+    fn open_binary(path: &Path, config: &Config) -> CoreResult<Self> {
+        let mut doc = Self::from_buffer(path, TextBuffer::new(), config);
+        doc.language = None;
+        doc.bytes = Some(ByteBuffer::from_file(path)?);
+        Ok(doc)
+    }
+
+    /// Opens a document from a file, decoding it with an explicit encoding.
+    pub fn from_file_with_encoding(
+        path: impl AsRef<Path>,
+        encoding: &str,
+        config: &Config,
+    ) -> CoreResult<Self> {
         let path = path.as_ref();
-        let buffer = TextBuffer::from_file(path)?;
+        let encoding = TextEncoding::for_label(encoding)?;
+        let stamp = DiskStamp::of(path);
+        let loader = FileLoader::open_with_encoding(path, encoding)?;
+        Self::load_from(path, stamp, loader, config, |_, _| {})
+    }
 
-        // Detect line ending from file content
-        let line_ending = LineEnding::detect(&buffer.text());
+    /// Finishes opening a file, which looked like `stamp` before it was
+    /// read.
+    fn load_from(
+        path: &Path,
+        stamp: Option<DiskStamp>,
+        mut loader: FileLoader,
+        config: &Config,
+        mut progress: impl FnMut(u64, u64),
+    ) -> CoreResult<Self> {
+        let size = loader.total_bytes();
+        let large_file = exceeds_megabytes(size, config.files.large_file_threshold);
+        let read_only = exceeds_megabytes(size, config.files.max_file_size);
+
+        // An oversized file only loads up to the limit, as a preview
+        let limit = if read_only {
+            config.files.max_file_size as u64 * 1024 * 1024
+        } else {
+            u64::MAX
+        };
+        loop {
+            let more = loader.load_chunk()?;
+            progress(loader.loaded_bytes(), size);
+            if !more || loader.loaded_bytes() >= limit {
+                break;
+            }
+        }
+        let buffer_config = BufferConfig {
+            max_history: config.editor.undo_limit,
+            coalesce_edits: !large_file,
+            undo_cache: if config.editor.persistent_undo && !read_only {
+                UndoCache::default_location()
+            } else {
+                None
+            },
+            ..BufferConfig::default()
+        };
+        let buffer = if loader.is_done() {
+            loader.finish(buffer_config)?
+        } else {
+            tracing::info!(
+                "{} is over the size limit, opening the start read-only",
+                path.display()
+            );
+            loader.finish_partial(buffer_config)
+        };
+        let mut doc = Self::from_buffer(path, buffer, config);
+        doc.disk_stamp = stamp;
+        doc.large_file = large_file;
+        doc.read_only = read_only;
+        Ok(doc)
+    }
 
+    fn from_buffer(path: &Path, buffer: TextBuffer, config: &Config) -> Self {
         // Get file name
         let name = path
             .file_name()
@@ -165,16 +378,49 @@ impl Document {
             .and_then(|e| e.to_str())
             .map(Self::language_from_extension);
 
-        Ok(Self {
+        // Infer tabs vs spaces from the content
+        let tab_config =
+            TabConfig::resolve(buffer.detect_indentation(), language.as_deref(), config);
+        let save_options = SaveOptions::resolve(language.as_deref(), config);
+
+        let mut doc = Self {
             id: DocumentId::new(),
+            saved_text: buffer.snapshot(),
             buffer,
             cursors: MultiCursor::new(),
             path: Some(path.to_path_buf()),
             name,
             language,
-            line_ending,
-            encoding: "utf-8".to_string(),
-            tab_config: TabConfig::default(),
+            tab_config,
+            multi_cursor: config.editor.multi_cursor,
+            block: None,
+            large_file: false,
+            read_only: false,
+            bytes: None,
+            marks: HashMap::new(),
+            save_options,
+            disk_stamp: DiskStamp::of(path),
+        };
+        doc.set_tab_config(tab_config);
+        doc
+    }
+
+    /// Returns the line ending named by `FileConfig::line_ending`.
+    fn default_line_ending(config: &Config) -> LineEnding {
+        LineEnding::parse(&config.files.line_ending).unwrap_or_else(|| {
+            tracing::warn!(
+                "Unknown line ending {:?}, falling back to LF",
+                config.files.line_ending
+            );
+            LineEnding::default()
+        })
+    }
+
+    /// Returns the encoding named by `FileConfig::encoding`.
+    fn default_encoding(config: &Config) -> TextEncoding {
+        TextEncoding::for_label(&config.files.encoding).unwrap_or_else(|e| {
+            tracing::warn!("{}, falling back to UTF-8", e);
+            TextEncoding::default()
         })
     }
 
@@ -226,9 +472,94 @@ impl Document {
         self.language.as_deref()
     }
 
+    /// Returns true if the document was opened in large-file mode.
+    pub fn is_large_file(&self) -> bool {
+        self.large_file
+    }
+
+    /// Returns true if the document can't be edited or saved.
+    ///
+    /// Read-only documents may hold only the start of their file (see
+    /// [`Document::load`]).
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns true if the document is a binary file, edited as bytes.
+    pub fn is_binary(&self) -> bool {
+        self.bytes.is_some()
+    }
+
+    /// Returns the content of a binary file.
+    pub fn bytes(&self) -> Option<&ByteBuffer> {
+        self.bytes.as_ref()
+    }
+
+    /// Returns the content of a binary file, for editing.
+    ///
+    /// Byte edits have their own undo history; `save` writes them out.
+    pub fn bytes_mut(&mut self) -> Option<&mut ByteBuffer> {
+        self.bytes.as_mut()
+    }
+
+    /// Returns true if the document should be syntax highlighted.
+    pub fn syntax_highlighting(&self) -> bool {
+        self.language.is_some() && !self.large_file
+    }
+
+    /// Returns true if long lines should wrap.
+    pub fn word_wrap(&self, config: &Config) -> bool {
+        config.editor.word_wrap && !self.large_file
+    }
+
+    /// Returns the file encoding.
+    pub fn encoding(&self) -> TextEncoding {
+        self.buffer.encoding()
+    }
+
+    /// Returns the line ending style used when saving.
+    pub fn line_ending(&self) -> LineEnding {
+        self.buffer.line_ending()
+    }
+
+    /// Converts the document to another line ending style.
+    ///
+    /// The buffer always holds `\n`, so only the saved file changes.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) -> CoreResult<()> {
+        self.ensure_writable()?;
+        self.buffer.set_line_ending(line_ending);
+        Ok(())
+    }
+
+    /// Returns the tab settings.
+    pub fn tab_config(&self) -> TabConfig {
+        self.tab_config
+    }
+
+    /// Sets the tab settings, keeping the buffer's config in sync.
+    pub fn set_tab_config(&mut self, tab_config: TabConfig) {
+        self.tab_config = tab_config;
+        let mut buffer_config = self.buffer.config().clone();
+        buffer_config.tab_width = tab_config.width;
+        buffer_config.use_spaces = tab_config.use_spaces;
+        self.buffer.set_config(buffer_config);
+    }
+
+    /// Returns which save steps run.
+    pub fn save_options(&self) -> SaveOptions {
+        self.save_options
+    }
+
+    /// Sets which save steps run.
+    pub fn set_save_options(&mut self, save_options: SaveOptions) {
+        self.save_options = save_options;
+    }
+
     /// Returns true if the document has unsaved changes.
     pub fn is_modified(&self) -> bool {
-        self.buffer.is_modified()
+        self.bytes
+            .as_ref()
+            .map_or_else(|| self.buffer.is_modified(), ByteBuffer::is_modified)
     }
 
     /// Returns the text buffer.
@@ -236,6 +567,11 @@ impl Document {
         &self.buffer
     }
 
+    /// Returns a cheap, versioned copy of the text for background tasks.
+    pub fn snapshot(&self) -> BufferSnapshot {
+        self.buffer.snapshot()
+    }
+
     /// Returns a mutable reference to the buffer.
     pub fn buffer_mut(&mut self) -> &mut TextBuffer {
         &mut self.buffer
@@ -268,16 +604,20 @@ impl Document {
 
     // ==================== File Operations ====================
 
-    /// Saves the document.
+    /// Saves the document through the save pipeline (see
+    /// [`SaveOptions`]).
     pub fn save(&mut self) -> CoreResult<()> {
-        self.buffer.save()?;
-        Ok(())
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| CoreError::InvalidOperation("Document has no file".to_string()))?;
+        self.save_to(&path)
     }
 
     /// Saves the document to a new path.
     pub fn save_as(&mut self, path: impl AsRef<Path>) -> CoreResult<()> {
         let path = path.as_ref();
-        self.buffer.save_as(path)?;
+        self.save_to(path)?;
         self.path = Some(path.to_path_buf());
         self.name = path
             .file_name()
@@ -291,134 +631,1034 @@ impl Document {
         Ok(())
     }
 
-    // ==================== Text Editing ====================
-
-    /// Inserts text at the current cursor position.
-    pub fn insert_at_cursor(&mut self, text: &str) -> CoreResult<()> {
-        let pos = self.cursor_position();
-        let idx = self.buffer.position_to_char_idx(pos)?;
-        self.buffer.insert(idx, text)?;
+    /// Runs the save pipeline: cleanup as one undoable edit, backup,
+    /// write, then the old file's owner.
+    fn save_to(&mut self, path: &Path) -> CoreResult<()> {
+        if let Some(bytes) = &mut self.bytes {
+            bytes.save_as(path)?;
+            self.disk_stamp = DiskStamp::of(path);
+            return Ok(());
+        }
+        self.ensure_writable()?;
+        let cleanup = save::cleanup_changes(&self.buffer, self.save_options);
+        if !cleanup.is_empty() {
+            self.apply_changes(&cleanup)?;
+        }
 
-        // Move cursor past inserted text
-        let new_idx = idx + text.chars().count();
-        let new_pos = self.buffer.char_idx_to_position(new_idx)?;
-        self.cursors.primary_mut().move_to(new_pos);
+        let metadata = std::fs::metadata(path).ok();
+        if self.save_options.backup {
+            save::write_backup(path)?;
+        }
+        self.buffer.save_as(path)?;
+        if let Some(metadata) = metadata {
+            save::restore_owner(path, &metadata);
+        }
+        self.saved_text = self.buffer.snapshot();
+        self.disk_stamp = DiskStamp::of(path);
+        Ok(())
+    }
 
+    /// Re-reads the file from disk, decoding it with `encoding`, as one
+    /// undoable edit.
+    ///
+    /// Use this when detection picked the wrong encoding. Refused while
+    /// there are unsaved changes, which the re-read text would replace.
+    pub fn reopen_with_encoding(&mut self, encoding: &str) -> CoreResult<()> {
+        self.ensure_writable()?;
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| CoreError::InvalidOperation("Document has no file".to_string()))?;
+        if self.is_modified() {
+            return Err(CoreError::InvalidOperation(
+                "Save or discard unsaved changes before reopening".to_string(),
+            ));
+        }
+        let stamp = DiskStamp::of(&path);
+        let disk = read_file(&path, TextEncoding::for_label(encoding)?)?;
+        let changes = line_changes(&self.buffer.text(), &disk.text());
+        if !changes.is_empty() {
+            self.apply_changes(&changes)?;
+        }
+        self.buffer.set_encoding(disk.encoding());
+        self.buffer.set_line_ending(disk.line_ending());
+        self.buffer.mark_saved();
+        self.saved_text = disk.snapshot();
+        self.disk_stamp = stamp;
         Ok(())
     }
 
-    /// Deletes the character before the cursor (backspace).
-    pub fn delete_backward(&mut self) -> CoreResult<()> {
-        let cursor = self.cursors.primary();
+    /// Returns true if the file may have changed on disk since it was
+    /// last read or written. A missing file doesn't count.
+    ///
+    /// A changed modification time or size gives a change away. Right
+    /// after a read or write neither has to, so until the timestamp is
+    /// old enough to be told apart from a later write's, this stays true
+    /// and [`reload`](Self::reload) compares the content.
+    pub fn changed_on_disk(&self) -> bool {
+        let Some(now) = self.path.as_deref().and_then(DiskStamp::of) else {
+            return false;
+        };
+        self.disk_stamp.is_none_or(|stamp| {
+            (now.modified, now.len) != (stamp.modified, stamp.len) || stamp.is_racy()
+        })
+    }
 
-        if cursor.has_selection() {
-            return self.delete_selection();
+    /// Re-reads the file after it changed on disk, as one undoable edit.
+    ///
+    /// Without unsaved changes the buffer takes the file's text. With
+    /// them, a three-way merge against the text as last saved keeps the
+    /// changes of both sides and marks the lines both changed. Only
+    /// lines that differ are replaced, so cursors elsewhere stay put.
+    ///
+    /// The encoding is detected again, falling back to the current one,
+    /// and the line ending taken from the file, so a file converted on
+    /// disk is saved the way it now is. Refused for read-only documents.
+    pub fn reload(&mut self) -> CoreResult<Reload> {
+        self.ensure_writable()?;
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| CoreError::InvalidOperation("Document has no file".to_string()))?;
+        let stamp = DiskStamp::of(&path);
+        let disk = FileLoader::open(&path, self.encoding())?.finish(BufferConfig::default())?;
+        let disk_text = disk.text();
+        let current = self.buffer.text().into_owned();
+
+        let (target, reload) = if self.is_modified() {
+            let merge = merge3(
+                &self.saved_text.text(),
+                &current,
+                &disk_text,
+                ["unsaved", "on disk"],
+            );
+            let conflicts = merge.conflicts;
+            (merge.text, Reload::Merged { conflicts })
+        } else {
+            (disk_text.to_string(), Reload::Replaced)
+        };
+        let changes = line_changes(&current, &target);
+        if !changes.is_empty() {
+            self.apply_changes(&changes)?;
         }
+        let converted =
+            (disk.encoding(), disk.line_ending()) != (self.encoding(), self.line_ending());
+        self.buffer.set_encoding(disk.encoding());
+        self.buffer.set_line_ending(disk.line_ending());
+        if target == disk_text {
+            self.buffer.mark_saved();
+        }
+        self.saved_text = disk.snapshot();
+        self.disk_stamp = stamp;
+        Ok(if changes.is_empty() && !converted {
+            Reload::Unchanged
+        } else {
+            reload
+        })
+    }
 
-        let pos = cursor.position;
-        let idx = self.buffer.position_to_char_idx(pos)?;
+    /// Converts the document to another encoding.
+    ///
+    /// The text is unchanged; the file is written in the new encoding
+    /// on the next save.
+    pub fn convert_encoding(&mut self, encoding: &str) -> CoreResult<()> {
+        self.ensure_writable()?;
+        let encoding = TextEncoding::for_label(encoding)?;
+        self.buffer.set_encoding(encoding);
+        Ok(())
+    }
+
+    // ==================== Text Editing ====================
+
+    /// Inserts text at every cursor, replacing any selections.
+    ///
+    /// Line endings in `text` (e.g. from a paste) are normalized to `\n`.
+    ///
+    /// In a block selection, cursors on lines too short to reach the
+    /// block are padded out to it with spaces first.
+    pub fn insert_at_cursor(&mut self, text: &str) -> CoreResult<()> {
+        let text = LineEnding::normalize(text);
+        let block = self.active_block();
+        self.edit_each(|doc, cursor| {
+            let padding = doc.block_padding(block, cursor)?;
+            Ok(Some((doc.cursor_range(cursor)?, padding + &text)))
+        })?;
+        self.update_block(block.filter(|_| !text.contains('\n')));
+        Ok(())
+    }
 
-        if idx > 0 {
-            self.buffer.delete(idx - 1..idx)?;
-            let new_pos = self.buffer.char_idx_to_position(idx - 1)?;
-            self.cursors.primary_mut().move_to(new_pos);
+    /// Inserts one text per cursor, in document order.
+    ///
+    /// This is how a multi-cursor copy is pasted back. In a block
+    /// selection, a single text with one line per cursor is split into
+    /// its lines, so pasting a copied column fills the block. If the
+    /// counts don't match, every cursor gets all the texts joined by
+    /// newlines.
+    pub fn insert_per_cursor(&mut self, texts: &[String]) -> CoreResult<()> {
+        let block = self.active_block();
+        let mut texts: Vec<String> = texts
+            .iter()
+            .map(|t| LineEnding::normalize(t).into_owned())
+            .collect();
+        if let [text] = texts.as_slice()
+            && block.is_some()
+            && text.lines().count() == self.cursors.len()
+        {
+            texts = text.lines().map(str::to_string).collect();
+        }
+        if texts.len() != self.cursors.len() {
+            return self.insert_at_cursor(&texts.join("\n"));
         }
 
+        let mut texts = texts.into_iter();
+        self.edit_each(|doc, cursor| {
+            let padding = doc.block_padding(block, cursor)?;
+            Ok(Some((
+                doc.cursor_range(cursor)?,
+                padding + &texts.next().unwrap_or_default(),
+            )))
+        })?;
+        // The pasted lines can differ in width, so the block is done
+        self.block = None;
+        Ok(())
+    }
+
+    /// Deletes the character before each cursor (backspace).
+    ///
+    /// Cursors with a selection delete the selection instead. In a
+    /// block selection, lines that don't reach the block are left alone.
+    pub fn delete_backward(&mut self) -> CoreResult<()> {
+        let block = self.active_block();
+        self.edit_each(|doc, cursor| {
+            if doc.outside_block(block, cursor)? {
+                return Ok(None);
+            }
+            let range = doc.cursor_range(cursor)?;
+            if !range.is_empty() || range.start == 0 {
+                return Ok(Some((range, String::new())));
+            }
+            // Delete the whole grapheme (e.g. an emoji with its modifier)
+            let start = doc.buffer.prev_grapheme_boundary(range.start);
+            Ok(Some((start..range.start, String::new())))
+        })?;
+        self.update_block(block);
         Ok(())
     }
 
-    /// Deletes the character after the cursor (delete key).
+    /// Deletes the character after each cursor (delete key).
+    ///
+    /// Cursors with a selection delete the selection instead. In a
+    /// block selection, lines that don't reach the block are left alone.
     pub fn delete_forward(&mut self) -> CoreResult<()> {
-        let cursor = self.cursors.primary();
+        let block = self.active_block();
+        self.edit_each(|doc, cursor| {
+            if doc.outside_block(block, cursor)? {
+                return Ok(None);
+            }
+            let range = doc.cursor_range(cursor)?;
+            if !range.is_empty() {
+                return Ok(Some((range, String::new())));
+            }
+            let end = doc.buffer.next_grapheme_boundary(range.start);
+            Ok(Some((range.start..end, String::new())))
+        })?;
+        self.update_block(block);
+        Ok(())
+    }
 
-        if cursor.has_selection() {
-            return self.delete_selection();
+    /// Deletes from each cursor back to the start of the word (ctrl+backspace).
+    ///
+    /// Stops at the start of the line; at the start of a line it joins
+    /// the line with the previous one.
+    pub fn delete_word_backward(&mut self, kind: WordKind) -> CoreResult<()> {
+        self.edit_each(|doc, cursor| {
+            let range = doc.cursor_range(cursor)?;
+            if !range.is_empty() {
+                return Ok(Some((range, String::new())));
+            }
+            let idx = range.start;
+            let line_start = doc
+                .buffer
+                .position_to_char_idx(Position::new(cursor.position.line, 0))?;
+            let start = if idx == line_start {
+                doc.buffer.prev_grapheme_boundary(idx)
+            } else {
+                doc.buffer.prev_word_start(idx, kind).max(line_start)
+            };
+            Ok(Some((start..idx, String::new())))
+        })
+    }
+
+    /// Deletes from each cursor to the end of the word (ctrl+delete).
+    ///
+    /// Stops at the end of the line; at the end of a line it joins the
+    /// next line onto it.
+    pub fn delete_word_forward(&mut self, kind: WordKind) -> CoreResult<()> {
+        self.edit_each(|doc, cursor| {
+            let range = doc.cursor_range(cursor)?;
+            if !range.is_empty() {
+                return Ok(Some((range, String::new())));
+            }
+            let idx = range.start;
+            let line = cursor.position.line;
+            let line_end = doc.buffer.position_to_char_idx(Position::new(line, 0))?
+                + doc.line_content_len(line);
+            let end = if idx >= line_end {
+                doc.buffer.next_grapheme_boundary(idx)
+            } else {
+                doc.buffer.next_word_end(idx, kind).min(line_end)
+            };
+            Ok(Some((idx..end, String::new())))
+        })
+    }
+
+    /// Deletes the selection of every cursor that has one.
+    pub fn delete_selection(&mut self) -> CoreResult<()> {
+        let block = self.active_block();
+        self.edit_each(|doc, cursor| {
+            Ok(if cursor.has_selection() {
+                Some((doc.cursor_range(cursor)?, String::new()))
+            } else {
+                None
+            })
+        })?;
+        self.update_block(block);
+        Ok(())
+    }
+
+    /// Replaces a range at every cursor as one edit.
+    ///
+    /// `edit` returns the char range to replace for a cursor (in the
+    /// text before any change) and its new text, or `None` to leave the
+    /// cursor alone. Changes are applied back to front, as one undo step
+    /// when there is more than one. Afterwards each edited cursor sits
+    /// after its new text, and cursors that ran into each other merge.
+    fn edit_each(
+        &mut self,
+        mut edit: impl FnMut(&Self, &Cursor) -> CoreResult<Option<(Range<usize>, String)>>,
+    ) -> CoreResult<()> {
+        self.ensure_writable()?;
+        self.cursors.merge_overlapping();
+
+        let mut changes = ChangeSet::new();
+        let mut targets = Vec::with_capacity(self.cursors.len());
+        let mut prev_end = 0;
+        for cursor in self.cursors.all() {
+            match edit(self, cursor)? {
+                Some((range, text)) => {
+                    // Neighbouring cursors may reach into each other's
+                    // range (two word deletions in one word); the earlier
+                    // one keeps the overlap.
+                    let start = range.start.max(prev_end);
+                    let end = range.end.max(start);
+                    prev_end = end;
+                    targets.push(Some(end));
+                    changes.push(Change {
+                        range: start..end,
+                        text,
+                    });
+                }
+                None => targets.push(None),
+            }
+        }
+        if changes
+            .changes()
+            .iter()
+            .all(|c| c.range.is_empty() && c.text.is_empty())
+        {
+            return Ok(());
         }
 
-        let pos = cursor.position;
-        let idx = self.buffer.position_to_char_idx(pos)?;
+        // Work out where the cursors go while the old text is still there
+        let mut moved = Vec::with_capacity(targets.len());
+        for (cursor, target) in self.cursors.all().iter().zip(&targets) {
+            let position = match target {
+                Some(end) => changes.map(*end, Affinity::Forward),
+                None => changes.map(
+                    self.buffer.position_to_char_idx(cursor.position)?,
+                    cursor.affinity,
+                ),
+            };
+            let anchor = match (target, cursor.anchor) {
+                (None, Some(anchor)) => {
+                    Some(changes.map(self.buffer.position_to_char_idx(anchor)?, cursor.affinity))
+                }
+                _ => None,
+            };
+            moved.push((position, anchor));
+        }
 
-        if idx < self.buffer.len_chars() {
-            self.buffer.delete(idx..idx + 1)?;
+        self.buffer.record_cursors(&self.cursors);
+        match changes.changes() {
+            // A lone insert or delete goes in directly, so typing still
+            // coalesces
+            [change] if change.range.is_empty() => {
+                self.buffer.insert(change.range.start, &change.text)?;
+            }
+            [change] if change.text.is_empty() => {
+                self.buffer.delete(change.range.clone())?;
+            }
+            _ => {
+                self.buffer.apply_changes(&changes)?;
+            }
         }
 
+        for (cursor, (position, anchor)) in self.cursors.all_mut().iter_mut().zip(moved) {
+            cursor.move_to(self.buffer.char_idx_to_position(position)?);
+            if let Some(anchor) = anchor {
+                cursor.anchor = Some(self.buffer.char_idx_to_position(anchor)?);
+            }
+        }
+        self.cursors.merge_overlapping();
+        self.buffer.record_cursors_after(&self.cursors);
         Ok(())
     }
 
-    /// Deletes the current selection.
-    pub fn delete_selection(&mut self) -> CoreResult<()> {
-        if let Some((start, end)) = self.cursors.primary().selection_range() {
-            let start_idx = self.buffer.position_to_char_idx(start)?;
-            let end_idx = self.buffer.position_to_char_idx(end)?;
-            self.buffer.delete(start_idx..end_idx)?;
-            self.cursors.primary_mut().move_to(start);
+    /// Fails if the document is read-only, or binary (its text can't be
+    /// edited; its bytes can).
+    fn ensure_writable(&self) -> CoreResult<()> {
+        if self.read_only {
+            return Err(CoreError::ReadOnly(self.name.clone()));
+        }
+        if self.bytes.is_some() {
+            return Err(CoreError::InvalidOperation(format!(
+                "{} is a binary file: edit its bytes instead",
+                self.name
+            )));
         }
         Ok(())
     }
 
+    /// Returns the char range a cursor covers (empty without a selection).
+    fn cursor_range(&self, cursor: &Cursor) -> CoreResult<Range<usize>> {
+        let (start, end) = cursor.range();
+        Ok(self.buffer.position_to_char_idx(start)?..self.buffer.position_to_char_idx(end)?)
+    }
+
+    /// Applies a change set (e.g. edits from a language server) as one
+    /// undo step, keeping every cursor and selection anchored to its text.
+    pub fn apply_changes(&mut self, changes: &ChangeSet) -> CoreResult<()> {
+        let mut cursors = self.cursors.clone();
+        for cursor in cursors.all_mut() {
+            let affinity = cursor.affinity;
+            cursor.position = changes.map_position(cursor.position, affinity, &self.buffer)?;
+            if let Some(anchor) = cursor.anchor {
+                cursor.anchor = Some(changes.map_position(anchor, affinity, &self.buffer)?);
+            }
+        }
+        self.edit_group(|doc| {
+            doc.buffer.apply_changes(changes)?;
+            doc.cursors = cursors;
+            Ok(())
+        })
+    }
+
     /// Returns the selected text.
+    ///
+    /// With several selections, their texts are joined by newlines.
     pub fn selected_text(&self) -> Option<String> {
-        let cursor = self.cursors.primary();
-        cursor.selection_range().and_then(|(start, end)| {
-            let start_idx = self.buffer.position_to_char_idx(start).ok()?;
-            let end_idx = self.buffer.position_to_char_idx(end).ok()?;
-            self.buffer.slice(start_idx..end_idx).ok().map(|s| s.into_owned())
-        })
+        let texts: Vec<String> = self
+            .cursors
+            .all()
+            .iter()
+            .filter(|c| c.has_selection())
+            .filter_map(|c| self.buffer.slice(self.cursor_range(c).ok()?).ok())
+            .map(|s| s.into_owned())
+            .collect();
+        (!texts.is_empty()).then(|| texts.join("\n"))
+    }
+
+    /// Returns the selected text of each cursor, in document order.
+    ///
+    /// Cursors without a selection contribute an empty string, so the
+    /// result always has one entry per cursor.
+    pub fn selected_texts(&self) -> Vec<String> {
+        self.cursors
+            .all()
+            .iter()
+            .map(|c| {
+                self.cursor_range(c)
+                    .ok()
+                    .and_then(|range| self.buffer.slice(range).ok())
+                    .map(|s| s.into_owned())
+                    .unwrap_or_default()
+            })
+            .collect()
     }
 
     /// Inserts a new line.
+    ///
+    /// The buffer stores `\n`; the document's line ending is applied on save.
     pub fn insert_newline(&mut self) -> CoreResult<()> {
-        self.insert_at_cursor(self.line_ending.as_str())
+        self.insert_at_cursor("\n")
+    }
+
+    // ==================== Indentation ====================
+
+    /// Handles the Tab key.
+    ///
+    /// With a selection this indents the selected lines. Otherwise it
+    /// inserts a tab, or spaces up to the next tab stop of each cursor.
+    /// Tab stops count display cells, so wide characters and tabs
+    /// already on the line are measured as they are shown.
+    pub fn insert_tab(&mut self) -> CoreResult<()> {
+        if self.cursors.all().iter().any(|c| c.has_selection()) {
+            return self.indent();
+        }
+        if !self.tab_config.use_spaces {
+            return self.insert_at_cursor("\t");
+        }
+        let width = self.tab_config.width.max(1);
+        self.edit_each(|doc, cursor| {
+            let range = doc.cursor_range(cursor)?;
+            let column = doc.buffer.display_column(cursor.position, width)?;
+            Ok(Some((range, " ".repeat(width - column % width))))
+        })
+    }
+
+    /// Indents every line touched by the selection by one level.
+    pub fn indent(&mut self) -> CoreResult<()> {
+        let unit = self.tab_config.indent_unit();
+        let added = unit.chars().count();
+        self.edit_group(|doc| {
+            for line in doc.selected_lines().into_iter().rev() {
+                // Leave empty lines alone
+                if doc.buffer.line(line)?.trim_end_matches('\n').is_empty() {
+                    continue;
+                }
+                let idx = doc.buffer.position_to_char_idx(Position::new(line, 0))?;
+                doc.buffer.insert(idx, &unit)?;
+                doc.shift_columns(line, added as isize);
+            }
+            Ok(())
+        })
+    }
+
+    /// Removes one level of indentation from every line touched by the selection.
+    pub fn outdent(&mut self) -> CoreResult<()> {
+        let width = self.tab_config.width.max(1);
+        self.edit_group(|doc| {
+            for line in doc.selected_lines().into_iter().rev() {
+                let text = doc.buffer.line(line)?;
+                let removed = if text.starts_with('\t') {
+                    1
+                } else {
+                    text.chars().take(width).take_while(|&c| c == ' ').count()
+                };
+                if removed == 0 {
+                    continue;
+                }
+                let idx = doc.buffer.position_to_char_idx(Position::new(line, 0))?;
+                doc.buffer.delete(idx..idx + removed)?;
+                doc.shift_columns(line, -(removed as isize));
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the lines covered by any cursor's selection (or its
+    /// line), in ascending order.
+    ///
+    /// A selection ending at column 0 doesn't include that last line.
+    fn selected_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self
+            .cursors
+            .all()
+            .iter()
+            .flat_map(|cursor| match cursor.selection_range() {
+                Some((start, end)) if end.line > start.line && end.column == 0 => {
+                    start.line..=end.line - 1
+                }
+                Some((start, end)) => start.line..=end.line,
+                None => cursor.position.line..=cursor.position.line,
+            })
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        lines
+    }
+
+    /// Returns the number of lines, leaving out the empty line after a
+    /// trailing `\n`: it has no text to move or copy.
+    fn text_line_count(&self) -> usize {
+        let len_lines = self.buffer.len_lines();
+        match self.buffer.line(len_lines - 1) {
+            Ok(last) if len_lines > 1 && last.is_empty() => len_lines - 1,
+            _ => len_lines,
+        }
+    }
+
+    /// Moves the cursors and anchors after text was added to or removed
+    /// from the start of `line`.
+    ///
+    /// Positions at column 0 stay put when indenting, so a selection of
+    /// whole lines still covers the whole lines afterwards.
+    fn shift_columns(&mut self, line: usize, delta: isize) {
+        let shift = |pos: &mut Position| {
+            if pos.line == line && (delta < 0 || pos.column > 0) {
+                pos.column = pos.column.saturating_add_signed(delta);
+            }
+        };
+        for cursor in self.cursors.all_mut() {
+            shift(&mut cursor.position);
+            if let Some(anchor) = cursor.anchor.as_mut() {
+                shift(anchor);
+            }
+        }
+    }
+
+    // ==================== Lines ====================
+
+    /// Deletes every line touched by a cursor.
+    pub fn delete_lines(&mut self) -> CoreResult<()> {
+        let blocks = self.replace_line_blocks(Some, |_| Vec::new())?;
+        let last_line = self.buffer.len_lines() - 1;
+        let positions = blocks
+            .iter()
+            .map(|b| Position::new(b.new.start.min(last_line), 0));
+        self.set_cursors(positions.map(|pos| (pos, pos)));
+        self.buffer.record_cursors_after(&self.cursors);
+        Ok(())
+    }
+
+    /// Copies every line touched by a cursor below itself. The cursors
+    /// move to the copy.
+    pub fn duplicate_lines(&mut self) -> CoreResult<()> {
+        let blocks = self.replace_line_blocks(Some, |lines| [lines.clone(), lines].concat())?;
+        self.shift_cursor_lines(&blocks, |b| b.old.len() as isize);
+        Ok(())
+    }
+
+    /// Swaps the lines touched by each cursor with the line above.
+    ///
+    /// Lines already at the top stay put.
+    pub fn move_lines_up(&mut self) -> CoreResult<()> {
+        let extend = |run: Range<usize>| (run.start > 0).then(|| run.start - 1..run.end);
+        let blocks = self.replace_line_blocks(extend, |mut lines| {
+            lines.rotate_left(1);
+            lines
+        })?;
+        self.shift_cursor_lines(&blocks, |_| -1);
+        Ok(())
+    }
+
+    /// Swaps the lines touched by each cursor with the line below.
+    ///
+    /// Lines already at the bottom stay put.
+    pub fn move_lines_down(&mut self) -> CoreResult<()> {
+        let len_lines = self.text_line_count();
+        let extend = |run: Range<usize>| (run.end < len_lines).then(|| run.start..run.end + 1);
+        let blocks = self.replace_line_blocks(extend, |mut lines| {
+            lines.rotate_right(1);
+            lines
+        })?;
+        self.shift_cursor_lines(&blocks, |_| 1);
+        Ok(())
+    }
+
+    /// Joins the lines touched by each cursor into one, or a single line
+    /// with the next. Each cursor lands on its last join.
+    pub fn join_lines(&mut self) -> CoreResult<()> {
+        let len_lines = self.text_line_count();
+        let extend = |run: Range<usize>| match run.len() {
+            1 => (run.end < len_lines).then(|| run.start..run.end + 1),
+            _ => Some(run),
+        };
+        let mut columns = Vec::new();
+        let blocks = self.replace_line_blocks(extend, |lines| {
+            let (joined, column) = join_lines(&lines);
+            columns.push(column);
+            vec![joined]
+        })?;
+        let positions = blocks
+            .iter()
+            .zip(columns)
+            .map(|(b, column)| Position::new(b.new.start, column));
+        self.set_cursors(positions.map(|pos| (pos, pos)));
+        self.buffer.record_cursors_after(&self.cursors);
+        Ok(())
+    }
+
+    /// Sorts the lines touched by each cursor, then selects them.
+    pub fn sort_lines(&mut self, sort: LineSort) -> CoreResult<()> {
+        self.rewrite_lines(|mut lines| {
+            sort_lines(&mut lines, sort);
+            lines
+        })
+    }
+
+    /// Removes repeated lines among those touched by each cursor,
+    /// keeping the first of each, then selects what's left.
+    pub fn unique_lines(&mut self) -> CoreResult<()> {
+        self.rewrite_lines(|mut lines| {
+            unique_lines(&mut lines);
+            lines
+        })
+    }
+
+    /// Reverses the order of the lines touched by each cursor, then
+    /// selects them.
+    pub fn reverse_lines(&mut self) -> CoreResult<()> {
+        self.rewrite_lines(|mut lines| {
+            lines.reverse();
+            lines
+        })
+    }
+
+    /// Shuffles the lines touched by each cursor, then selects them.
+    pub fn shuffle_lines(&mut self) -> CoreResult<()> {
+        let mut seed = RandomState::new().hash_one(self.buffer.version());
+        self.rewrite_lines(|mut lines| {
+            shuffle_lines(&mut lines, seed);
+            seed = seed.wrapping_add(1);
+            lines
+        })
+    }
+
+    /// Replaces the lines touched by each cursor and selects the result.
+    fn rewrite_lines(
+        &mut self,
+        transform: impl FnMut(Vec<String>) -> Vec<String>,
+    ) -> CoreResult<()> {
+        let blocks = self.replace_line_blocks(Some, transform)?;
+        let mut selections = Vec::with_capacity(blocks.len());
+        for block in blocks.iter().filter(|b| !b.new.is_empty()) {
+            let last = block.new.end - 1;
+            let len = self
+                .buffer
+                .line(last)?
+                .trim_end_matches('\n')
+                .chars()
+                .count();
+            selections.push((Position::new(block.new.start, 0), Position::new(last, len)));
+        }
+        self.set_cursors(selections.into_iter());
+        self.buffer.record_cursors_after(&self.cursors);
+        Ok(())
+    }
+
+    /// Replaces the lines touched by the cursors as one undo step.
+    ///
+    /// Touched lines are taken in runs of consecutive lines. `extend`
+    /// widens a run (say, to take in the line below) or skips it by
+    /// returning `None`; `transform` gets the lines of each run in
+    /// order, without their `\n`, and returns the new ones.
+    ///
+    /// Blocks never take in the empty line after a trailing `\n`. If no
+    /// line changes, nothing is recorded and no blocks are returned.
+    fn replace_line_blocks(
+        &mut self,
+        extend: impl Fn(Range<usize>) -> Option<Range<usize>>,
+        mut transform: impl FnMut(Vec<String>) -> Vec<String>,
+    ) -> CoreResult<Vec<LineBlock>> {
+        self.ensure_writable()?;
+        let len_lines = self.text_line_count();
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for line in self.selected_lines() {
+            match runs.last_mut() {
+                Some(run) if run.end == line => run.end += 1,
+                _ => runs.push(line..line + 1),
+            }
+        }
+
+        let mut blocks: Vec<LineBlock> = Vec::with_capacity(runs.len());
+        let mut edits = Vec::new();
+        let mut offset = 0isize;
+        for run in runs {
+            let Some(old) = extend(run) else {
+                continue;
+            };
+            let old = old.start..old.end.min(len_lines);
+            if old.is_empty() || blocks.last().is_some_and(|b| b.old.end > old.start) {
+                continue;
+            }
+            let start = self
+                .buffer
+                .position_to_char_idx(Position::new(old.start, 0))?;
+            let end = if old.end < self.buffer.len_lines() {
+                self.buffer
+                    .position_to_char_idx(Position::new(old.end, 0))?
+            } else {
+                self.buffer.len_chars()
+            };
+            let text = self.buffer.slice(start..end)?;
+            let has_newline = text.ends_with('\n');
+            let lines: Vec<String> = text
+                .strip_suffix('\n')
+                .unwrap_or(&text)
+                .split('\n')
+                .map(String::from)
+                .collect();
+
+            let new_lines = transform(lines.clone());
+            let new_start = old.start.saturating_add_signed(offset);
+            offset += new_lines.len() as isize - old.len() as isize;
+            let block = LineBlock {
+                old,
+                new: new_start..new_start + new_lines.len(),
+            };
+            blocks.push(block);
+            if new_lines == lines {
+                continue;
+            }
+            if new_lines.is_empty() {
+                // The last line has no `\n` of its own: take the one before it
+                let start = if !has_newline && start > 0 {
+                    start - 1
+                } else {
+                    start
+                };
+                edits.push((start..end, String::new()));
+            } else {
+                let mut replacement = new_lines.join("\n");
+                if has_newline {
+                    replacement.push('\n');
+                }
+                edits.push((start..end, replacement));
+            }
+        }
+
+        if edits.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.edit_group(|doc| {
+            for (range, text) in edits.into_iter().rev() {
+                doc.buffer.replace(range, &text)?;
+            }
+            Ok(())
+        })?;
+        Ok(blocks)
+    }
+
+    /// Moves the cursors with their lines after
+    /// [`replace_line_blocks`](Self::replace_line_blocks).
+    ///
+    /// A position inside a block goes where the block went, plus
+    /// `shift` lines; one after every block moves by how much they grew.
+    /// A selection ending at column 0 of the line after a block belongs
+    /// to the block.
+    fn shift_cursor_lines(&mut self, blocks: &[LineBlock], shift: impl Fn(&LineBlock) -> isize) {
+        let map = |pos: Position| {
+            let block = blocks
+                .iter()
+                .find(|b| b.old.contains(&pos.line))
+                .or_else(|| {
+                    blocks
+                        .iter()
+                        .find(|b| pos.column == 0 && b.old.end == pos.line)
+                });
+            let line = match block {
+                Some(b) => (b.new.start + pos.line - b.old.start).saturating_add_signed(shift(b)),
+                None => {
+                    let grown: isize = blocks
+                        .iter()
+                        .filter(|b| b.old.end <= pos.line)
+                        .map(|b| b.new.len() as isize - b.old.len() as isize)
+                        .sum();
+                    pos.line.saturating_add_signed(grown)
+                }
+            };
+            Position::new(line, pos.column)
+        };
+        for cursor in self.cursors.all_mut() {
+            cursor.position = map(cursor.position);
+            cursor.anchor = cursor.anchor.map(map);
+        }
+        self.cursors.merge_overlapping();
+        self.buffer.record_cursors_after(&self.cursors);
+    }
+
+    /// Replaces all cursors with one per `(anchor, position)` pair; the
+    /// last becomes the primary.
+    fn set_cursors(&mut self, selections: impl Iterator<Item = (Position, Position)>) {
+        let mut cursors: Option<MultiCursor> = None;
+        for (anchor, position) in selections {
+            match cursors.as_mut() {
+                Some(cursors) => {
+                    cursors.add_selection(anchor, position);
+                }
+                None => {
+                    let mut cursor = Cursor::new(anchor);
+                    cursor.select_to(position);
+                    cursors = Some(MultiCursor::from_cursor(cursor));
+                }
+            }
+        }
+        if let Some(mut cursors) = cursors {
+            for cursor in cursors.all_mut() {
+                if !cursor.has_selection() {
+                    cursor.clear_selection();
+                }
+            }
+            self.cursors = cursors;
+        }
     }
 
     // ==================== Undo/Redo ====================
 
-    /// Undoes the last action.
+    /// Undoes the last action, restoring the cursors from before it.
     pub fn undo(&mut self) -> CoreResult<()> {
-        self.buffer.undo()?;
+        self.ensure_writable()?;
+        let step = self.buffer.undo()?;
+        self.restore_cursors(step);
         Ok(())
     }
 
     /// Redoes the last undone action.
     pub fn redo(&mut self) -> CoreResult<()> {
-        self.buffer.redo()?;
+        self.ensure_writable()?;
+        let step = self.buffer.redo()?;
+        self.restore_cursors(step);
         Ok(())
     }
 
+    /// Moves to another state of the undo tree.
+    ///
+    /// Returns false if there was nowhere to go (or the document is
+    /// read-only).
+    pub fn jump_history(&mut self, target: UndoTarget) -> bool {
+        if self.read_only {
+            return false;
+        }
+        match self.buffer.jump_history(target) {
+            Some(step) => {
+                self.restore_cursors(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs `f` as a single undo step.
+    ///
+    /// The cursors before and after are recorded so undo and redo can
+    /// put them back. Groups nest, so grouped edits may call each other.
+    fn edit_group<T>(&mut self, f: impl FnOnce(&mut Self) -> CoreResult<T>) -> CoreResult<T> {
+        self.buffer.record_cursors(&self.cursors);
+        self.buffer.begin_undo_group();
+        let result = f(self);
+        self.buffer.end_undo_group();
+        self.buffer.record_cursors_after(&self.cursors);
+        result
+    }
+
+    /// Puts the cursors back after undo/redo.
+    ///
+    /// Uses the recorded cursors if there are any, otherwise places a
+    /// single cursor after the step's last edit.
+    fn restore_cursors(&mut self, step: UndoStep) {
+        if let Some(cursors) = step.cursors {
+            self.cursors = cursors;
+            return;
+        }
+        if let Some(idx) = step.end_position()
+            && let Ok(pos) = self.buffer.char_idx_to_position(idx)
+        {
+            self.cursors.collapse_to_primary();
+            self.cursors.primary_mut().move_to(pos);
+        }
+        self.clamp_cursor_to_buffer();
+    }
+
+    /// Returns the tips of all undo branches, oldest first.
+    pub fn undo_branches(&self) -> Vec<UndoBranch> {
+        self.buffer.undo_branches()
+    }
+
     // ==================== Cursor Movement ====================
 
-    /// Moves the cursor up by n lines.
+    /// Moves the cursor up by n lines, keeping its display column.
     pub fn move_cursor_up(&mut self, n: usize) {
+        let goal = self.goal_display_column();
         self.cursors.primary_mut().move_up(n);
-        self.clamp_cursor_to_line();
+        self.apply_goal_display_column(goal);
     }
 
-    /// Moves the cursor down by n lines.
+    /// Moves the cursor down by n lines, keeping its display column.
     pub fn move_cursor_down(&mut self, n: usize) {
+        let goal = self.goal_display_column();
         let max_line = self.buffer.len_lines().saturating_sub(1);
         self.cursors.primary_mut().move_down(n, max_line);
-        self.clamp_cursor_to_line();
+        self.apply_goal_display_column(goal);
     }
 
-    /// Moves the cursor left by n characters.
+    /// Moves the cursor left by n grapheme clusters.
     pub fn move_cursor_left(&mut self, n: usize) {
-        self.cursors.primary_mut().move_left(n);
-        self.clamp_cursor_to_line();
+        let idx = self.cursor_char_idx();
+        let idx = (0..n).fold(idx, |idx, _| self.buffer.prev_grapheme_boundary(idx));
+        self.move_cursor_to_char_idx(idx);
     }
 
-    /// Moves the cursor right by n characters.
+    /// Moves the cursor right by n grapheme clusters.
     pub fn move_cursor_right(&mut self, n: usize) {
+        let idx = self.cursor_char_idx();
+        let idx = (0..n).fold(idx, |idx, _| self.buffer.next_grapheme_boundary(idx));
+        self.move_cursor_to_char_idx(idx);
+    }
+
+    /// Moves the cursor to the start of the previous word.
+    pub fn move_word_left(&mut self, kind: WordKind) {
+        let idx = self.buffer.prev_word_start(self.cursor_char_idx(), kind);
+        self.move_cursor_to_char_idx(idx);
+    }
+
+    /// Moves the cursor to the end of the next word.
+    pub fn move_word_right(&mut self, kind: WordKind) {
+        let idx = self.buffer.next_word_end(self.cursor_char_idx(), kind);
+        self.move_cursor_to_char_idx(idx);
+    }
+
+    /// Returns the display column vertical movement should aim for.
+    ///
+    /// Moving through a short line doesn't lose the original column.
+    fn goal_display_column(&self) -> usize {
+        let cursor = self.cursors.primary();
+        cursor.preferred_column.unwrap_or_else(|| {
+            self.buffer
+                .display_column(cursor.position, self.tab_config.width)
+                .unwrap_or(cursor.position.column)
+        })
+    }
+
+    /// Puts the cursor at the goal display column on its (new) line.
+    fn apply_goal_display_column(&mut self, goal: usize) {
+        let line = self.cursor_position().line;
+        let column = self
+            .buffer
+            .column_at_display(line, goal, self.tab_config.width)
+            .unwrap_or(0);
+        let cursor = self.cursors.primary_mut();
+        cursor.position.column = column;
+        cursor.preferred_column = Some(goal);
+        self.clamp_cursor_to_line();
+    }
+
+    /// Returns the length of a line in chars, not counting its `\n`.
+    fn line_content_len(&self, line: usize) -> usize {
+        let text = self.buffer.line(line).unwrap_or_default();
+        text.strip_suffix('\n').unwrap_or(&text).chars().count()
+    }
+
+    fn cursor_char_idx(&self) -> usize {
         let pos = self.cursor_position();
-        let line_len = self.buffer.line_len(pos.line).unwrap_or(0);
-        let is_last = pos.line >= self.buffer.len_lines().saturating_sub(1);
-        self.cursors.primary_mut().move_right(n, line_len, is_last);
+        self.buffer
+            .position_to_char_idx(pos)
+            .unwrap_or_else(|_| self.buffer.len_chars())
+    }
+
+    fn move_cursor_to_char_idx(&mut self, idx: usize) {
+        if let Ok(pos) = self.buffer.char_idx_to_position(idx) {
+            self.cursors.primary_mut().move_to(pos);
+        }
         self.clamp_cursor_to_line();
     }
 
     /// Moves cursor to the start of the current line.
     pub fn move_to_line_start(&mut self) {
         let pos = self.cursor_position();
-        self.cursors.primary_mut().move_to(Position::new(pos.line, 0));
+        self.cursors
+            .primary_mut()
+            .move_to(Position::new(pos.line, 0));
     }
 
     /// Moves cursor to the end of the current line.
@@ -436,7 +1676,9 @@ impl Document {
             } else {
                 0
             };
-            self.cursors.primary_mut().move_to(Position::new(pos.line, col));
+            self.cursors
+                .primary_mut()
+                .move_to(Position::new(pos.line, col));
         }
     }
 
@@ -446,14 +1688,178 @@ impl Document {
         self.clamp_cursor_to_line();
     }
 
+    // ==================== Multiple Cursors ====================
+
+    /// Returns true if multiple cursors are allowed.
+    pub fn multi_cursor(&self) -> bool {
+        self.multi_cursor
+    }
+
+    /// Allows or forbids multiple cursors (`EditorConfig::multi_cursor`).
+    ///
+    /// Forbidding them drops all but the primary cursor.
+    pub fn set_multi_cursor(&mut self, enabled: bool) {
+        self.multi_cursor = enabled;
+        if !enabled {
+            self.cursors.collapse_to_primary();
+        }
+    }
+
+    /// Adds a cursor, which becomes the primary one.
+    ///
+    /// Returns false if multiple cursors are disabled or a cursor is
+    /// already there.
+    pub fn add_cursor(&mut self, pos: Position) -> bool {
+        if !self.multi_cursor {
+            return false;
+        }
+        let Ok(idx) = self.buffer.position_to_char_idx(pos) else {
+            return false;
+        };
+        let Ok(pos) = self.buffer.char_idx_to_position(idx) else {
+            return false;
+        };
+        let added = self.cursors.add(pos);
+        self.cursors.merge_overlapping();
+        added
+    }
+
+    /// Adds a cursor on the line above the topmost cursor, at the same
+    /// display column.
+    pub fn add_cursor_above(&mut self) -> bool {
+        let top = self.cursors.all()[0].position;
+        top.line > 0 && self.add_cursor_at_display_column(top, top.line - 1)
+    }
+
+    /// Adds a cursor on the line below the bottommost cursor, at the
+    /// same display column.
+    pub fn add_cursor_below(&mut self) -> bool {
+        let bottom = self.cursors.all()[self.cursors.len() - 1].position;
+        bottom.line + 1 < self.buffer.len_lines()
+            && self.add_cursor_at_display_column(bottom, bottom.line + 1)
+    }
+
+    fn add_cursor_at_display_column(&mut self, from: Position, line: usize) -> bool {
+        let width = self.tab_config.width;
+        let Ok(display) = self.buffer.display_column(from, width) else {
+            return false;
+        };
+        let column = self
+            .buffer
+            .column_at_display(line, display, width)
+            .unwrap_or(0);
+        self.add_cursor(Position::new(line, column))
+    }
+
+    /// Removes all cursors but the primary one.
+    pub fn collapse_cursors(&mut self) {
+        self.cursors.collapse_to_primary();
+    }
+
+    // ==================== Block Selection ====================
+
+    /// Selects a rectangle of display columns (e.g. from an alt+drag),
+    /// with one cursor per line.
+    ///
+    /// Returns false if multiple cursors are disabled.
+    pub fn select_block(&mut self, block: BlockSelection) -> CoreResult<bool> {
+        if !self.multi_cursor {
+            return Ok(false);
+        }
+        self.cursors = block.to_cursors(&self.buffer, self.tab_config.width)?;
+        self.block = Some((block, self.cursors.clone()));
+        Ok(true)
+    }
+
+    /// Grows or shrinks the block selection by moving its active corner
+    /// by whole lines and display columns.
+    ///
+    /// Starts a block at the primary cursor if there is none.
+    pub fn extend_block(&mut self, lines: isize, columns: isize) -> CoreResult<bool> {
+        let block = match self.active_block() {
+            Some(block) => block,
+            None => {
+                let pos = self.cursor_position();
+                let column = self.buffer.display_column(pos, self.tab_config.width)?;
+                let corner = Position::new(pos.line, column);
+                BlockSelection::new(corner, corner)
+            }
+        };
+        let max_line = self.buffer.len_lines().saturating_sub(1);
+        let active = Position::new(
+            block.active.line.saturating_add_signed(lines).min(max_line),
+            block.active.column.saturating_add_signed(columns),
+        );
+        self.select_block(BlockSelection::new(block.anchor, active))
+    }
+
+    /// Returns the block selection, if the cursors still come from it.
+    pub fn block_selection(&self) -> Option<BlockSelection> {
+        self.active_block()
+    }
+
+    fn active_block(&self) -> Option<BlockSelection> {
+        self.block
+            .as_ref()
+            .filter(|(_, cursors)| *cursors == self.cursors)
+            .map(|(block, _)| *block)
+    }
+
+    /// Keeps a block selection going after an edit, as a zero-wide block
+    /// at the primary cursor's new display column.
+    fn update_block(&mut self, block: Option<BlockSelection>) {
+        self.block = block.and_then(|block| {
+            let column = self
+                .buffer
+                .display_column(self.cursor_position(), self.tab_config.width)
+                .ok()?;
+            Some((block.collapse_to(column), self.cursors.clone()))
+        });
+    }
+
+    /// Returns the spaces that pad a cursor's line out to the block.
+    fn block_padding(&self, block: Option<BlockSelection>, cursor: &Cursor) -> CoreResult<String> {
+        let Some(block) = block else {
+            return Ok(String::new());
+        };
+        if cursor.has_selection() {
+            return Ok(String::new());
+        }
+        let column = self
+            .buffer
+            .display_column(cursor.position, self.tab_config.width)?;
+        Ok(" ".repeat(block.columns().start.saturating_sub(column)))
+    }
+
+    /// True if a block deletion should skip this cursor: its line has
+    /// nothing selected, or doesn't reach a zero-wide block.
+    fn outside_block(&self, block: Option<BlockSelection>, cursor: &Cursor) -> CoreResult<bool> {
+        let Some(block) = block else {
+            return Ok(false);
+        };
+        if !block.is_empty() {
+            return Ok(!cursor.has_selection());
+        }
+        let column = self
+            .buffer
+            .display_column(cursor.position, self.tab_config.width)?;
+        Ok(column != block.columns().start)
+    }
+
     /// Clamps cursor column to current line length.
+    ///
+    /// The cursor can sit on the line's last grapheme (its `\n`, if any)
+    /// but not past it.
     fn clamp_cursor_to_line(&mut self) {
         let pos = self.cursor_position();
-        if let Ok(line_len) = self.buffer.line_len(pos.line) {
-            let max_col = line_len.saturating_sub(1);
-            if pos.column > max_col && line_len > 0 {
-                self.cursors.primary_mut().position.column = max_col;
-            }
+        if let Ok(line_len) = self.buffer.line_len(pos.line)
+            && line_len > 0
+            && pos.column >= line_len
+            && let Ok(line_start) = self.buffer.position_to_char_idx(Position::new(pos.line, 0))
+        {
+            let line_end = line_start + line_len;
+            let max_col = self.buffer.prev_grapheme_boundary(line_end) - line_start;
+            self.cursors.primary_mut().position.column = max_col;
         }
     }
 
@@ -476,6 +1882,382 @@ impl Document {
     pub fn select_to(&mut self, pos: Position) {
         self.cursors.primary_mut().select_to(pos);
     }
+
+    /// Selects a character range, leaving the cursor at its end.
+    pub fn select_char_range(&mut self, range: std::ops::Range<usize>) -> CoreResult<()> {
+        let start = self.buffer.char_idx_to_position(range.start)?;
+        let end = self.buffer.char_idx_to_position(range.end)?;
+        let cursor = self.cursors.primary_mut();
+        cursor.move_to(start);
+        cursor.select_to(end);
+        Ok(())
+    }
+
+    /// Selects the word under the cursor.
+    ///
+    /// Does nothing on an empty line or if there is already a selection.
+    pub fn select_word(&mut self) -> CoreResult<()> {
+        if self.cursors.primary().has_selection() {
+            return Ok(());
+        }
+        self.select_text_object(TextObject::Word(WordKind::Word), ObjectExtent::Inner)?;
+        Ok(())
+    }
+
+    /// Selects a text object at every cursor.
+    ///
+    /// Cursors with no such object around them are left alone. Selecting
+    /// a bracket object that is already selected takes the next pair out.
+    /// Returns false if no cursor found one.
+    pub fn select_text_object(
+        &mut self,
+        object: TextObject,
+        extent: ObjectExtent,
+    ) -> CoreResult<bool> {
+        let mut found = Vec::with_capacity(self.cursors.len());
+        for cursor in self.cursors.all() {
+            let (start, end) = cursor
+                .selection_range()
+                .unwrap_or((cursor.position, cursor.position));
+            let range =
+                self.buffer.position_to_char_idx(start)?..self.buffer.position_to_char_idx(end)?;
+            let object = match self.buffer.text_object(range, object, extent) {
+                Some(range) => Some((
+                    self.buffer.char_idx_to_position(range.start)?,
+                    self.buffer.char_idx_to_position(range.end)?,
+                )),
+                None => None,
+            };
+            found.push(object);
+        }
+
+        let any = found.iter().any(Option::is_some);
+        for (cursor, object) in self.cursors.all_mut().iter_mut().zip(found) {
+            if let Some((start, end)) = object {
+                cursor.move_to(start);
+                cursor.select_to(end);
+            }
+        }
+        self.cursors.merge_overlapping();
+        Ok(any)
+    }
+
+    // ==================== Brackets ====================
+
+    /// Finds the bracket at or just before the primary cursor and its
+    /// partner.
+    ///
+    /// Scans the plain text; with the string and comment ranges of a
+    /// parse, use [`TextBuffer::matching_bracket_with`] to skip those.
+    pub fn matching_bracket(&self) -> Option<BracketMatch> {
+        self.buffer.matching_bracket(self.cursor_char_idx())
+    }
+
+    /// Moves every cursor next to a bracket onto its partner.
+    ///
+    /// Returns false if no cursor was next to a matched bracket.
+    pub fn jump_to_matching_bracket(&mut self) -> CoreResult<bool> {
+        self.apply_bracket_matches(|cursor, m, buffer| {
+            cursor.move_to(buffer.char_idx_to_position(m.partner)?);
+            Ok(())
+        })
+    }
+
+    /// Selects from every cursor's bracket through its partner,
+    /// brackets included.
+    ///
+    /// Returns false if no cursor was next to a matched bracket.
+    pub fn select_to_matching_bracket(&mut self) -> CoreResult<bool> {
+        self.apply_bracket_matches(|cursor, m, buffer| {
+            let range = m.range();
+            let (from, to) = if m.bracket < m.partner {
+                (range.start, range.end)
+            } else {
+                (range.end, range.start)
+            };
+            cursor.move_to(buffer.char_idx_to_position(from)?);
+            cursor.select_to(buffer.char_idx_to_position(to)?);
+            Ok(())
+        })
+    }
+
+    /// Runs `apply` on each cursor that has a matched bracket.
+    fn apply_bracket_matches(
+        &mut self,
+        apply: impl Fn(&mut Cursor, BracketMatch, &TextBuffer) -> CoreResult<()>,
+    ) -> CoreResult<bool> {
+        let mut matches = Vec::with_capacity(self.cursors.len());
+        for cursor in self.cursors.all() {
+            let char_idx = self.buffer.position_to_char_idx(cursor.position)?;
+            matches.push(self.buffer.matching_bracket(char_idx));
+        }
+
+        let any = matches.iter().any(Option::is_some);
+        for (cursor, found) in self.cursors.all_mut().iter_mut().zip(matches) {
+            if let Some(m) = found {
+                apply(cursor, m, &self.buffer)?;
+            }
+        }
+        self.cursors.merge_overlapping();
+        Ok(any)
+    }
+
+    // ==================== Marks ====================
+
+    /// Sets mark `name` (`a`–`z`) at the primary cursor, replacing any
+    /// mark of that name.
+    pub fn set_mark(&mut self, name: char) -> CoreResult<()> {
+        if !crate::marks::is_local_mark(name) {
+            return Err(CoreError::InvalidOperation(format!(
+                "Not a local mark: {name:?}"
+            )));
+        }
+        let anchor = self.anchor_cursor()?;
+        if let Some(old) = self.marks.insert(name, anchor) {
+            self.buffer.remove_anchor(old);
+        }
+        Ok(())
+    }
+
+    /// Returns where mark `name` is now.
+    pub fn mark(&self, name: char) -> Option<Position> {
+        self.buffer.anchor_position(*self.marks.get(&name)?)
+    }
+
+    /// Anchors the primary cursor's position so it follows edits.
+    ///
+    /// The caller owns the anchor and releases it with
+    /// [`TextBuffer::remove_anchor`].
+    pub fn anchor_cursor(&mut self) -> CoreResult<AnchorId> {
+        self.anchor_at(self.cursor_position())
+    }
+
+    /// Anchors `position`, clamped to the text: a saved position may be
+    /// from before the file shrank.
+    pub fn anchor_at(&mut self, position: Position) -> CoreResult<AnchorId> {
+        let last_line = self.buffer.len_lines().saturating_sub(1);
+        let char_idx = if position.line > last_line {
+            self.buffer.len_chars()
+        } else {
+            let line_start = self
+                .buffer
+                .position_to_char_idx(Position::new(position.line, 0))?;
+            let line_end = self
+                .buffer
+                .position_to_char_idx(Position::new(position.line + 1, 0))
+                .map_or(self.buffer.len_chars(), |next| next - 1);
+            (line_start + position.column).min(line_end)
+        };
+        Ok(self.buffer.add_anchor(char_idx, Affinity::Backward)?)
+    }
+
+    // ==================== Search ====================
+
+    /// Selects the next match of `query` after the cursor.
+    ///
+    /// Returns false if the document has no matches.
+    pub fn find_next(&mut self, query: &SearchQuery) -> CoreResult<bool> {
+        let cursor = self.cursors.primary();
+        let mut from = match cursor.selection_range() {
+            // An empty match (`^`, `\b`) stays selected as an empty range:
+            // step past it, or the same match would be found forever
+            Some((start, end)) if start == end => self.buffer.position_to_char_idx(end)? + 1,
+            Some((_, end)) => self.buffer.position_to_char_idx(end)?,
+            None => self.buffer.position_to_char_idx(cursor.position)?,
+        };
+        if from > self.buffer.len_chars() {
+            from = 0;
+        }
+        match self.buffer.search_next(query, from) {
+            Some(range) => {
+                self.select_char_range(range)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Selects the previous match of `query` before the cursor.
+    ///
+    /// Returns false if the document has no matches.
+    pub fn find_previous(&mut self, query: &SearchQuery) -> CoreResult<bool> {
+        let cursor = self.cursors.primary();
+        let before = cursor
+            .selection_range()
+            .map_or(cursor.position, |(start, _)| start);
+        let before = self.buffer.position_to_char_idx(before)?;
+        match self.buffer.search_prev(query, before) {
+            Some(range) => {
+                self.select_char_range(range)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Replaces every match of `query`, expanding capture groups.
+    ///
+    /// Returns the number of replacements made.
+    pub fn replace_all(&mut self, query: &SearchQuery, replacement: &str) -> CoreResult<usize> {
+        self.ensure_writable()?;
+        self.buffer.record_cursors(&self.cursors);
+        let count = self.buffer.replace_all_matches(query, replacement)?;
+        if count > 0 {
+            self.cursors.collapse_to_primary();
+            self.cursors.primary_mut().clear_selection();
+            self.clamp_cursor_to_buffer();
+            self.buffer.record_cursors_after(&self.cursors);
+        }
+        Ok(count)
+    }
+
+    // ==================== Occurrences ====================
+
+    /// Adds a selection at the next occurrence of the primary selection
+    /// (ctrl+d), wrapping around at the end of the document.
+    ///
+    /// Without a selection this first selects the word under the cursor.
+    /// Occurrences that already have a cursor are passed over. Returns
+    /// false if nothing was selected or added.
+    pub fn add_next_occurrence(&mut self, options: SearchOptions) -> CoreResult<bool> {
+        if !self.cursors.primary().has_selection() {
+            self.select_word()?;
+            return Ok(self.cursors.primary().has_selection());
+        }
+        if !self.multi_cursor {
+            return Ok(false);
+        }
+        let Some(range) = self.next_free_occurrence(options)? else {
+            return Ok(false);
+        };
+        let anchor = self.buffer.char_idx_to_position(range.start)?;
+        let position = self.buffer.char_idx_to_position(range.end)?;
+        Ok(self.cursors.add_selection(anchor, position))
+    }
+
+    /// Moves the primary selection on to the next occurrence, leaving
+    /// the current one unselected.
+    ///
+    /// Without a selection this selects the word under the cursor.
+    pub fn skip_occurrence(&mut self, options: SearchOptions) -> CoreResult<bool> {
+        if !self.cursors.primary().has_selection() {
+            self.select_word()?;
+            return Ok(self.cursors.primary().has_selection());
+        }
+        let Some(range) = self.next_free_occurrence(options)? else {
+            return Ok(false);
+        };
+        if self.cursors.remove_primary() {
+            let anchor = self.buffer.char_idx_to_position(range.start)?;
+            let position = self.buffer.char_idx_to_position(range.end)?;
+            self.cursors.add_selection(anchor, position);
+        } else {
+            self.select_char_range(range)?;
+        }
+        Ok(true)
+    }
+
+    /// Selects every occurrence of the primary selection (or of the word
+    /// under the cursor), one cursor each.
+    ///
+    /// Returns the number of selections.
+    pub fn select_all_occurrences(&mut self, options: SearchOptions) -> CoreResult<usize> {
+        if !self.cursors.primary().has_selection() {
+            self.select_word()?;
+        }
+        let Some(query) = self.selection_query(options)? else {
+            return Ok(0);
+        };
+        if !self.multi_cursor {
+            return Ok(1);
+        }
+
+        // Add the current selection last so it stays primary
+        let current = self.cursor_range(self.cursors.primary())?;
+        let mut ranges: Vec<Range<usize>> = self
+            .buffer
+            .search_all(&query)
+            .into_iter()
+            .filter(|range| *range != current)
+            .collect();
+        ranges.push(current);
+
+        let selections = ranges
+            .into_iter()
+            .map(|range| {
+                Ok((
+                    self.buffer.char_idx_to_position(range.start)?,
+                    self.buffer.char_idx_to_position(range.end)?,
+                ))
+            })
+            .collect::<CoreResult<Vec<_>>>()?;
+        self.set_cursors(selections.into_iter());
+        Ok(self.cursors.len())
+    }
+
+    /// Builds a literal query for the primary selection's text.
+    fn selection_query(&self, options: SearchOptions) -> CoreResult<Option<SearchQuery>> {
+        let cursor = self.cursors.primary();
+        if !cursor.has_selection() {
+            return Ok(None);
+        }
+        let text = self.buffer.slice(self.cursor_range(cursor)?)?;
+        let options = SearchOptions {
+            regex: false,
+            multiline: text.contains('\n'),
+            ..options
+        };
+        Ok(Some(SearchQuery::new(&text, options)?))
+    }
+
+    /// Finds the next occurrence of the primary selection that no
+    /// cursor covers yet.
+    fn next_free_occurrence(&self, options: SearchOptions) -> CoreResult<Option<Range<usize>>> {
+        let Some(query) = self.selection_query(options)? else {
+            return Ok(None);
+        };
+        let taken = self
+            .cursors
+            .all()
+            .iter()
+            .map(|c| self.cursor_range(c))
+            .collect::<CoreResult<Vec<_>>>()?;
+        let mut from = self.cursor_range(self.cursors.primary())?.end;
+        // Each miss lands on a different cursor, so this is enough to
+        // go all the way around
+        for _ in 0..=taken.len() {
+            let Some(range) = self.buffer.search_next(&query, from) else {
+                break;
+            };
+            if !taken.contains(&range) {
+                return Ok(Some(range));
+            }
+            from = range.end;
+        }
+        Ok(None)
+    }
+
+    /// Moves the cursor back inside the buffer after a large edit.
+    fn clamp_cursor_to_buffer(&mut self) {
+        let pos = self.cursor_position();
+        let max_line = self.buffer.len_lines().saturating_sub(1);
+        let line = pos.line.min(max_line);
+        let line_len = self.buffer.line_len(line).unwrap_or(0);
+        self.cursors
+            .primary_mut()
+            .move_to(Position::new(line, pos.column.min(line_len)));
+        self.clamp_cursor_to_line();
+    }
+}
+
+/// True if `bytes` is over a size limit in MB (0 means no limit).
+fn exceeds_megabytes(bytes: u64, limit: usize) -> bool {
+    limit > 0 && bytes > limit as u64 * 1024 * 1024
+}
+
+/// Reads a file as it is on disk, for comparing against the buffer.
+fn read_file(path: &Path, encoding: TextEncoding) -> CoreResult<TextBuffer> {
+    Ok(FileLoader::open_with_encoding(path, encoding)?.finish(BufferConfig::default())?)
 }
 
 impl Default for Document {
@@ -592,3 +2374,793 @@ impl Default for DocumentManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_encoding_fallback_and_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("legacy.txt");
+        // "Привет" in Windows-1251
+        std::fs::write(&path, b"\xCF\xF0\xE8\xE2\xE5\xF2").unwrap();
+
+        let mut config = Config::default();
+        config.files.encoding = "windows-1251".to_string();
+        let mut doc = Document::from_file_with_config(&path, &config).unwrap();
+        assert_eq!(doc.text(), "Привет");
+        assert_eq!(doc.encoding().name(), "windows-1251");
+
+        // Detection alone would have picked Windows-1252
+        doc.reopen_with_encoding("koi8-r").unwrap();
+        assert_eq!(doc.encoding().name(), "KOI8-R");
+        assert_eq!(doc.text(), "оПХБЕР");
+        assert!(!doc.is_modified());
+
+        // Reopening is undoable, and refused over unsaved changes
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "Привет");
+        assert!(matches!(
+            doc.reopen_with_encoding("utf-8"),
+            Err(CoreError::InvalidOperation(_))
+        ));
+        doc.redo().unwrap();
+        assert_eq!(doc.text(), "оПХБЕР");
+        doc.buffer_mut().mark_saved();
+
+        doc.convert_encoding("utf-8").unwrap();
+        assert!(doc.is_modified());
+    }
+
+    #[test]
+    fn test_marks_survive_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("marks.txt");
+        std::fs::write(&path, b"one\n\xCF\xF0\nlast\n").unwrap();
+        let mut config = Config::default();
+        config.files.encoding = "windows-1251".to_string();
+        let mut doc = Document::from_file_with_config(&path, &config).unwrap();
+
+        doc.move_cursor_to(Position::new(2, 2));
+        doc.set_mark('a').unwrap();
+        doc.move_cursor_to(Position::new(0, 1));
+        doc.set_mark('b').unwrap();
+        let version = doc.snapshot().version();
+
+        // Only the middle line changes; the marks keep their anchors
+        doc.reopen_with_encoding("koi8-r").unwrap();
+        assert_eq!(doc.text(), "one\nоП\nlast\n");
+        assert!(doc.snapshot().version() > version);
+        doc.move_cursor_to(Position::new(1, 0));
+        doc.set_mark('c').unwrap();
+        assert_eq!(doc.mark('a'), Some(Position::new(2, 2)));
+        assert_eq!(doc.mark('b'), Some(Position::new(0, 1)));
+        assert_eq!(doc.mark('c'), Some(Position::new(1, 0)));
+    }
+
+    #[test]
+    fn test_large_and_oversized_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("big.rs");
+        let line = "let x = 1;\n";
+        std::fs::write(&path, line.repeat(150_000)).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len();
+
+        let mut config = Config::default();
+        config.editor.word_wrap = true;
+        config.files.large_file_threshold = 1;
+        let mut updates = Vec::new();
+        let mut doc = Document::load(&path, &config, |loaded, total| {
+            updates.push((loaded, total))
+        })
+        .unwrap();
+        assert!(updates.len() > 1);
+        assert_eq!(updates.last(), Some(&(size, size)));
+        assert_eq!(doc.buffer().len_lines(), 150_001);
+
+        assert!(doc.is_large_file() && !doc.is_read_only());
+        assert!(!doc.syntax_highlighting());
+        assert!(!doc.word_wrap(&config));
+        // No coalescing: each keystroke is its own undo step
+        doc.insert_at_cursor("a").unwrap();
+        doc.insert_at_cursor("b").unwrap();
+        doc.undo().unwrap();
+        assert!(doc.text().starts_with("alet"));
+
+        // Over the limit: only the start is loaded, and can't be changed
+        config.files.max_file_size = 1;
+        let mut doc = Document::load(&path, &config, |_, _| {}).unwrap();
+        assert!(doc.is_read_only());
+        assert!(doc.text().len() <= 1024 * 1024);
+        assert!(line.repeat(150_000).starts_with(&*doc.text()));
+        assert!(matches!(doc.reload(), Err(CoreError::ReadOnly(_))));
+        assert!(matches!(
+            doc.insert_at_cursor("x"),
+            Err(CoreError::ReadOnly(_))
+        ));
+        assert!(matches!(
+            doc.set_line_ending(LineEnding::CrLf),
+            Err(CoreError::ReadOnly(_))
+        ));
+        assert!(doc.save().is_err());
+        assert!(!doc.is_modified());
+
+        let small = Document::from_file_with_config(&path, &Config::default()).unwrap();
+        assert!(!small.is_large_file() && small.syntax_highlighting());
+
+        // The undo limit comes from the config
+        config.editor.undo_limit = 1;
+        config.files.max_file_size = 0;
+        let mut doc = Document::load(&path, &config, |_, _| {}).unwrap();
+        doc.insert_at_cursor("a").unwrap();
+        doc.insert_at_cursor("b").unwrap();
+        doc.undo().unwrap();
+        assert!(doc.text().starts_with("alet"));
+        assert!(!doc.buffer().can_undo());
+    }
+
+    #[test]
+    fn test_binary_file_opens_as_bytes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.bin");
+        std::fs::write(&path, b"\x7fELF\x02\x01\x01\x00").unwrap();
+
+        let mut doc = Document::from_file_with_config(&path, &Config::default()).unwrap();
+        assert!(doc.is_binary() && !doc.syntax_highlighting());
+        assert!(matches!(
+            doc.insert_at_cursor("x"),
+            Err(CoreError::InvalidOperation(_))
+        ));
+
+        let bytes = doc.bytes_mut().unwrap();
+        bytes.overwrite(4, &[0x01]).unwrap();
+        assert!(doc.is_modified());
+        doc.save().unwrap();
+        assert!(!doc.is_modified());
+        assert_eq!(std::fs::read(&path).unwrap(), b"\x7fELF\x01\x01\x01\x00");
+    }
+
+    #[test]
+    fn test_crlf_is_normalized() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dos.txt");
+        std::fs::write(&path, "abc\r\ndef").unwrap();
+
+        let mut doc = Document::from_file(&path).unwrap();
+        assert_eq!(doc.line_ending(), LineEnding::CrLf);
+        doc.move_to_line_end();
+        assert_eq!(doc.cursor_position(), Position::new(0, 3));
+
+        doc.insert_at_cursor("\r\nx").unwrap();
+        assert_eq!(doc.text(), "abc\nx\ndef");
+        doc.save().unwrap();
+        // The save pipeline adds the final newline, in the file's style
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "abc\r\nx\r\ndef\r\n"
+        );
+    }
+
+    #[test]
+    fn test_save_pipeline() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("run.sh");
+        std::fs::write(&path, "echo hi").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+        }
+
+        let mut config = Config::default();
+        config.files.backup_on_save = true;
+        let mut doc = Document::from_file_with_config(&path, &config).unwrap();
+        doc.move_to_line_end();
+        doc.insert_at_cursor("  \necho bye\t").unwrap();
+        doc.save().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "echo hi\necho bye\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(save::backup_path(&path)).unwrap(),
+            "echo hi"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o750);
+        }
+
+        // The cleanup is one undo step, separate from the typing
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "echo hi  \necho bye\t");
+        assert!(doc.is_modified());
+    }
+
+    #[test]
+    fn test_find_next_steps_past_empty_matches() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("a\nb\nc").unwrap();
+        doc.move_cursor_to(Position::new(0, 0));
+        let options = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let query = SearchQuery::new("^", options).unwrap();
+
+        let mut lines = Vec::new();
+        for _ in 0..4 {
+            assert!(doc.find_next(&query).unwrap());
+            lines.push(doc.cursor_position().line);
+        }
+        assert_eq!(lines, [0, 1, 2, 0]);
+    }
+
+    /// Writes `text` with a timestamp too old to be mistaken for a newer
+    /// write's.
+    fn write_old(path: &Path, text: &str) {
+        std::fs::write(path, text).unwrap();
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+    }
+
+    #[test]
+    fn test_reload_from_disk() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+        let mut doc = Document::from_file(&path).unwrap();
+        doc.move_cursor_to(Position::new(2, 1));
+
+        // Without unsaved changes the file's text is taken, cursors kept
+        write_old(&path, "zero\none\ntwo\nthree\nfour\n");
+        assert_eq!(doc.reload().unwrap(), Reload::Replaced);
+        assert_eq!(doc.text(), "zero\none\ntwo\nthree\nfour\n");
+        assert_eq!(doc.cursor_position(), Position::new(3, 1));
+        assert!(!doc.is_modified() && !doc.changed_on_disk());
+        assert_eq!(doc.reload().unwrap(), Reload::Unchanged);
+
+        // Unsaved changes are merged with the file's
+        doc.apply_changes(&ChangeSet::new().replace(9..12, "TWO"))
+            .unwrap();
+        std::fs::write(&path, "zero\none\ntwo\nthree\nFOUR\n").unwrap();
+        assert_eq!(doc.reload().unwrap(), Reload::Merged { conflicts: 0 });
+        assert_eq!(doc.text(), "zero\none\nTWO\nthree\nFOUR\n");
+        assert!(doc.is_modified());
+
+        // The last merge is now the base: only new changes conflict
+        std::fs::write(&path, "zero\none\n2\nthree\nFOUR\n").unwrap();
+        assert_eq!(doc.reload().unwrap(), Reload::Merged { conflicts: 1 });
+        assert_eq!(
+            doc.text(),
+            "zero\none\n<<<<<<< unsaved\nTWO\n=======\n2\n>>>>>>> on disk\nthree\nFOUR\n"
+        );
+
+        // Each reload is one undo step
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "zero\none\nTWO\nthree\nFOUR\n");
+    }
+
+    #[test]
+    fn test_changed_on_disk() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        write_old(&path, "one\n");
+        let mut doc = Document::from_file(&path).unwrap();
+        assert!(!doc.changed_on_disk());
+
+        // A new size gives a write away, even under the same timestamp
+        write_old(&path, "one\ntwo\n");
+        assert!(doc.changed_on_disk());
+        assert_eq!(doc.reload().unwrap(), Reload::Replaced);
+        assert!(!doc.changed_on_disk());
+
+        // Right after a write, a same-size one can leave the metadata as is
+        std::fs::write(&path, "one\nTWO\n").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(doc.reload().unwrap(), Reload::Replaced);
+        std::fs::write(&path, "ONE\nTWO\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        assert!(doc.changed_on_disk());
+        assert_eq!(doc.reload().unwrap(), Reload::Replaced);
+        assert_eq!(doc.text(), "ONE\nTWO\n");
+
+        // A file converted on disk is saved the way it now is
+        write_old(&path, "\u{feff}ONE\r\nTWO\r\n");
+        assert_eq!(doc.reload().unwrap(), Reload::Replaced);
+        assert_eq!(doc.text(), "ONE\nTWO\n");
+        assert_eq!(doc.line_ending(), LineEnding::CrLf);
+        assert!(doc.encoding().has_bom());
+        assert!(!doc.is_modified() && !doc.changed_on_disk());
+    }
+
+    #[test]
+    fn test_new_document_line_ending_from_config() {
+        let mut config = Config::default();
+        config.files.line_ending = "crlf".to_string();
+        let doc = Document::with_config(&config);
+        assert_eq!(doc.line_ending(), LineEnding::CrLf);
+        assert!(!doc.is_modified());
+    }
+
+    #[test]
+    fn test_indentation_detection_beats_language_config() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.languages.insert(
+            "go".to_string(),
+            crate::config::LanguageConfig {
+                use_spaces: Some(false),
+                tab_size: Some(8),
+                ..Default::default()
+            },
+        );
+
+        // Detected two-space indentation wins over the Go override
+        let spaced = dir.path().join("spaced.go");
+        std::fs::write(&spaced, "func f() {\n  x()\n  if y {\n    z()\n  }\n}\n").unwrap();
+        let doc = Document::from_file_with_config(&spaced, &config).unwrap();
+        assert!(doc.tab_config().use_spaces);
+        assert_eq!(doc.tab_config().width, 2);
+        assert_eq!(doc.buffer().config().tab_width, 2);
+
+        // Nothing to detect: the language override applies
+        let flat = dir.path().join("flat.go");
+        std::fs::write(&flat, "package main\n").unwrap();
+        let doc = Document::from_file_with_config(&flat, &config).unwrap();
+        assert!(!doc.tab_config().use_spaces);
+        assert_eq!(doc.tab_config().width, 8);
+    }
+
+    #[test]
+    fn test_tab_indent_outdent() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("ab\ncd\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 1));
+        doc.insert_tab().unwrap();
+        // Pads to the next tab stop
+        assert_eq!(doc.text(), "a   b\ncd\n");
+
+        doc.move_cursor_to(Position::new(0, 0));
+        doc.select_to(Position::new(2, 0));
+        doc.indent().unwrap();
+        assert_eq!(doc.text(), "    a   b\n    cd\n");
+        assert_eq!(
+            doc.cursors().primary().selection_range(),
+            Some((Position::new(0, 0), Position::new(2, 0)))
+        );
+
+        doc.outdent().unwrap();
+        doc.outdent().unwrap();
+        assert_eq!(doc.text(), "a   b\ncd\n");
+
+        doc.set_tab_config(TabConfig {
+            width: 4,
+            use_spaces: false,
+        });
+        doc.clear_selection();
+        doc.move_cursor_to(Position::new(1, 1));
+        doc.indent().unwrap();
+        assert_eq!(doc.text(), "a   b\n\tcd\n");
+        assert_eq!(doc.cursor_position(), Position::new(1, 2));
+
+        // Tab stops count display cells, not chars
+        let mut doc = Document::new();
+        doc.insert_at_cursor("日本\n\tx").unwrap();
+        doc.move_cursor_to(Position::new(0, 2));
+        doc.insert_tab().unwrap();
+        doc.move_cursor_to(Position::new(1, 1));
+        doc.insert_tab().unwrap();
+        assert_eq!(doc.text(), "日本    \n\t    x");
+    }
+
+    #[test]
+    fn test_undo_indent_restores_selection() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("a\nb\nc\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 1));
+        doc.select_to(Position::new(2, 1));
+        let before = *doc.cursors().primary();
+
+        // Three inserts, one undo step
+        doc.indent().unwrap();
+        assert_eq!(doc.text(), "    a\n    b\n    c\n");
+        let after = *doc.cursors().primary();
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "a\nb\nc\n");
+        assert_eq!(*doc.cursors().primary(), before);
+
+        // Redo puts back the selection as it was after the indent
+        doc.redo().unwrap();
+        assert_eq!(doc.text(), "    a\n    b\n    c\n");
+        assert_eq!(*doc.cursors().primary(), after);
+        assert_eq!(
+            after.selection_range(),
+            Some((Position::new(0, 5), Position::new(2, 5)))
+        );
+    }
+
+    #[test]
+    fn test_typing_over_selection_is_one_undo_step() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("hello world").unwrap();
+        doc.move_cursor_to(Position::new(0, 6));
+        doc.select_to(Position::new(0, 11));
+
+        doc.insert_at_cursor("there").unwrap();
+        assert_eq!(doc.text(), "hello there");
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "hello world");
+        assert_eq!(
+            doc.cursors().primary().selection_range(),
+            Some((Position::new(0, 6), Position::new(0, 11)))
+        );
+    }
+
+    #[test]
+    fn test_apply_changes_keeps_cursors_anchored() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("fn main() {}\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 3));
+        doc.select_to(Position::new(0, 7));
+
+        // Rename `main` to `start` and add a line above
+        let changes = ChangeSet::new()
+            .insert(0, "// entry\n")
+            .replace(3..7, "start");
+        doc.apply_changes(&changes).unwrap();
+        assert_eq!(doc.text(), "// entry\nfn start() {}\n");
+
+        let cursor = doc.cursors().primary();
+        assert_eq!(cursor.anchor, Some(Position::new(1, 3)));
+        assert_eq!(cursor.position, Position::new(1, 8));
+
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "fn main() {}\n");
+    }
+
+    #[test]
+    fn test_grapheme_movement_and_deletion() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("a👍🏽b\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 1));
+
+        doc.move_cursor_right(1);
+        assert_eq!(doc.cursor_position(), Position::new(0, 3));
+        doc.move_cursor_left(1);
+        assert_eq!(doc.cursor_position(), Position::new(0, 1));
+
+        // Delete removes the emoji together with its modifier
+        doc.delete_forward().unwrap();
+        assert_eq!(doc.text(), "ab\n");
+    }
+
+    #[test]
+    fn test_vertical_movement_keeps_display_column() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("日本語\nab\nabcdef\n\tx\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 2));
+
+        // Display column 4 is char column 4 in ASCII...
+        doc.move_cursor_down(2);
+        assert_eq!(doc.cursor_position(), Position::new(2, 4));
+        // ...even after passing through a shorter line
+        doc.move_cursor_up(1);
+        assert_eq!(doc.cursor_position(), Position::new(1, 2));
+        doc.move_cursor_down(1);
+        assert_eq!(doc.cursor_position(), Position::new(2, 4));
+        // A 4-wide tab ends at display column 4
+        doc.move_cursor_down(1);
+        assert_eq!(doc.cursor_position(), Position::new(3, 1));
+    }
+
+    #[test]
+    fn test_word_movement_and_deletion() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("let parseHttp = 1;\nnext\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 0));
+
+        doc.move_word_right(WordKind::Word);
+        doc.move_word_right(WordKind::Subword);
+        assert_eq!(doc.cursor_position(), Position::new(0, 9));
+        doc.move_word_right(WordKind::Word);
+        assert_eq!(doc.cursor_position(), Position::new(0, 13));
+        doc.move_word_left(WordKind::Subword);
+        assert_eq!(doc.cursor_position(), Position::new(0, 9));
+
+        doc.select_word().unwrap();
+        assert_eq!(doc.selected_text().as_deref(), Some("parseHttp"));
+        doc.clear_selection();
+
+        // Word deletion stops at the line start, then joins lines
+        doc.move_cursor_to(Position::new(1, 4));
+        doc.delete_word_backward(WordKind::Word).unwrap();
+        assert_eq!(doc.text(), "let parseHttp = 1;\n\n");
+        doc.delete_word_backward(WordKind::Word).unwrap();
+        assert_eq!(doc.text(), "let parseHttp = 1;\n");
+        doc.move_cursor_to(Position::new(0, 4));
+        doc.delete_word_forward(WordKind::Subword).unwrap();
+        assert_eq!(doc.text(), "let Http = 1;\n");
+    }
+
+    #[test]
+    fn test_select_text_object_at_each_cursor() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("f(a, (b))\ng(c)\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 7));
+        doc.add_cursor(Position::new(1, 2));
+
+        assert!(
+            doc.select_text_object(TextObject::Parens, ObjectExtent::Inner)
+                .unwrap()
+        );
+        assert_eq!(doc.selected_texts(), ["b", "c"]);
+        // Again: the next pair out where there is one
+        assert!(
+            doc.select_text_object(TextObject::Parens, ObjectExtent::Inner)
+                .unwrap()
+        );
+        assert_eq!(doc.selected_texts(), ["a, (b)", "c"]);
+
+        doc.collapse_cursors();
+        doc.clear_selection();
+        assert!(
+            !doc.select_text_object(TextObject::Quote('"'), ObjectExtent::Inner)
+                .unwrap()
+        );
+        assert!(
+            doc.select_text_object(TextObject::Line, ObjectExtent::Around)
+                .unwrap()
+        );
+        assert_eq!(doc.selected_text().as_deref(), Some("g(c)\n"));
+    }
+
+    #[test]
+    fn test_matching_bracket_skips_strings() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("f(\")\", '(')").unwrap();
+        doc.move_cursor_to(Position::new(0, 1));
+        // Plain text: the `)` in the string looks like the partner
+        assert_eq!(doc.matching_bracket().map(|m| m.partner), Some(3));
+
+        // A string and a char literal, as a parse would report them
+        let literals = [2..5, 7..10];
+        let is_code = |idx| !literals.iter().any(|r: &Range<usize>| r.contains(&idx));
+        let found = doc.buffer().matching_bracket_with(1, is_code);
+        assert_eq!(found.map(|m| m.partner), Some(10));
+
+        assert!(doc.jump_to_matching_bracket().unwrap());
+        assert_eq!(doc.cursor_position(), Position::new(0, 3));
+        assert!(doc.select_to_matching_bracket().unwrap());
+        assert_eq!(doc.selected_text().as_deref(), Some("(\")"));
+        assert_eq!(doc.cursor_position(), Position::new(0, 1));
+
+        doc.clear_selection();
+        doc.move_cursor_to(Position::new(0, 0));
+        assert!(!doc.jump_to_matching_bracket().unwrap());
+    }
+
+    #[test]
+    fn test_marks_follow_edits() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("one\ntwo\nthree").unwrap();
+        doc.move_cursor_to(Position::new(1, 1));
+        doc.set_mark('a').unwrap();
+        assert!(doc.set_mark('A').is_err());
+
+        doc.move_cursor_to(Position::new(0, 0));
+        doc.insert_at_cursor("zero\n").unwrap();
+        assert_eq!(doc.mark('a'), Some(Position::new(2, 1)));
+        doc.undo().unwrap();
+        assert_eq!(doc.mark('a'), Some(Position::new(1, 1)));
+
+        // Setting it again moves it
+        doc.set_mark('a').unwrap();
+        assert_eq!(doc.mark('a'), Some(Position::new(0, 0)));
+        assert_eq!(doc.mark('b'), None);
+    }
+
+    #[test]
+    fn test_line_operations() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("c\nb\na\nx\nb2\nb10").unwrap();
+        // A selection over lines 0-2 and a cursor on line 4
+        doc.move_cursor_to(Position::new(0, 0));
+        doc.select_to(Position::new(3, 0));
+        assert!(doc.cursors.add(Position::new(4, 1)));
+
+        doc.move_lines_down().unwrap();
+        assert_eq!(doc.text(), "x\nc\nb\na\nb10\nb2");
+        assert_eq!(doc.selected_text().as_deref(), Some("c\nb\na\n"));
+        assert_eq!(doc.cursors().all()[1].position, Position::new(5, 1));
+        // One undo step for every cursor
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "c\nb\na\nx\nb2\nb10");
+
+        doc.collapse_cursors();
+        doc.move_cursor_to(Position::new(3, 0));
+        doc.select_to(Position::new(5, 3));
+        doc.sort_lines(LineSort::Natural).unwrap();
+        assert_eq!(doc.text(), "c\nb\na\nb2\nb10\nx");
+        assert_eq!(doc.selected_text().as_deref(), Some("b2\nb10\nx"));
+
+        doc.move_cursor_to(Position::new(0, 0));
+        doc.duplicate_lines().unwrap();
+        assert_eq!(doc.text(), "c\nc\nb\na\nb2\nb10\nx");
+        assert_eq!(doc.cursor_position(), Position::new(1, 0));
+
+        doc.join_lines().unwrap();
+        assert_eq!(doc.text(), "c\nc b\na\nb2\nb10\nx");
+        assert_eq!(doc.cursor_position(), Position::new(1, 1));
+
+        doc.move_cursor_to(Position::new(5, 0));
+        doc.delete_lines().unwrap();
+        assert_eq!(doc.text(), "c\nc b\na\nb2\nb10");
+        assert_eq!(doc.cursor_position(), Position::new(4, 0));
+    }
+
+    #[test]
+    fn test_line_operations_with_trailing_newline() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("a\nb\n").unwrap();
+        let version = doc.snapshot().version();
+
+        // The last line has nothing below it to swap with
+        doc.move_cursor_to(Position::new(1, 1));
+        doc.move_lines_down().unwrap();
+        assert_eq!(doc.text(), "a\nb\n");
+        assert_eq!(doc.cursor_position(), Position::new(1, 1));
+        assert_eq!(doc.snapshot().version(), version);
+        doc.move_lines_up().unwrap();
+        assert_eq!(doc.text(), "b\na\n");
+        assert_eq!(doc.cursor_position(), Position::new(0, 1));
+
+        // The empty line after the `\n` isn't moved or copied
+        doc.move_cursor_to(Position::new(2, 0));
+        doc.move_lines_up().unwrap();
+        doc.duplicate_lines().unwrap();
+        assert_eq!(doc.text(), "b\na\n");
+        assert_eq!(doc.cursor_position(), Position::new(2, 0));
+    }
+
+    #[test]
+    fn test_multi_cursor_editing() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("one\ntwo\nthree\n").unwrap();
+        doc.move_cursor_to(Position::new(0, 3));
+        assert!(doc.add_cursor_below());
+        assert!(doc.add_cursor_below());
+
+        doc.insert_at_cursor("!").unwrap();
+        assert_eq!(doc.text(), "one!\ntwo!\nthr!ee\n");
+        let columns: Vec<_> = doc
+            .cursors()
+            .all()
+            .iter()
+            .map(|c| c.position.column)
+            .collect();
+        assert_eq!(columns, [4, 4, 4]);
+
+        // One undo step for all three cursors
+        doc.undo().unwrap();
+        assert_eq!(doc.text(), "one\ntwo\nthree\n");
+        assert_eq!(doc.cursors().len(), 3);
+
+        // Cursors that delete into each other merge
+        doc.collapse_cursors();
+        doc.move_cursor_to(Position::new(0, 2));
+        doc.add_cursor(Position::new(0, 3));
+        doc.delete_word_backward(WordKind::Word).unwrap();
+        assert_eq!(doc.text(), "\ntwo\nthree\n");
+        assert_eq!(doc.cursors().len(), 1);
+    }
+
+    #[test]
+    fn test_multi_cursor_copy_and_gating() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("ab cd").unwrap();
+        doc.select_char_range(0..2).unwrap();
+        doc.add_cursor(Position::new(0, 5));
+        doc.select_to(Position::new(0, 3));
+
+        assert_eq!(doc.selected_texts(), ["ab", "cd"]);
+        assert_eq!(doc.selected_text().as_deref(), Some("ab\ncd"));
+        doc.insert_per_cursor(&["1".to_string(), "2".to_string()])
+            .unwrap();
+        assert_eq!(doc.text(), "1 2");
+
+        doc.set_multi_cursor(false);
+        assert!(doc.cursors().is_single());
+        assert!(!doc.add_cursor(Position::new(0, 0)));
+    }
+
+    #[test]
+    fn test_add_next_occurrence() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("foo bar Foo foobar foo").unwrap();
+        doc.move_cursor_to(Position::new(0, 1));
+        let options = SearchOptions {
+            whole_word: true,
+            ..SearchOptions::default()
+        };
+
+        // First press selects the word, then each press adds one
+        assert!(doc.add_next_occurrence(options).unwrap());
+        assert_eq!(doc.selected_texts(), ["foo"]);
+        assert!(doc.add_next_occurrence(options).unwrap());
+        assert_eq!(doc.cursor_position(), Position::new(0, 22));
+        // Wraps around, and finds nothing new
+        assert!(!doc.add_next_occurrence(options).unwrap());
+        assert_eq!(doc.cursors().len(), 2);
+
+        // Typing replaces all of them
+        doc.insert_at_cursor("baz").unwrap();
+        assert_eq!(doc.text(), "baz bar Foo foobar baz");
+    }
+
+    #[test]
+    fn test_skip_and_select_all_occurrences() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("a.b a.b a.b").unwrap();
+        doc.select_char_range(0..3).unwrap();
+        let options = SearchOptions::default();
+
+        doc.skip_occurrence(options).unwrap();
+        assert_eq!(doc.cursors().len(), 1);
+        assert_eq!(doc.cursor_position(), Position::new(0, 7));
+        doc.add_next_occurrence(options).unwrap();
+        doc.skip_occurrence(options).unwrap();
+        let starts: Vec<_> = doc
+            .cursors()
+            .all()
+            .iter()
+            .map(|c| c.range().0.column)
+            .collect();
+        assert_eq!(starts, [0, 4]);
+
+        assert_eq!(doc.select_all_occurrences(options).unwrap(), 3);
+        assert_eq!(doc.selected_texts(), ["a.b", "a.b", "a.b"]);
+    }
+
+    #[test]
+    fn test_block_selection_editing() {
+        let mut doc = Document::new();
+        doc.insert_at_cursor("let a = 1;\nx\nlet bc = 2;\n")
+            .unwrap();
+
+        // Zero-wide block at display column 4 on all three lines
+        let block = BlockSelection::new(Position::new(0, 4), Position::new(2, 4));
+        assert!(doc.select_block(block).unwrap());
+        assert_eq!(doc.cursors().len(), 3);
+
+        // The short line is padded out to the block
+        doc.insert_at_cursor("mut ").unwrap();
+        assert_eq!(doc.text(), "let mut a = 1;\nx   mut \nlet mut bc = 2;\n");
+        assert_eq!(doc.block_selection().unwrap().columns(), 8..8);
+
+        doc.delete_backward().unwrap();
+        assert_eq!(doc.text(), "let muta = 1;\nx   mut\nlet mutbc = 2;\n");
+
+        // Select a column, copy it, and paste it back as one text
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        doc.select_block(BlockSelection::new(
+            Position::new(0, 0),
+            Position::new(2, 3),
+        ))
+        .unwrap();
+        assert_eq!(doc.selected_texts(), ["let", "x", "let"]);
+        doc.select_block(BlockSelection::new(
+            Position::new(0, 11),
+            Position::new(2, 11),
+        ))
+        .unwrap();
+        doc.insert_per_cursor(&["A\nB\nC".to_string()]).unwrap();
+        assert_eq!(doc.text(), "let a = 1; A\nx          B\nlet bc = 2;C\n");
+    }
+}
@@ -6,13 +6,22 @@
 //! complex subsystems. External code only needs to interact with
 //! `Editor`, not individual components.
 
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::{Duration, Instant};
+
+use luminex_buffer::{ChangeSet, Position, SearchOptions, SearchQuery};
 
 use crate::command::CommandRegistry;
 use crate::config::Config;
-use crate::document::{Document, DocumentId, DocumentManager};
+use crate::document::{
+    BlockSelection, Document, DocumentId, DocumentManager, LineEnding, LineSort, ObjectExtent,
+    Reload, TextObject, UndoBranch, UndoTarget, WordKind,
+};
 use crate::event::{EditorEvent, EventBus};
 use crate::keymap::Keymap;
+use crate::marks::{JumpList, Location, SavedLocation, Session, is_global_mark, is_local_mark};
+use crate::recovery::{RECOVERY_INTERVAL, RecoveryFile, RecoveryStore, RecoveryTask};
 use crate::workspace::Workspace;
 use crate::{CoreError, CoreResult};
 
@@ -51,8 +60,26 @@ pub struct Editor {
     /// Editor mode (normal, insert, etc.)
     mode: EditorMode,
 
-    /// Clipboard content
-    clipboard: String,
+    /// Clipboard content, one text per cursor that copied it
+    clipboard: Vec<String>,
+
+    /// Options applied to the next search
+    search_options: SearchOptions,
+
+    /// The active search, if any (reused by find next/previous)
+    search: Option<SearchQuery>,
+
+    /// Marks `A`–`Z`
+    global_marks: HashMap<char, Location>,
+
+    /// Where big movements came from
+    jumps: JumpList,
+
+    /// Writes recovery files for unsaved documents, once started
+    recovery: Option<(RecoveryStore, RecoveryTask)>,
+
+    /// When each document was last edited, for autosave
+    last_edits: HashMap<DocumentId, Instant>,
 
     /// Whether the editor should quit
     should_quit: bool,
@@ -83,7 +110,13 @@ impl Editor {
             commands: CommandRegistry::new(),
             event_bus: EventBus::new(),
             mode: EditorMode::default(),
-            clipboard: String::new(),
+            clipboard: Vec::new(),
+            search_options: SearchOptions::default(),
+            search: None,
+            global_marks: HashMap::new(),
+            jumps: JumpList::default(),
+            recovery: None,
+            last_edits: HashMap::new(),
             should_quit: false,
         }
     }
@@ -99,7 +132,13 @@ impl Editor {
             commands: CommandRegistry::new(),
             event_bus: EventBus::new(),
             mode: EditorMode::default(),
-            clipboard: String::new(),
+            clipboard: Vec::new(),
+            search_options: SearchOptions::default(),
+            search: None,
+            global_marks: HashMap::new(),
+            jumps: JumpList::default(),
+            recovery: None,
+            last_edits: HashMap::new(),
             should_quit: false,
         }
     }
@@ -118,19 +157,66 @@ impl Editor {
         }
 
         // Create new document
-        let doc = Document::from_file(path)?;
+        let events = self.event_bus.clone();
+        let doc = Document::load(path, &self.config, |loaded, total| {
+            events.emit(EditorEvent::LoadProgress {
+                path: path.to_path_buf(),
+                loaded,
+                total,
+            });
+        })?;
+        Ok(self.add_document(doc))
+    }
+
+    /// Loads a file on a background task.
+    ///
+    /// `LoadProgress` events are emitted as chunks are read. Pass the
+    /// finished document to [`add_document`](Self::add_document).
+    /// Must be called from within a Tokio runtime.
+    pub fn load_file(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> tokio::task::JoinHandle<CoreResult<Document>> {
+        let path = path.as_ref().to_path_buf();
+        let config = self.config.clone();
+        let events = self.event_bus.clone();
+        tokio::task::spawn_blocking(move || {
+            Document::load(&path, &config, |loaded, total| {
+                events.emit(EditorEvent::LoadProgress {
+                    path: path.clone(),
+                    loaded,
+                    total,
+                });
+            })
+        })
+    }
+
+    /// Adds a loaded document and focuses it.
+    ///
+    /// If its file is already open, that document is focused instead.
+    pub fn add_document(&mut self, doc: Document) -> DocumentId {
+        if let Some(id) = doc
+            .path()
+            .and_then(|path| self.documents.find_by_path(path))
+        {
+            self.documents.set_active(id);
+            self.emit(EditorEvent::DocumentFocused(id));
+            return id;
+        }
+
         let id = self.documents.add(doc);
         self.documents.set_active(id);
+        self.attach_locations(id);
 
         self.emit(EditorEvent::DocumentOpened(id));
         self.emit(EditorEvent::DocumentFocused(id));
 
-        Ok(id)
+        id
     }
 
     /// Creates a new untitled document.
     pub fn new_document(&mut self) -> DocumentId {
-        let doc = Document::new();
+        let doc = Document::with_config(&self.config);
         let id = self.documents.add(doc);
         self.documents.set_active(id);
 
@@ -142,7 +228,9 @@ impl Editor {
 
     /// Closes a document.
     pub fn close_document(&mut self, id: DocumentId) -> CoreResult<()> {
+        self.detach_locations(id);
         self.documents.close(id)?;
+        self.forget_edits(id);
         self.emit(EditorEvent::DocumentClosed(id));
         Ok(())
     }
@@ -152,6 +240,7 @@ impl Editor {
         let doc = self.active_document_mut()?;
         doc.save()?;
         let id = doc.id();
+        self.forget_edits(id);
         self.emit(EditorEvent::DocumentSaved(id));
         Ok(())
     }
@@ -161,10 +250,83 @@ impl Editor {
         let doc = self.active_document_mut()?;
         doc.save_as(path)?;
         let id = doc.id();
+        self.forget_edits(id);
         self.emit(EditorEvent::DocumentSaved(id));
         Ok(())
     }
 
+    /// Reopens the current document with an explicit encoding.
+    pub fn reopen_with_encoding(&mut self, encoding: &str) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.reopen_with_encoding(encoding)?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Reloads the current document from disk, merging in any unsaved
+    /// changes (see [`Document::reload`]).
+    pub fn reload_current(&mut self) -> CoreResult<Reload> {
+        let reload = self.active_document_mut()?.reload()?;
+        if reload != Reload::Unchanged {
+            let id = self.active_document()?.id();
+            self.document_changed(id);
+        }
+        Ok(reload)
+    }
+
+    /// Reloads every document whose file changed on disk, emitting
+    /// `FileChangedOnDisk` for each, and returns them with what the
+    /// reload did: a [`Reload::Merged`] with conflicts needs the user's
+    /// attention.
+    ///
+    /// The UI calls this from a timer or when the window regains focus.
+    /// Does nothing unless `FileConfig::watch_files` is set. Read-only
+    /// and binary documents are left alone.
+    pub fn check_disk_changes(&mut self) -> Vec<(DocumentId, Reload)> {
+        if !self.config.files.watch_files {
+            return Vec::new();
+        }
+        let mut reloaded = Vec::new();
+        for id in self.documents.order().to_vec() {
+            let Some(doc) = self
+                .documents
+                .get_mut(id)
+                .filter(|doc| !doc.is_read_only() && !doc.is_binary() && doc.changed_on_disk())
+            else {
+                continue;
+            };
+            match doc.reload() {
+                Ok(Reload::Unchanged) => {}
+                Ok(reload) => {
+                    if let Some(path) = doc.path() {
+                        self.event_bus
+                            .emit(EditorEvent::FileChangedOnDisk(path.to_path_buf()));
+                    }
+                    self.document_changed(id);
+                    reloaded.push((id, reload));
+                }
+                Err(e) => tracing::warn!("Could not reload {}: {}", doc.name(), e),
+            }
+        }
+        reloaded
+    }
+
+    /// Converts the current document to another encoding.
+    pub fn convert_encoding(&mut self, encoding: &str) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.convert_encoding(encoding)?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Converts the current document to another line ending style.
+    pub fn convert_line_ending(&mut self, line_ending: LineEnding) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.set_line_ending(line_ending)?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
     /// Returns the active document.
     pub fn active_document(&self) -> CoreResult<&Document> {
         self.documents.active().ok_or(CoreError::NoActiveDocument)
@@ -215,6 +377,118 @@ impl Editor {
         Ok(())
     }
 
+    /// Deletes the selection or back to the start of the word.
+    pub fn delete_word_backward(&mut self, kind: WordKind) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.delete_word_backward(kind)?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Deletes the selection or up to the end of the word.
+    pub fn delete_word_forward(&mut self, kind: WordKind) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.delete_word_forward(kind)?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Inserts a tab (or indents the selected lines).
+    pub fn insert_tab(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.insert_tab()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Indents the selected lines by one level.
+    pub fn indent(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.indent()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Outdents the selected lines by one level.
+    pub fn outdent(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.outdent()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Deletes every line touched by a cursor.
+    pub fn delete_lines(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.delete_lines()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Copies every line touched by a cursor below itself.
+    pub fn duplicate_lines(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.duplicate_lines()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Moves the lines touched by each cursor up by one.
+    pub fn move_lines_up(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.move_lines_up()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Moves the lines touched by each cursor down by one.
+    pub fn move_lines_down(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.move_lines_down()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Joins the lines touched by each cursor, or a line with the next.
+    pub fn join_lines(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.join_lines()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Sorts the lines touched by each cursor.
+    pub fn sort_lines(&mut self, sort: LineSort) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.sort_lines(sort)?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Removes repeated lines among those touched by each cursor.
+    pub fn unique_lines(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.unique_lines()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Reverses the lines touched by each cursor.
+    pub fn reverse_lines(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.reverse_lines()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
+    /// Shuffles the lines touched by each cursor.
+    pub fn shuffle_lines(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.shuffle_lines()?;
+        self.emit_document_changed();
+        Ok(())
+    }
+
     /// Undoes the last action.
     pub fn undo(&mut self) -> CoreResult<()> {
         let doc = self.active_document_mut()?;
@@ -231,6 +505,22 @@ impl Editor {
         Ok(())
     }
 
+    /// Moves to another state of the undo tree.
+    pub fn undo_jump(&mut self, target: UndoTarget) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        if doc.jump_history(target) {
+            self.emit_document_changed();
+        }
+        Ok(())
+    }
+
+    /// Returns the undo branches of the active document, for the branch picker.
+    pub fn undo_branches(&self) -> Vec<UndoBranch> {
+        self.active_document()
+            .map(|doc| doc.undo_branches())
+            .unwrap_or_default()
+    }
+
     // ==================== Cursor Movement ====================
 
     /// Moves cursor up by n lines.
@@ -281,6 +571,22 @@ impl Editor {
         Ok(())
     }
 
+    /// Moves cursor to the start of the previous word.
+    pub fn move_word_left(&mut self, kind: WordKind) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.move_word_left(kind);
+        self.emit_cursor_moved();
+        Ok(())
+    }
+
+    /// Moves cursor to the end of the next word.
+    pub fn move_word_right(&mut self, kind: WordKind) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.move_word_right(kind);
+        self.emit_cursor_moved();
+        Ok(())
+    }
+
     // ==================== Selection ====================
 
     /// Selects all text.
@@ -291,15 +597,112 @@ impl Editor {
         Ok(())
     }
 
+    /// Adds a cursor on the line above the topmost cursor.
+    pub fn add_cursor_above(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        if doc.add_cursor_above() {
+            self.emit_cursor_moved();
+        }
+        Ok(())
+    }
+
+    /// Adds a cursor on the line below the bottommost cursor.
+    pub fn add_cursor_below(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        if doc.add_cursor_below() {
+            self.emit_cursor_moved();
+        }
+        Ok(())
+    }
+
+    /// Selects a rectangle of display columns, one cursor per line.
+    ///
+    /// This is what an alt+drag produces.
+    pub fn select_block(&mut self, block: BlockSelection) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        if doc.select_block(block)? {
+            self.emit_selection_changed();
+        }
+        Ok(())
+    }
+
+    /// Moves the active corner of the block selection, starting one at
+    /// the cursor if needed.
+    pub fn extend_block(&mut self, lines: isize, columns: isize) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        if doc.extend_block(lines, columns)? {
+            self.emit_selection_changed();
+        }
+        Ok(())
+    }
+
+    /// Removes all cursors but the primary one.
+    pub fn collapse_cursors(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.collapse_cursors();
+        self.emit_cursor_moved();
+        Ok(())
+    }
+
+    /// Selects the word under the cursor.
+    pub fn select_word(&mut self) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        doc.select_word()?;
+        self.emit_selection_changed();
+        Ok(())
+    }
+
+    /// Selects a text object at every cursor.
+    ///
+    /// Returns false if no cursor has one around it.
+    pub fn select_text_object(
+        &mut self,
+        object: TextObject,
+        extent: ObjectExtent,
+    ) -> CoreResult<bool> {
+        let doc = self.active_document_mut()?;
+        let found = doc.select_text_object(object, extent)?;
+        self.emit_selection_changed();
+        Ok(found)
+    }
+
+    /// Moves each cursor next to a bracket onto its partner.
+    ///
+    /// Returns false if no cursor is next to a matched bracket.
+    pub fn jump_to_matching_bracket(&mut self) -> CoreResult<bool> {
+        let doc = self.active_document_mut()?;
+        let found = doc.jump_to_matching_bracket()?;
+        self.emit_cursor_moved();
+        Ok(found)
+    }
+
+    /// Selects from each cursor's bracket through its partner.
+    ///
+    /// Returns false if no cursor is next to a matched bracket.
+    pub fn select_to_matching_bracket(&mut self) -> CoreResult<bool> {
+        let doc = self.active_document_mut()?;
+        let found = doc.select_to_matching_bracket()?;
+        self.emit_selection_changed();
+        Ok(found)
+    }
+
     /// Copies selection to clipboard.
+    ///
+    /// With several cursors, each cursor's selection is kept separately
+    /// so pasting with the same number of cursors gives each its own.
     pub fn copy(&mut self) -> CoreResult<()> {
         let doc = self.active_document()?;
-        if let Some(text) = doc.selected_text() {
-            self.clipboard = text;
+        if doc.selected_text().is_some() {
+            self.clipboard = doc.selected_texts();
         }
         Ok(())
     }
 
+    /// Returns the clipboard as one text.
+    pub fn clipboard_text(&self) -> String {
+        self.clipboard.join("\n")
+    }
+
     /// Cuts selection to clipboard.
     pub fn cut(&mut self) -> CoreResult<()> {
         self.copy()?;
@@ -311,11 +714,387 @@ impl Editor {
 
     /// Pastes from clipboard.
     pub fn paste(&mut self) -> CoreResult<()> {
-        let text = self.clipboard.clone();
-        self.insert_text(&text)?;
+        let texts = self.clipboard.clone();
+        let doc = self.active_document_mut()?;
+        doc.insert_per_cursor(&texts)?;
+        self.emit_document_changed();
         Ok(())
     }
 
+    // ==================== Search ====================
+
+    /// Returns the options used for new searches.
+    pub fn search_options(&self) -> SearchOptions {
+        self.search_options
+    }
+
+    /// Sets the options used for new searches.
+    pub fn set_search_options(&mut self, options: SearchOptions) {
+        self.search_options = options;
+    }
+
+    /// Adds a selection at the next occurrence of the current selection.
+    pub fn add_next_occurrence(&mut self) -> CoreResult<()> {
+        let options = self.search_options;
+        let doc = self.active_document_mut()?;
+        if doc.add_next_occurrence(options)? {
+            self.emit_selection_changed();
+        }
+        Ok(())
+    }
+
+    /// Moves the newest selection on to the next occurrence.
+    pub fn skip_occurrence(&mut self) -> CoreResult<()> {
+        let options = self.search_options;
+        let doc = self.active_document_mut()?;
+        if doc.skip_occurrence(options)? {
+            self.emit_selection_changed();
+        }
+        Ok(())
+    }
+
+    /// Selects every occurrence of the current selection.
+    ///
+    /// Returns the number of selections.
+    pub fn select_all_occurrences(&mut self) -> CoreResult<usize> {
+        let options = self.search_options;
+        let doc = self.active_document_mut()?;
+        let count = doc.select_all_occurrences(options)?;
+        self.emit_selection_changed();
+        Ok(count)
+    }
+
+    /// Returns the active search query, if any.
+    pub fn search_query(&self) -> Option<&SearchQuery> {
+        self.search.as_ref()
+    }
+
+    /// Starts a new search and selects the first match after the cursor.
+    ///
+    /// Returns the total number of matches in the active document.
+    pub fn find(&mut self, pattern: &str) -> CoreResult<usize> {
+        let query = SearchQuery::new(pattern, self.search_options)?;
+        let count = self.active_document()?.buffer().search_all(&query).len();
+        self.jump_with(|doc| doc.find_next(&query))?;
+        self.search = Some(query);
+        self.emit_selection_changed();
+        Ok(count)
+    }
+
+    /// Selects the next match of the active search.
+    pub fn find_next(&mut self) -> CoreResult<()> {
+        let Some(query) = self.search.clone() else {
+            return Ok(());
+        };
+        if self.jump_with(|doc| doc.find_next(&query))? {
+            self.emit_selection_changed();
+        }
+        Ok(())
+    }
+
+    /// Selects the previous match of the active search.
+    pub fn find_previous(&mut self) -> CoreResult<()> {
+        let Some(query) = self.search.clone() else {
+            return Ok(());
+        };
+        if self.jump_with(|doc| doc.find_previous(&query))? {
+            self.emit_selection_changed();
+        }
+        Ok(())
+    }
+
+    /// Replaces every match of the active search.
+    ///
+    /// Returns the number of replacements made.
+    pub fn replace_all(&mut self, replacement: &str) -> CoreResult<usize> {
+        let Some(query) = self.search.clone() else {
+            return Err(CoreError::InvalidOperation("No active search".to_string()));
+        };
+        let count = self
+            .active_document_mut()?
+            .replace_all(&query, replacement)?;
+        if count > 0 {
+            self.emit_document_changed();
+        }
+        Ok(count)
+    }
+
+    // ==================== Marks and Jumps ====================
+
+    /// Moves the cursor to the start of `line` (0-based, clamped to the
+    /// document), recording a jump.
+    pub fn goto_line(&mut self, line: usize) -> CoreResult<()> {
+        self.jump_with(|doc| {
+            let line = line.min(doc.buffer().len_lines().saturating_sub(1));
+            doc.move_cursor_to(Position::new(line, 0));
+            Ok(())
+        })?;
+        self.emit_cursor_moved();
+        Ok(())
+    }
+
+    /// Opens `path` and moves the cursor to `position`, recording a
+    /// jump. This is how go-to-definition lands.
+    pub fn goto_location(
+        &mut self,
+        path: impl AsRef<Path>,
+        position: Position,
+    ) -> CoreResult<DocumentId> {
+        if self.active_document().is_ok() {
+            self.push_jump()?;
+        }
+        let id = self.open_file(path)?;
+        self.move_active_to(position)?;
+        Ok(id)
+    }
+
+    /// Records the primary cursor's position in the jump list.
+    ///
+    /// Any older jump to the same line is dropped, and so is everything
+    /// ahead if the list was being walked.
+    pub fn push_jump(&mut self) -> CoreResult<()> {
+        let location = self.here()?;
+        self.record_jump(location);
+        Ok(())
+    }
+
+    /// Goes back to where the last jump came from.
+    ///
+    /// Returns false if there's nowhere further back.
+    pub fn jump_back(&mut self) -> CoreResult<bool> {
+        if self.jumps.at_end() && self.active_document().is_ok() {
+            // Record where we are so jump_forward can come back here
+            self.push_jump()?;
+            self.jumps.back();
+        }
+        match self.jumps.back().cloned() {
+            Some(location) => self.goto(&location),
+            None => Ok(false),
+        }
+    }
+
+    /// Undoes a [`jump_back`](Self::jump_back).
+    ///
+    /// Returns false if there's nowhere further forward.
+    pub fn jump_forward(&mut self) -> CoreResult<bool> {
+        match self.jumps.forward().cloned() {
+            Some(location) => self.goto(&location),
+            None => Ok(false),
+        }
+    }
+
+    /// Sets mark `name` at the primary cursor: `a`–`z` in the active
+    /// document, `A`–`Z` globally.
+    pub fn set_mark(&mut self, name: char) -> CoreResult<()> {
+        if !is_global_mark(name) {
+            return self.active_document_mut()?.set_mark(name);
+        }
+        let location = self.here()?;
+        if let Some(old) = self.global_marks.insert(name, location) {
+            self.release(vec![old]);
+        }
+        Ok(())
+    }
+
+    /// Goes to mark `name`, recording a jump. A global mark in a closed
+    /// file opens it.
+    ///
+    /// Returns false if the mark isn't set.
+    pub fn goto_mark(&mut self, name: char) -> CoreResult<bool> {
+        if is_local_mark(name) {
+            let Some(position) = self.active_document()?.mark(name) else {
+                return Ok(false);
+            };
+            self.jump_with(|doc| {
+                doc.move_cursor_to(position);
+                Ok(())
+            })?;
+            self.emit_cursor_moved();
+            return Ok(true);
+        }
+        let Some(location) = self.global_marks.get(&name).cloned() else {
+            return Ok(false);
+        };
+        if self.active_document().is_ok() {
+            self.push_jump()?;
+        }
+        self.goto(&location)
+    }
+
+    /// Returns the global marks and jump list, for saving.
+    pub fn session(&self) -> Session {
+        let entries = self.jumps.entries();
+        let jump_index = entries[..self.jumps.current()]
+            .iter()
+            .filter(|location| location.saved(&self.documents).is_some())
+            .count();
+        Session {
+            global_marks: self
+                .global_marks
+                .iter()
+                .filter_map(|(&name, location)| Some((name, location.saved(&self.documents)?)))
+                .collect(),
+            jumps: entries
+                .iter()
+                .filter_map(|location| location.saved(&self.documents))
+                .collect(),
+            jump_index,
+        }
+    }
+
+    /// Replaces the global marks and jump list with a saved session.
+    pub fn restore_session(&mut self, session: Session) {
+        let old = self
+            .global_marks
+            .drain()
+            .map(|(_, location)| location)
+            .chain(self.jumps.entries().iter().cloned())
+            .collect();
+        self.release(old);
+
+        self.global_marks = session
+            .global_marks
+            .into_iter()
+            .filter(|&(name, _)| is_global_mark(name))
+            .map(|(name, saved)| (name, Location::Closed(saved)))
+            .collect();
+        let jumps = session.jumps.into_iter().map(Location::Closed).collect();
+        self.jumps.restore(jumps, session.jump_index);
+
+        let open: Vec<DocumentId> = self.documents.order().to_vec();
+        for id in open {
+            self.attach_locations(id);
+        }
+    }
+
+    /// Anchors the primary cursor of the active document.
+    fn here(&mut self) -> CoreResult<Location> {
+        let doc = self.active_document_mut()?;
+        let anchor = doc.anchor_cursor()?;
+        Ok(Location::Open {
+            doc: doc.id(),
+            anchor,
+        })
+    }
+
+    fn record_jump(&mut self, location: Location) {
+        let documents = &self.documents;
+        let dropped = self
+            .jumps
+            .push(location.clone(), |old| old.same_line(&location, documents));
+        self.release(dropped);
+    }
+
+    /// Runs a movement on the active document, recording a jump from
+    /// where the cursor was if it moved.
+    fn jump_with<T>(
+        &mut self,
+        movement: impl FnOnce(&mut Document) -> CoreResult<T>,
+    ) -> CoreResult<T> {
+        let from = self.here()?;
+        let doc = self.active_document_mut()?;
+        let before = doc.cursor_position();
+        let result = movement(doc);
+        if result.is_ok() && self.active_document()?.cursor_position() != before {
+            self.record_jump(from);
+        } else {
+            self.release(vec![from]);
+        }
+        result
+    }
+
+    /// Focuses a location's document and moves the cursor there.
+    ///
+    /// Returns false if it points into a document that's gone.
+    fn goto(&mut self, location: &Location) -> CoreResult<bool> {
+        match location {
+            Location::Open { doc, anchor } => {
+                let Some(position) = self.document(*doc)?.buffer().anchor_position(*anchor) else {
+                    return Ok(false);
+                };
+                self.documents.set_active(*doc);
+                self.emit(EditorEvent::DocumentFocused(*doc));
+                self.move_active_to(position)?;
+            }
+            Location::Closed(saved) => {
+                self.open_file(&saved.path)?;
+                self.move_active_to(saved.position)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Moves the active document's cursor, clamping the line.
+    fn move_active_to(&mut self, position: Position) -> CoreResult<()> {
+        let doc = self.active_document_mut()?;
+        let line = position
+            .line
+            .min(doc.buffer().len_lines().saturating_sub(1));
+        doc.move_cursor_to(Position::new(line, position.column));
+        self.emit_cursor_moved();
+        Ok(())
+    }
+
+    /// Releases the anchors of locations no longer held.
+    fn release(&mut self, locations: Vec<Location>) {
+        for location in locations {
+            if let Location::Open { doc, anchor } = location
+                && let Some(doc) = self.documents.get_mut(doc)
+            {
+                doc.buffer_mut().remove_anchor(anchor);
+            }
+        }
+    }
+
+    /// Anchors the saved locations in a newly opened document.
+    fn attach_locations(&mut self, id: DocumentId) {
+        let Some(doc) = self.documents.get_mut(id) else {
+            return;
+        };
+        let Some(path) = doc.path().map(Path::to_path_buf) else {
+            return;
+        };
+        let locations = self
+            .global_marks
+            .values_mut()
+            .chain(self.jumps.entries_mut());
+        for location in locations {
+            if let Location::Closed(SavedLocation {
+                path: saved,
+                position,
+            }) = location
+                && *saved == path
+                && let Ok(anchor) = doc.anchor_at(*position)
+            {
+                *location = Location::Open { doc: id, anchor };
+            }
+        }
+    }
+
+    /// Turns locations in a document about to close back into paths and
+    /// positions. Those in an untitled document are dropped.
+    fn detach_locations(&mut self, id: DocumentId) {
+        let documents = &self.documents;
+        let detach = |location: &Location| match location {
+            Location::Open { doc, .. } if *doc == id => {
+                location.saved(documents).map(Location::Closed)
+            }
+            other => Some(other.clone()),
+        };
+        self.global_marks = self
+            .global_marks
+            .iter()
+            .filter_map(|(&name, location)| Some((name, detach(location)?)))
+            .collect();
+        let entries = self.jumps.entries();
+        let current = entries[..self.jumps.current()]
+            .iter()
+            .filter_map(detach)
+            .count();
+        let entries = entries.iter().filter_map(detach).collect();
+        self.jumps.restore(entries, current);
+    }
+
     // ==================== Mode ====================
 
     /// Returns the current editor mode.
@@ -366,6 +1145,11 @@ impl Editor {
     /// Updates the configuration.
     pub fn set_config(&mut self, config: Config) {
         self.config = config;
+        for id in self.documents.order().to_vec() {
+            if let Some(doc) = self.documents.get_mut(id) {
+                doc.set_multi_cursor(self.config.editor.multi_cursor);
+            }
+        }
         self.keymap = Keymap::from_config(&self.config);
         self.emit(EditorEvent::ConfigChanged);
     }
@@ -375,6 +1159,130 @@ impl Editor {
         &self.keymap
     }
 
+    // ==================== Recovery ====================
+
+    /// Starts writing recovery files for unsaved documents to `store`.
+    ///
+    /// Call [`leftover_recovery`](Self::leftover_recovery) first to find
+    /// the files an earlier session left behind. Must be called from
+    /// within a Tokio runtime.
+    pub fn start_recovery(&mut self, store: RecoveryStore) {
+        let task = RecoveryTask::spawn(store.clone(), RECOVERY_INTERVAL);
+        for doc in self.documents.iter().filter(|doc| doc.is_modified()) {
+            task.update(doc);
+        }
+        self.recovery = Some((store, task));
+    }
+
+    /// Writes the queued recovery files now, e.g. before quitting.
+    pub async fn flush_recovery(&self) {
+        if let Some((_, task)) = &self.recovery {
+            task.flush().await;
+        }
+    }
+
+    /// Writes the queued recovery files and stops writing more.
+    ///
+    /// Files of documents still unsaved stay behind, and are offered by
+    /// the next editor to start.
+    pub async fn stop_recovery(&mut self) {
+        if let Some((_, task)) = self.recovery.take() {
+            task.shutdown().await;
+        }
+    }
+
+    /// Returns the recovery files that don't belong to an open document:
+    /// the unsaved work of editors that are no longer running.
+    pub fn leftover_recovery(&self) -> CoreResult<Vec<RecoveryFile>> {
+        let mut files = self.recovery_store()?.list()?;
+        files.retain(|file| self.documents.get(file.id).is_none());
+        Ok(files)
+    }
+
+    /// Opens a recovery file: its file if that still exists, or a new
+    /// untitled document. The recovered text replaces the file's as one
+    /// undo step, so the document shows as modified until saved.
+    pub fn restore_recovery(&mut self, file: RecoveryFile) -> CoreResult<DocumentId> {
+        let id = match &file.path {
+            Some(path) if path.exists() => self.open_file(path)?,
+            _ => self.new_document(),
+        };
+        let doc = self.active_document_mut()?;
+        if doc.text() != file.text {
+            let len = doc.buffer().len_chars();
+            doc.apply_changes(&ChangeSet::new().replace(0..len, file.text.as_str()))?;
+        }
+        self.recovery_store()?.remove(file.id)?;
+        self.emit_document_changed();
+        Ok(id)
+    }
+
+    /// Deletes a recovery file without restoring it.
+    pub fn discard_recovery(&self, file: &RecoveryFile) -> CoreResult<()> {
+        self.recovery_store()?.remove(file.id)
+    }
+
+    /// Saves the documents last edited at least `auto_save_delay`
+    /// milliseconds before `now`, and returns them.
+    ///
+    /// The UI calls this from a timer. Untitled and read-only documents
+    /// are left to crash recovery. A failed save is logged, and tried
+    /// again after the next edit.
+    pub fn autosave(&mut self, now: Instant) -> Vec<DocumentId> {
+        let delay = self.config.files.auto_save_delay;
+        if delay == 0 {
+            return Vec::new();
+        }
+        let due: Vec<DocumentId> = self
+            .last_edits
+            .iter()
+            .filter(|&(_, &edited)| {
+                now.saturating_duration_since(edited) >= Duration::from_millis(delay.into())
+            })
+            .map(|(&id, _)| id)
+            .collect();
+
+        let mut saved = Vec::new();
+        for id in due {
+            let Some(doc) = self.documents.get_mut(id) else {
+                self.last_edits.remove(&id);
+                continue;
+            };
+            if !doc.is_modified() || doc.path().is_none() || doc.is_read_only() {
+                self.last_edits.remove(&id);
+                continue;
+            }
+            match doc.save() {
+                Ok(()) => {
+                    self.forget_edits(id);
+                    self.emit(EditorEvent::DocumentSaved(id));
+                    saved.push(id);
+                }
+                Err(e) => {
+                    tracing::warn!("Autosave of {} failed: {}", doc.name(), e);
+                    self.last_edits.remove(&id);
+                }
+            }
+        }
+        saved
+    }
+
+    fn recovery_store(&self) -> CoreResult<&RecoveryStore> {
+        self.recovery
+            .as_ref()
+            .map(|(store, _)| store)
+            .ok_or_else(|| CoreError::Recovery("crash recovery is not running".to_string()))
+    }
+
+    /// Drops the pending autosave and recovery file of a document that
+    /// was saved or closed.
+    fn forget_edits(&mut self, id: DocumentId) {
+        self.last_edits.remove(&id);
+        if let Some((_, task)) = &self.recovery {
+            task.remove(id);
+        }
+    }
+
     // ==================== Lifecycle ====================
 
     /// Signals that the editor should quit.
@@ -404,9 +1312,26 @@ impl Editor {
         self.event_bus.emit(event);
     }
 
-    fn emit_document_changed(&self) {
-        if let Some(doc) = self.documents.active() {
-            self.emit(EditorEvent::DocumentChanged(doc.id()));
+    fn emit_document_changed(&mut self) {
+        if let Some(id) = self.documents.active().map(Document::id) {
+            self.last_edits.insert(id, Instant::now());
+            self.document_changed(id);
+        }
+    }
+
+    /// Announces a change to a document and queues its recovery file.
+    fn document_changed(&self, id: DocumentId) {
+        let Some(doc) = self.documents.get(id) else {
+            return;
+        };
+        self.emit(EditorEvent::DocumentChanged(id));
+        if let Some((_, task)) = &self.recovery {
+            // Undoing back to the saved text leaves nothing to recover
+            if doc.is_modified() {
+                task.update(doc);
+            } else {
+                task.remove(id);
+            }
         }
     }
 
@@ -33,6 +33,12 @@ pub enum EditorEvent {
     DocumentSaved(DocumentId),
     /// A document's content changed
     DocumentChanged(DocumentId),
+    /// Part of a file has been read while opening it
+    LoadProgress {
+        path: std::path::PathBuf,
+        loaded: u64,
+        total: u64,
+    },
     /// A document received focus
     DocumentFocused(DocumentId),
 
@@ -72,6 +72,22 @@ impl Modifiers {
         meta: false,
     };
 
+    /// Ctrl+Alt.
+    pub const CTRL_ALT: Modifiers = Modifiers {
+        ctrl: true,
+        alt: true,
+        shift: false,
+        meta: false,
+    };
+
+    /// Alt+Shift.
+    pub const ALT_SHIFT: Modifiers = Modifiers {
+        ctrl: false,
+        alt: true,
+        shift: true,
+        meta: false,
+    };
+
     /// Returns true if no modifiers are pressed.
     pub fn is_empty(&self) -> bool {
         !self.ctrl && !self.alt && !self.shift && !self.meta
@@ -307,6 +323,7 @@ impl Keymap {
     }
 
     /// Creates a keymap from configuration.
+    #[allow(clippy::collapsible_if)]
     pub fn from_config(config: &Config) -> Self {
         let mut keymap = Self::new();
 
@@ -386,6 +403,78 @@ impl Keymap {
                 SelectAll,
                 vec![Normal, Insert],
             ),
+            // Indentation
+            (
+                KeyPress::new(Key::Tab, Modifiers::NONE),
+                InsertTab,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Tab, Modifiers::SHIFT),
+                Outdent,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Char(']'), Modifiers::CTRL),
+                Indent,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('['), Modifiers::CTRL),
+                Outdent,
+                vec![Normal, Insert],
+            ),
+            // Search
+            (
+                KeyPress::new(Key::Char('f'), Modifiers::CTRL),
+                Find { pattern: None },
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::F(3), Modifiers::NONE),
+                FindNext,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::F(3), Modifiers::SHIFT),
+                FindPrevious,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('h'), Modifiers::CTRL),
+                Replace { replacement: None },
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('d'), Modifiers::CTRL),
+                AddNextOccurrence,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('l'), Modifiers::CTRL_SHIFT),
+                SelectAllOccurrences,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('\\'), Modifiers::CTRL_SHIFT),
+                JumpToMatchingBracket,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('g'), Modifiers::CTRL),
+                GotoLine { line: None },
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('-'), Modifiers::CTRL_ALT),
+                JumpBack,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('-'), Modifiers::CTRL_SHIFT),
+                JumpForward,
+                vec![Normal, Insert],
+            ),
             // Cursor movement (Insert mode)
             (
                 KeyPress::new(Key::Up, Modifiers::NONE),
@@ -407,6 +496,91 @@ impl Keymap {
                 MoveRight { count: 1 },
                 vec![Insert],
             ),
+            (
+                KeyPress::new(Key::Left, Modifiers::CTRL),
+                MoveWordLeft,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Right, Modifiers::CTRL),
+                MoveWordRight,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Left, Modifiers::ALT),
+                MoveSubwordLeft,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Right, Modifiers::ALT),
+                MoveSubwordRight,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Backspace, Modifiers::CTRL),
+                DeleteWordBackward,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Delete, Modifiers::CTRL),
+                DeleteWordForward,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Up, Modifiers::CTRL_ALT),
+                AddCursorAbove,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Down, Modifiers::CTRL_ALT),
+                AddCursorBelow,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Up, Modifiers::ALT),
+                MoveLinesUp,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Down, Modifiers::ALT),
+                MoveLinesDown,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('k'), Modifiers::CTRL_SHIFT),
+                DeleteLine,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('d'), Modifiers::CTRL_SHIFT),
+                DuplicateLine,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Char('j'), Modifiers::CTRL),
+                JoinLines,
+                vec![Normal, Insert],
+            ),
+            (
+                KeyPress::new(Key::Up, Modifiers::ALT_SHIFT),
+                BlockSelectUp,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Down, Modifiers::ALT_SHIFT),
+                BlockSelectDown,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Left, Modifiers::ALT_SHIFT),
+                BlockSelectLeft,
+                vec![Insert],
+            ),
+            (
+                KeyPress::new(Key::Right, Modifiers::ALT_SHIFT),
+                BlockSelectRight,
+                vec![Insert],
+            ),
             (
                 KeyPress::new(Key::Home, Modifiers::NONE),
                 MoveToLineStart,
@@ -438,6 +612,11 @@ impl Keymap {
                 MoveRight { count: 1 },
                 vec![Normal],
             ),
+            (
+                KeyPress::new(Key::Char('%'), Modifiers::NONE),
+                JumpToMatchingBracket,
+                vec![Normal],
+            ),
             (
                 KeyPress::new(Key::Char('i'), Modifiers::NONE),
                 EnterInsertMode,
@@ -469,9 +648,21 @@ impl Keymap {
                 when: None,
             });
         }
+
+        // Chords
+        self.bindings.push(KeyBinding {
+            keys: vec![
+                KeyPress::new(Key::Char('k'), Modifiers::CTRL),
+                KeyPress::new(Key::Char('d'), Modifiers::CTRL),
+            ],
+            command: SkipOccurrence,
+            modes: vec![Normal, Insert],
+            when: None,
+        });
     }
 
     /// Rebuilds the key index.
+    #[allow(clippy::unwrap_or_default)]
     fn rebuild_index(&mut self) {
         self.by_key.clear();
         for (i, binding) in self.bindings.iter().enumerate() {
@@ -32,6 +32,9 @@ pub mod document;
 pub mod editor;
 pub mod event;
 pub mod keymap;
+pub mod marks;
+pub mod recovery;
+pub mod save;
 pub mod workspace;
 
 pub use command::{Command, CommandContext, CommandRegistry};
@@ -40,6 +43,9 @@ pub use document::{Document, DocumentId};
 pub use editor::Editor;
 pub use event::{EditorEvent, EventBus};
 pub use keymap::{KeyBinding, Keymap};
+pub use marks::{SavedLocation, Session};
+pub use recovery::{RecoveryFile, RecoveryStore};
+pub use save::SaveOptions;
 pub use workspace::Workspace;
 
 /// Result type for core operations
@@ -71,4 +77,13 @@ pub enum CoreError {
 
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
+
+    #[error("Document is read-only: {0}")]
+    ReadOnly(String),
+
+    #[error("Session error: {0}")]
+    Session(String),
+
+    #[error("Recovery error: {0}")]
+    Recovery(String),
 }
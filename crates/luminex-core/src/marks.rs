@@ -0,0 +1,275 @@
+//! Named marks, the jump list and the session that keeps them.
+//!
+//! ## Learning: Where Was I?
+//!
+//! Two kinds of bookmarks make it cheap to wander off and come back:
+//!
+//! - **Marks** are set by name. `a`–`z` belong to one file; `A`–`Z`
+//!   are global and remember the file too, so jumping to one can open
+//!   it.
+//! - **The jump list** is set automatically. Every big movement (go to
+//!   line, search, go to definition) first records where the cursor
+//!   was, and back/forward walk that history like a browser's.
+//!
+//! ```text
+//!   jumps: [main.rs:10] [lib.rs:88] [main.rs:200]  (here)
+//!   back:                            ^ current
+//!   back:               ^ current
+//!   forward:                         ^ current
+//!   a new jump drops everything after current, then appends
+//! ```
+//!
+//! While a file is open its marks are buffer anchors
+//! (see [`AnchorId`]), so they stay on their text as it's edited. When
+//! the file is closed they become a plain path and position, which is
+//! also the form saved in the [`Session`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use luminex_buffer::{AnchorId, Position};
+
+use crate::document::{DocumentId, DocumentManager};
+use crate::{CoreError, CoreResult};
+
+/// How many jumps are remembered.
+pub const MAX_JUMPS: usize = 100;
+
+/// Where a global mark or jump points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Location {
+    /// In an open document, anchored to its text
+    Open { doc: DocumentId, anchor: AnchorId },
+    /// In a file that isn't open
+    Closed(SavedLocation),
+}
+
+impl Location {
+    /// Returns the file and position this points to now, or `None` if
+    /// its document is gone or has never been saved.
+    pub(crate) fn saved(&self, documents: &DocumentManager) -> Option<SavedLocation> {
+        match self {
+            Location::Open { doc, anchor } => {
+                let doc = documents.get(*doc)?;
+                Some(SavedLocation {
+                    path: doc.path()?.to_path_buf(),
+                    position: doc.buffer().anchor_position(*anchor)?,
+                })
+            }
+            Location::Closed(saved) => Some(saved.clone()),
+        }
+    }
+
+    /// Returns true if both point into the same line of the same file.
+    pub(crate) fn same_line(&self, other: &Location, documents: &DocumentManager) -> bool {
+        match (self, other) {
+            (
+                Location::Open { doc, anchor: a },
+                Location::Open {
+                    doc: other_doc,
+                    anchor: b,
+                },
+            ) => {
+                let line =
+                    |anchor| Some(documents.get(*doc)?.buffer().anchor_position(anchor)?.line);
+                doc == other_doc && line(*a).is_some() && line(*a) == line(*b)
+            }
+            (Location::Closed(a), Location::Closed(b)) => {
+                a.path == b.path && a.position.line == b.position.line
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A position in a file, as saved in a session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedLocation {
+    pub path: PathBuf,
+    pub position: Position,
+}
+
+/// Back/forward history of big movements.
+#[derive(Debug, Default)]
+pub(crate) struct JumpList {
+    entries: Vec<Location>,
+    /// Index of the entry last jumped to; `entries.len()` when not
+    /// walking the list
+    current: usize,
+}
+
+impl JumpList {
+    /// Records a location, dropping any entries after the current one
+    /// and older ones for which `same_place` is true.
+    ///
+    /// Returns the dropped entries so their anchors can be released.
+    pub(crate) fn push(
+        &mut self,
+        location: Location,
+        same_place: impl Fn(&Location) -> bool,
+    ) -> Vec<Location> {
+        let ahead = if self.at_end() {
+            self.entries.len()
+        } else {
+            self.current + 1
+        };
+        let mut dropped = self.entries.split_off(ahead);
+        let (duplicates, kept) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(same_place);
+        dropped.extend::<Vec<_>>(duplicates);
+        self.entries = kept;
+        self.entries.push(location);
+        if self.entries.len() > MAX_JUMPS {
+            dropped.extend(self.entries.drain(..self.entries.len() - MAX_JUMPS));
+        }
+        self.current = self.entries.len();
+        dropped
+    }
+
+    /// Returns true when not walking the list: nothing has been jumped
+    /// back to since the last push.
+    pub(crate) fn at_end(&self) -> bool {
+        self.current >= self.entries.len()
+    }
+
+    /// Steps back, returning the location to go to.
+    pub(crate) fn back(&mut self) -> Option<&Location> {
+        self.current = self.current.checked_sub(1)?;
+        self.entries.get(self.current)
+    }
+
+    /// Steps forward, returning the location to go to.
+    pub(crate) fn forward(&mut self) -> Option<&Location> {
+        if self.current + 1 >= self.entries.len() {
+            return None;
+        }
+        self.current += 1;
+        self.entries.get(self.current)
+    }
+
+    /// Returns every entry, oldest first.
+    pub(crate) fn entries(&self) -> &[Location] {
+        &self.entries
+    }
+
+    /// Returns every entry for updating in place.
+    pub(crate) fn entries_mut(&mut self) -> &mut [Location] {
+        &mut self.entries
+    }
+
+    /// Returns the index of the current entry.
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Replaces the whole list.
+    pub(crate) fn restore(&mut self, entries: Vec<Location>, current: usize) {
+        self.current = current.min(entries.len());
+        self.entries = entries;
+    }
+}
+
+/// Returns true for `a`–`z`, the names of marks local to a file.
+pub fn is_local_mark(name: char) -> bool {
+    name.is_ascii_lowercase()
+}
+
+/// Returns true for `A`–`Z`, the names of global marks.
+pub fn is_global_mark(name: char) -> bool {
+    name.is_ascii_uppercase()
+}
+
+/// State that outlives the editor process: global marks and the jump
+/// list. Marks local to a file are not kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub global_marks: BTreeMap<char, SavedLocation>,
+    #[serde(default)]
+    pub jumps: Vec<SavedLocation>,
+    /// Index of the current jump (`jumps.len()` when not walking it)
+    #[serde(default)]
+    pub jump_index: usize,
+}
+
+impl Session {
+    /// Loads a session saved by [`save`](Self::save).
+    pub fn load(path: impl AsRef<Path>) -> CoreResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| CoreError::Session(e.to_string()))
+    }
+
+    /// Saves the session as JSON, creating the directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> CoreResult<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content =
+            serde_json::to_string_pretty(self).map_err(|e| CoreError::Session(e.to_string()))?;
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Returns the default session file path.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("luminex").join("session.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize) -> Location {
+        Location::Closed(SavedLocation {
+            path: PathBuf::from("a.rs"),
+            position: Position::new(line, 0),
+        })
+    }
+
+    #[test]
+    fn test_jump_list_walks_and_truncates() {
+        let mut jumps = JumpList::default();
+        for line in 0..3 {
+            assert!(jumps.push(at(line), |_| false).is_empty());
+        }
+        assert!(jumps.at_end());
+        assert_eq!(jumps.back(), Some(&at(2)));
+        assert_eq!(jumps.back(), Some(&at(1)));
+        assert_eq!(jumps.forward(), Some(&at(2)));
+        assert_eq!(jumps.forward(), None);
+
+        // A new jump drops what was ahead, and the older copy of line 0
+        jumps.back();
+        let dropped = jumps.push(at(0), |l| *l == at(0));
+        assert_eq!(dropped, [at(2), at(0)]);
+        assert_eq!(jumps.entries(), [at(1), at(0)]);
+        assert!(jumps.at_end());
+
+        for line in 0..MAX_JUMPS {
+            jumps.push(at(line + 10), |_| false);
+        }
+        assert_eq!(jumps.entries().len(), MAX_JUMPS);
+        assert_eq!(jumps.entries()[0], at(10));
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("session.json");
+        let mut session = Session::default();
+        session.global_marks.insert(
+            'A',
+            SavedLocation {
+                path: PathBuf::from("/src/main.rs"),
+                position: Position::new(3, 4),
+            },
+        );
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).unwrap(), session);
+        assert!(Session::load(dir.path().join("missing.json")).is_err());
+    }
+}
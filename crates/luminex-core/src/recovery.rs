@@ -0,0 +1,502 @@
+//! Crash recovery: copies of unsaved documents written in the background.
+//!
+//! ## Learning: Snapshots Cross Threads Cheaply
+//!
+//! The editor lives on the UI thread, and writing files there would
+//! stall typing. Instead, every change sends a [`BufferSnapshot`] of the
+//! document to a background task. A snapshot shares the rope's nodes
+//! with the live buffer, so sending one copies nothing; only the task
+//! turns it into text, on a blocking thread, when its timer fires.
+//!
+//! ```text
+//!   UI thread                        recovery task
+//!   edit ──snapshot──▶ channel ──▶ pending[doc] = latest
+//!   edit ──snapshot──▶ channel ──▶ pending[doc] = latest  (replaces)
+//!   save ──remove────▶ channel ──▶ delete doc's file
+//!                                   every RECOVERY_INTERVAL:
+//!                                     write pending as JSON files
+//! ```
+//!
+//! A clean save or close removes a document's file, so any file still
+//! in the store when the editor starts belongs to a session that ended
+//! with unsaved changes, usually a crash. The UI offers to restore or
+//! discard each one.
+//!
+//! Several editors can run at once, so each writes to a directory of
+//! its own and holds a lock on a file in it for as long as it runs. The
+//! OS drops the lock when the process ends, however it ends, so a
+//! directory whose lock can be taken belongs to an editor that is gone.
+//!
+//! ```text
+//!   recovery/
+//!     4021-1f0c…/   lock (held: running)   <doc id>.json …
+//!     3977-a83e…/   lock (free: crashed)   <doc id>.json …   ◀ offered
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+use luminex_buffer::BufferSnapshot;
+
+use crate::document::{Document, DocumentId};
+use crate::{CoreError, CoreResult};
+
+/// How often the recovery task writes pending documents.
+pub const RECOVERY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The file an editor keeps locked in its directory while it runs.
+const LOCK_FILE: &str = "lock";
+
+/// The extension of an editor's directory while it is being set up.
+const CLAIMING: &str = "new";
+
+/// An unsaved document as written to the recovery store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryFile {
+    /// The document it was written for
+    pub id: DocumentId,
+    /// The document's file, or `None` if it was untitled
+    pub path: Option<PathBuf>,
+    /// The document's display name
+    pub name: String,
+    /// When it was written, in seconds since the Unix epoch
+    pub written_at: u64,
+    /// The unsaved text
+    pub text: String,
+}
+
+/// The directory recovery files are kept in.
+///
+/// Clones share one directory of their own, locked from the first
+/// write until the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct RecoveryStore {
+    dir: PathBuf,
+    /// This editor's directory, inside `dir`
+    own: PathBuf,
+    /// The locked file in `own`, once written to
+    lock: Arc<Mutex<Option<File>>>,
+}
+
+impl RecoveryStore {
+    /// Uses `dir`, which is created on the first write.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let own = dir.join(format!(
+            "{}-{}",
+            std::process::id(),
+            Uuid::new_v4().simple()
+        ));
+        Self {
+            dir,
+            own,
+            lock: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns the default recovery directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("luminex").join("recovery"))
+    }
+
+    /// Returns the directory of this store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes a recovery file, replacing any earlier one for its document.
+    pub fn write(&self, file: &RecoveryFile) -> CoreResult<()> {
+        self.claim()?;
+        let content =
+            serde_json::to_string(file).map_err(|e| CoreError::Recovery(e.to_string()))?;
+        // Never leave a half-written file where a crash could find it
+        let path = self.own.join(file_name(file.id));
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, content)?;
+        std::fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    /// Removes the recovery file of a document, if there is one, from
+    /// this editor's directory or that of one no longer running.
+    pub fn remove(&self, id: DocumentId) -> CoreResult<()> {
+        remove_if_exists(&self.own.join(file_name(id)))?;
+        for dir in self.dead_dirs()? {
+            remove_if_exists(&dir.join(file_name(id)))?;
+            remove_if_done(&dir);
+        }
+        Ok(())
+    }
+
+    /// Returns the recovery files of editors no longer running, oldest
+    /// first.
+    ///
+    /// Files that can't be read are skipped with a warning.
+    pub fn list(&self) -> CoreResult<Vec<RecoveryFile>> {
+        let mut files = Vec::new();
+        for dir in self.dead_dirs()? {
+            remove_if_done(&dir);
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                let parsed = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| {
+                        serde_json::from_str::<RecoveryFile>(&content).map_err(|e| e.to_string())
+                    });
+                match parsed {
+                    Ok(file) => files.push(file),
+                    Err(e) => tracing::warn!("Skipping recovery file {}: {}", path.display(), e),
+                }
+            }
+        }
+        files.sort_by_key(|file| file.written_at);
+        Ok(files)
+    }
+
+    /// Creates and locks this editor's directory, the first time.
+    fn claim(&self) -> CoreResult<()> {
+        let mut lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if lock.is_none() {
+            // Lock the file before the directory appears under its real
+            // name, so no other editor ever sees it unlocked
+            let claiming = self.own.with_extension(CLAIMING);
+            std::fs::create_dir_all(&claiming)?;
+            let mut file = File::create(claiming.join(LOCK_FILE))?;
+            file.try_lock()
+                .map_err(|e| CoreError::Recovery(e.to_string()))?;
+            // For whoever looks at the directory; the lock is what counts
+            writeln!(file, "{}", std::process::id())?;
+            std::fs::rename(&claiming, &self.own)?;
+            *lock = Some(file);
+        }
+        Ok(())
+    }
+
+    /// Returns the directories of other editors that are no longer
+    /// running.
+    fn dead_dirs(&self) -> CoreResult<Vec<PathBuf>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut dirs = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let claiming = path.extension().is_some_and(|ext| ext == CLAIMING);
+            if path.is_dir() && path != self.own && !claiming && !is_running(&path) {
+                dirs.push(path);
+            }
+        }
+        Ok(dirs)
+    }
+}
+
+/// True if the editor owning `dir` still holds its lock.
+fn is_running(dir: &Path) -> bool {
+    let Ok(file) = File::open(dir.join(LOCK_FILE)) else {
+        return false;
+    };
+    // Taking the lock (dropped again with `file`) means its owner is gone
+    matches!(file.try_lock(), Err(TryLockError::WouldBlock))
+}
+
+/// Deletes a dead editor's directory once no recovery files are left.
+fn remove_if_done(dir: &Path) {
+    let has_files = std::fs::read_dir(dir).is_ok_and(|mut entries| {
+        entries.any(|entry| entry.is_ok_and(|entry| entry.file_name() != LOCK_FILE))
+    });
+    if !has_files && let Err(e) = std::fs::remove_dir_all(dir) {
+        tracing::warn!("Could not remove {}: {}", dir.display(), e);
+    }
+}
+
+fn remove_if_exists(path: &Path) -> CoreResult<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn file_name(id: DocumentId) -> String {
+    format!("{id}.json")
+}
+
+/// A document waiting to be written.
+struct Pending {
+    path: Option<PathBuf>,
+    name: String,
+    snapshot: BufferSnapshot,
+}
+
+enum Message {
+    Update(DocumentId, Pending),
+    Remove(DocumentId),
+    Flush(oneshot::Sender<()>),
+}
+
+/// The background task writing recovery files.
+pub struct RecoveryTask {
+    tx: mpsc::UnboundedSender<Message>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl RecoveryTask {
+    /// Starts the task, writing to `store` every `interval`.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn(store: RecoveryStore, interval: Duration) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(async move {
+            let mut pending = HashMap::new();
+            let mut timer = tokio::time::interval(interval);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    message = rx.recv() => match message {
+                        Some(Message::Update(id, update)) => {
+                            pending.insert(id, update);
+                        }
+                        Some(Message::Remove(id)) => {
+                            pending.remove(&id);
+                            if let Err(e) = store.remove(id) {
+                                tracing::warn!("Could not remove recovery file: {}", e);
+                            }
+                        }
+                        Some(Message::Flush(done)) => {
+                            write_pending(&store, &mut pending).await;
+                            let _ = done.send(());
+                        }
+                        None => {
+                            write_pending(&store, &mut pending).await;
+                            break;
+                        }
+                    },
+                    _ = timer.tick() => write_pending(&store, &mut pending).await,
+                }
+            }
+        });
+        Self { tx, task }
+    }
+
+    /// Queues the document's current text to be written.
+    pub fn update(&self, doc: &Document) {
+        let pending = Pending {
+            path: doc.path().map(Path::to_path_buf),
+            name: doc.name().to_string(),
+            snapshot: doc.snapshot(),
+        };
+        let _ = self.tx.send(Message::Update(doc.id(), pending));
+    }
+
+    /// Removes a document's recovery file, dropping any queued write.
+    pub fn remove(&self, id: DocumentId) {
+        let _ = self.tx.send(Message::Remove(id));
+    }
+
+    /// Writes everything queued so far without waiting for the timer.
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.tx.send(Message::Flush(done)).is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    /// Writes everything queued, then stops the task.
+    pub async fn shutdown(self) {
+        drop(self.tx);
+        let _ = self.task.await;
+    }
+}
+
+/// Writes the pending documents on a blocking thread.
+async fn write_pending(store: &RecoveryStore, pending: &mut HashMap<DocumentId, Pending>) {
+    if pending.is_empty() {
+        return;
+    }
+    let batch: Vec<(DocumentId, Pending)> = pending.drain().collect();
+    let store = store.clone();
+    let written = tokio::task::spawn_blocking(move || {
+        let written_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        for (id, doc) in batch {
+            let file = RecoveryFile {
+                id,
+                path: doc.path,
+                name: doc.name,
+                written_at,
+                text: doc.snapshot.text().into_owned(),
+            };
+            if let Err(e) = store.write(&file) {
+                tracing::warn!("Could not write recovery file for {}: {}", file.name, e);
+            }
+        }
+    })
+    .await;
+    if let Err(e) = written {
+        tracing::warn!("Recovery writer failed: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Editor;
+    use std::time::Instant;
+
+    fn file(name: &str, written_at: u64) -> RecoveryFile {
+        RecoveryFile {
+            id: DocumentId::new(),
+            path: None,
+            name: name.to_string(),
+            written_at,
+            text: format!("text of {name}"),
+        }
+    }
+
+    /// Counts the recovery files of every editor in `dir`.
+    fn count_files(dir: &Path) -> usize {
+        let dirs = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path());
+        dirs.flat_map(|dir| std::fs::read_dir(dir).unwrap())
+            .filter(|entry| entry.as_ref().unwrap().file_name() != LOCK_FILE)
+            .count()
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("recovery");
+        let crashed = RecoveryStore::new(&root);
+        let store = RecoveryStore::new(&root);
+        assert!(store.list().unwrap().is_empty());
+
+        let (a, b) = (file("a", 20), file("b", 10));
+        crashed.write(&a).unwrap();
+        crashed.write(&b).unwrap();
+        store.write(&file("own", 0)).unwrap();
+        // Another running editor's files, and our own, aren't leftovers
+        assert!(store.list().unwrap().is_empty());
+
+        drop(crashed);
+        assert_eq!(store.list().unwrap(), [b.clone(), a.clone()]);
+        store.remove(b.id).unwrap();
+        store.remove(b.id).unwrap();
+        assert_eq!(store.list().unwrap(), std::slice::from_ref(&a));
+
+        // A dead editor's directory goes with its last file
+        store.remove(a.id).unwrap();
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 1);
+
+        let junk = RecoveryStore::new(&root);
+        junk.write(&file("junk", 0)).unwrap();
+        std::fs::write(junk.own.join("junk.json"), "{").unwrap();
+        drop(junk);
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_task_writes_latest_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RecoveryStore::new(dir.path());
+        let task = RecoveryTask::spawn(store.clone(), Duration::from_secs(3600));
+
+        let mut doc = Document::new();
+        doc.insert_at_cursor("one").unwrap();
+        task.update(&doc);
+        doc.insert_at_cursor(" two").unwrap();
+        task.update(&doc);
+        task.flush().await;
+        let written = std::fs::read_to_string(store.own.join(file_name(doc.id()))).unwrap();
+        let file: RecoveryFile = serde_json::from_str(&written).unwrap();
+        assert_eq!((file.id, file.text.as_str()), (doc.id(), "one two"));
+
+        task.remove(doc.id());
+        task.shutdown().await;
+        assert_eq!(count_files(dir.path()), 0);
+    }
+
+    #[tokio::test]
+    async fn test_editor_recovers_unsaved_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("recovery");
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "saved\n").unwrap();
+
+        // A session that ends without saving
+        let mut crashed = Editor::new();
+        crashed.start_recovery(RecoveryStore::new(&root));
+        crashed.new_document();
+        crashed.insert_text("untitled work").unwrap();
+        crashed.open_file(&path).unwrap();
+        crashed.insert_text("more ").unwrap();
+        crashed.flush_recovery().await;
+        assert!(crashed.leftover_recovery().unwrap().is_empty());
+
+        // Nothing is offered until it stops
+        let mut editor = Editor::new();
+        editor.start_recovery(RecoveryStore::new(&root));
+        assert!(editor.leftover_recovery().unwrap().is_empty());
+        crashed.stop_recovery().await;
+        let files = editor.leftover_recovery().unwrap();
+        assert_eq!(files.len(), 2);
+        let (file, untitled) = match files[0].path {
+            Some(_) => (files[0].clone(), files[1].clone()),
+            None => (files[1].clone(), files[0].clone()),
+        };
+        assert_eq!(untitled.text, "untitled work");
+        editor.discard_recovery(&untitled).unwrap();
+
+        editor.restore_recovery(file).unwrap();
+        let doc = editor.active_document().unwrap();
+        assert_eq!(
+            (doc.text().as_ref(), doc.is_modified()),
+            ("more saved\n", true)
+        );
+        editor.flush_recovery().await;
+        assert_eq!(editor.leftover_recovery().unwrap(), []);
+        assert_eq!(count_files(&root), 1);
+
+        editor.save_current().unwrap();
+        editor.flush_recovery().await;
+        assert_eq!(count_files(&root), 0);
+    }
+
+    #[test]
+    fn test_autosave_after_delay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auto.txt");
+        std::fs::write(&path, "").unwrap();
+
+        let mut config = crate::Config::default();
+        config.files.auto_save_delay = 1000;
+        let mut editor = Editor::with_config(config);
+        let id = editor.open_file(&path).unwrap();
+        editor.new_document();
+        editor.insert_text("untitled").unwrap();
+        editor.open_file(&path).unwrap();
+        editor.insert_text("typed").unwrap();
+
+        let edited = Instant::now();
+        assert!(editor.autosave(edited).is_empty());
+        assert_eq!(editor.autosave(edited + Duration::from_secs(2)), [id]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "typed\n");
+        assert!(editor.autosave(edited + Duration::from_secs(4)).is_empty());
+        assert!(editor.has_unsaved_changes());
+    }
+}
@@ -0,0 +1,179 @@
+//! The save pipeline: cleanup, backup, write, restore the owner.
+//!
+//! ## Learning: A Pipeline of Small Steps
+//!
+//! Saving is more than writing bytes. Each step is simple on its own,
+//! and the order matters:
+//!
+//! ```text
+//!   1. cleanup   trim trailing whitespace, add the final newline
+//!                (one edit, so a single undo brings it all back)
+//!   2. backup    copy the file as it is on disk to `name~`
+//!   3. write     the buffer encodes and writes the text
+//!   4. owner     put back the owner and group the old file had
+//! ```
+//!
+//! Step 4 is needed because the buffer writes a new file and renames it
+//! over the old one. The buffer copies the mode bits across, so a
+//! script keeps its executable bit, but a new file belongs to whoever
+//! created it.
+//!
+//! Which steps run comes from `FileConfig`, and a language can turn any
+//! of them off (Markdown uses trailing spaces as line breaks).
+
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use luminex_buffer::{ChangeSet, TextBuffer};
+
+use crate::config::Config;
+
+/// Which save steps run for a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveOptions {
+    /// Remove whitespace at the end of every line
+    pub trim_trailing_whitespace: bool,
+    /// End a non-empty file with a newline
+    pub final_newline: bool,
+    /// Copy the old file to `name~` before overwriting it
+    pub backup: bool,
+}
+
+impl SaveOptions {
+    /// Resolves the save steps for a document: per-language settings
+    /// override the `FileConfig` ones.
+    pub fn resolve(language: Option<&str>, config: &Config) -> Self {
+        let lang = language.map(|l| config.language(l)).unwrap_or_default();
+        Self {
+            trim_trailing_whitespace: lang
+                .trim_trailing_whitespace
+                .unwrap_or(config.files.trim_trailing_whitespace),
+            final_newline: lang.final_newline.unwrap_or(config.files.final_newline),
+            backup: lang.backup_on_save.unwrap_or(config.files.backup_on_save),
+        }
+    }
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self::resolve(None, &Config::default())
+    }
+}
+
+/// Returns the edits the cleanup step makes to `buffer`.
+pub(crate) fn cleanup_changes(buffer: &TextBuffer, options: SaveOptions) -> ChangeSet {
+    let mut changes = ChangeSet::new();
+    let text = buffer.text();
+    if options.trim_trailing_whitespace {
+        let mut line_start = 0;
+        for line in text.split('\n') {
+            let len = line.chars().count();
+            let trimmed = line.trim_end().chars().count();
+            if trimmed < len {
+                changes = changes.delete(line_start + trimmed..line_start + len);
+            }
+            line_start += len + 1;
+        }
+    }
+    if options.final_newline {
+        // Judge the text as it will be once trimmed
+        let content = if options.trim_trailing_whitespace {
+            text.trim_end_matches(|c: char| c.is_whitespace() && c != '\n')
+        } else {
+            &text
+        };
+        if !content.is_empty() && !content.ends_with('\n') {
+            changes = changes.insert(buffer.len_chars(), "\n");
+        }
+    }
+    changes
+}
+
+/// Returns where the backup of `path` goes.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push("~");
+    path.with_file_name(name)
+}
+
+/// Copies `path` to its backup. Does nothing if the file doesn't exist
+/// yet.
+pub(crate) fn write_backup(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        std::fs::copy(path, backup_path(path))?;
+    }
+    Ok(())
+}
+
+/// Gives `path` the owner and group from `metadata`, on Unix.
+///
+/// Only root may give a file away, so a failed change is ignored: the
+/// file then belongs to whoever saved it, as with any other editor.
+pub(crate) fn restore_owner(path: &Path, metadata: &Metadata) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid()));
+    }
+    #[cfg(not(unix))]
+    let _ = (path, metadata);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LanguageConfig;
+
+    fn cleaned(text: &str, options: SaveOptions) -> String {
+        let mut buffer = TextBuffer::from(text);
+        buffer
+            .apply_changes(&cleanup_changes(&buffer, options))
+            .unwrap();
+        buffer.text().into_owned()
+    }
+
+    #[test]
+    fn test_cleanup() {
+        let all = SaveOptions {
+            trim_trailing_whitespace: true,
+            final_newline: true,
+            backup: false,
+        };
+        assert_eq!(cleaned("a  \n\tb\t\n  \nc ", all), "a\n\tb\n\nc\n");
+        assert_eq!(cleaned("a\n", all), "a\n");
+        assert_eq!(cleaned("", all), "");
+        assert_eq!(cleaned("   ", all), "");
+
+        let trim_only = SaveOptions {
+            final_newline: false,
+            ..all
+        };
+        assert_eq!(cleaned("a \nb ", trim_only), "a\nb");
+        let newline_only = SaveOptions {
+            trim_trailing_whitespace: false,
+            ..all
+        };
+        assert_eq!(cleaned("a \nb ", newline_only), "a \nb \n");
+    }
+
+    #[test]
+    fn test_language_overrides() {
+        let mut config = Config::default();
+        config.files.backup_on_save = true;
+        config.languages.insert(
+            "markdown".to_string(),
+            LanguageConfig {
+                trim_trailing_whitespace: Some(false),
+                ..Default::default()
+            },
+        );
+
+        let markdown = SaveOptions::resolve(Some("markdown"), &config);
+        assert!(!markdown.trim_trailing_whitespace && markdown.final_newline && markdown.backup);
+        assert!(SaveOptions::resolve(Some("rust"), &config).trim_trailing_whitespace);
+        assert_eq!(
+            backup_path(Path::new("/src/main.rs")),
+            Path::new("/src/main.rs~")
+        );
+    }
+}
@@ -158,6 +158,7 @@ impl FileTree {
     }
 
     /// Builds a file node recursively.
+    #[allow(clippy::collapsible_if)]
     fn build_node(path: &Path, depth: usize) -> CoreResult<FileNode> {
         let name = path
             .file_name()
@@ -224,6 +225,7 @@ impl FileTree {
         results
     }
 
+    #[allow(clippy::only_used_in_recursion)]
     fn find_files_recursive(
         &self,
         node: &FileNode,
@@ -332,6 +334,7 @@ pub enum NodeKind {
     Directory,
 }
 
+#[allow(clippy::io_other_error)]
 impl From<notify::Error> for CoreError {
     fn from(err: notify::Error) -> Self {
         CoreError::Io(std::io::Error::new(
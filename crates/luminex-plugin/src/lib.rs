@@ -239,6 +239,7 @@ impl PluginContext {
     }
 
     /// Registers a command handler.
+    #[allow(clippy::type_complexity)]
     pub fn register_command(&mut self, _id: &str, _handler: Box<dyn Fn(&[String]) + Send + Sync>) {
         // Would register with command system
     }
@@ -18,6 +18,7 @@
 //! - The `tree-sitter` crate provides safe wrappers
 
 use std::collections::HashMap;
+use std::ops::Range;
 use tree_sitter::{Language, Node, Parser, Tree};
 
 /// Errors that can occur during syntax highlighting.
@@ -111,6 +112,18 @@ impl Highlighter {
         }
     }
 
+    /// Returns the byte ranges of strings and comments, in order.
+    ///
+    /// Brackets and quotes inside them aren't code, so bracket matching
+    /// skips these ranges. Empty until the source has been parsed.
+    pub fn non_code_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        if let Some(tree) = &self.tree {
+            collect_non_code(tree.root_node(), &mut ranges);
+        }
+        ranges
+    }
+
     /// Returns the syntax tree (for debugging).
     pub fn tree(&self) -> Option<&Tree> {
         self.tree.as_ref()
@@ -177,6 +190,21 @@ impl HighlightKind {
     }
 }
 
+/// Collects string and comment nodes, without descending into them.
+fn collect_non_code(node: Node, ranges: &mut Vec<Range<usize>>) {
+    if matches!(
+        highlight_kind_from_node(node.kind()),
+        HighlightKind::String | HighlightKind::Comment
+    ) {
+        ranges.push(node.start_byte()..node.end_byte());
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_non_code(child, ranges);
+    }
+}
+
 /// Maps node kinds to highlight kinds based on common tree-sitter node types.
 fn highlight_kind_from_node(kind: &str) -> HighlightKind {
     match kind {
@@ -287,6 +315,17 @@ mod tests {
         assert!(!spans.is_empty());
     }
 
+    #[test]
+    fn test_non_code_ranges() {
+        let mut highlighter = Highlighter::new("rust").unwrap();
+        let source = "let s = \"(\"; // )\nf(s)";
+        highlighter.parse(source).unwrap();
+
+        let ranges = highlighter.non_code_ranges();
+        let text: Vec<&str> = ranges.iter().map(|r| &source[r.clone()]).collect();
+        assert_eq!(text, ["\"(\"", "// )"]);
+    }
+
     #[test]
     fn test_unknown_language() {
         let result = Highlighter::new("unknown_lang");
@@ -3,11 +3,15 @@
 //! A fully functional text editor with file browsing and editing.
 
 use iced::keyboard;
+use iced::widget::text::Wrapping;
 use iced::widget::{
     button, column, container, horizontal_space, row, scrollable, stack, text, text_editor, text_input,
     Column, Row, Space, mouse_area,
 };
 use iced::{Background, Border, Color, Element, Font, Length, Padding, Point, Subscription, Task, Theme};
+use luminex_buffer::{Position, TextBuffer};
+use luminex_core::Document;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 // PTY terminal support
@@ -97,6 +101,50 @@ impl FileNode {
 // Tab Info
 // ============================================================================
 
+/// How many lines either side of the cursor bracket matching scans.
+/// Pairs further apart than this aren't highlighted.
+const BRACKET_WINDOW: usize = 1000;
+
+/// A file read from disk, with the modes its tab opens in.
+#[derive(Debug, Clone)]
+pub struct OpenedFile {
+    path: PathBuf,
+    text: String,
+    highlight: bool,
+    wrap: bool,
+    read_only: bool,
+}
+
+impl OpenedFile {
+    /// Reads a file as a core [`Document`], which picks large-file and
+    /// read-only mode from its size.
+    ///
+    /// Binary files show as a read-only hex dump.
+    fn read(path: PathBuf, config: &luminex_core::Config) -> Result<Self, String> {
+        let doc = Document::from_file_with_config(&path, config)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        if let Some(bytes) = doc.bytes() {
+            let rows: Vec<String> = (0..bytes.rows(16))
+                .filter_map(|row| bytes.hex_row(row, 16))
+                .collect();
+            return Ok(Self {
+                text: rows.join("\n"),
+                highlight: false,
+                wrap: false,
+                read_only: true,
+                path,
+            });
+        }
+        Ok(Self {
+            text: doc.text().into_owned(),
+            highlight: doc.syntax_highlighting(),
+            wrap: doc.word_wrap(config),
+            read_only: doc.is_read_only(),
+            path,
+        })
+    }
+}
+
 struct TabInfo {
     path: Option<PathBuf>,
     name: String,
@@ -108,10 +156,18 @@ struct TabInfo {
     redo_stack: Vec<String>,
     #[allow(dead_code)]
     last_saved_content: String,
+    /// The bracket at the cursor and its partner, as (line, byte range)
+    matching_brackets: Vec<(usize, Range<usize>)>,
+    /// Syntax highlighting is on (off for large files)
+    highlight: bool,
+    /// Long lines wrap (never for large files)
+    wrap: bool,
+    /// Edits and saves are refused (files over the size limit)
+    read_only: bool,
 }
 
 impl TabInfo {
-    fn new_untitled(id: usize) -> Self {
+    fn new_untitled(id: usize, config: &luminex_core::Config) -> Self {
         Self {
             path: None,
             name: format!("untitled-{}.rs", id),
@@ -121,10 +177,21 @@ impl TabInfo {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
             last_saved_content: String::new(),
+            matching_brackets: Vec::new(),
+            highlight: true,
+            wrap: config.editor.word_wrap,
+            read_only: false,
         }
     }
 
-    fn from_file(path: PathBuf, text: String) -> Self {
+    fn from_file(file: OpenedFile) -> Self {
+        let OpenedFile {
+            path,
+            text,
+            highlight,
+            wrap,
+            read_only,
+        } = file;
         let name = path
             .file_name()
             .map(|n| n.to_string_lossy().to_string())
@@ -141,6 +208,60 @@ impl TabInfo {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
             last_saved_content: text,
+            matching_brackets: Vec::new(),
+            highlight,
+            wrap,
+            read_only,
+        }
+    }
+
+    /// Finds the bracket at the cursor and its partner, for highlighting.
+    ///
+    /// There is no syntax tree here, so brackets in strings and comments
+    /// count too. This runs on every cursor move, so only the lines
+    /// within [`BRACKET_WINDOW`] of the cursor are scanned.
+    fn update_matching_brackets(&mut self) {
+        self.matching_brackets.clear();
+        let (cursor_line, byte_column) = self.content.cursor_position();
+        let first = cursor_line.saturating_sub(BRACKET_WINDOW);
+        let last = (cursor_line + BRACKET_WINDOW).min(self.content.line_count().saturating_sub(1));
+        let mut window = String::new();
+        for index in first..=last {
+            if index > first {
+                window.push('\n');
+            }
+            if let Some(text) = self.content.line(index) {
+                window.push_str(&text);
+            }
+        }
+
+        let buffer = TextBuffer::from(window);
+        let line = cursor_line - first;
+        let column = match buffer.line(line) {
+            Ok(text) => text
+                .get(..byte_column)
+                .map_or(0, |before| before.chars().count()),
+            Err(_) => return,
+        };
+        let Some(found) = buffer
+            .position_to_char_idx(Position::new(line, column))
+            .ok()
+            .and_then(|char_idx| buffer.matching_bracket(char_idx))
+        else {
+            return;
+        };
+
+        for char_idx in [found.bracket, found.partner] {
+            let Ok(pos) = buffer.char_idx_to_position(char_idx) else {
+                continue;
+            };
+            let Ok(text) = buffer.line(pos.line) else {
+                continue;
+            };
+            if let Some((start, bracket)) = text.char_indices().nth(pos.column) {
+                self.matching_brackets
+                    .push((first + pos.line, start..start + bracket.len_utf8()));
+            }
         }
     }
 
@@ -163,6 +284,7 @@ impl TabInfo {
             let current = self.content.text();
             self.redo_stack.push(current);
             self.content = text_editor::Content::with_text(&previous);
+            self.update_matching_brackets();
             true
         } else {
             false
@@ -174,6 +296,7 @@ impl TabInfo {
             let current = self.content.text();
             self.undo_stack.push(current);
             self.content = text_editor::Content::with_text(&next);
+            self.update_matching_brackets();
             true
         } else {
             false
@@ -338,6 +461,8 @@ pub struct App {
     terminal_focused: bool,
     /// Editor scroll offset in lines (tracked from EditorAction::Scroll).
     editor_scroll_offset: f32,
+    /// Settings shared with the core (file size limits, word wrap).
+    config: luminex_core::Config,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -469,7 +594,7 @@ pub enum Message {
     KeyPressed(keyboard::Key, keyboard::Modifiers),
 
     // Async results
-    FileOpened(Result<(PathBuf, String), String>),
+    FileOpened(Result<OpenedFile, String>),
     FolderOpened(Result<PathBuf, String>),
     FileSaved(Result<PathBuf, String>),
     FileDeleted(Result<PathBuf, String>),
@@ -477,8 +602,9 @@ pub enum Message {
 
 impl App {
     fn new() -> (Self, Task<Message>) {
+        let config = luminex_core::Config::load();
         let mut app = Self {
-            tabs: vec![TabInfo::new_untitled(1)],
+            tabs: vec![TabInfo::new_untitled(1, &config)],
             active_tab: 0,
             sidebar_visible: true,
             sidebar_width: 250.0,
@@ -515,6 +641,7 @@ impl App {
             terminal_spawned: false,
             terminal_focused: false,
             editor_scroll_offset: 0.0,
+            config,
         };
 
         // Set initial content with sample Rust code
@@ -587,15 +714,17 @@ impl Config {
             Message::NewFile => {
                 self.active_menu = None;
                 self.untitled_counter += 1;
-                self.tabs.push(TabInfo::new_untitled(self.untitled_counter));
+                self.tabs
+                    .push(TabInfo::new_untitled(self.untitled_counter, &self.config));
                 self.active_tab = self.tabs.len() - 1;
                 self.status_message = "New file created".to_string();
             }
 
             Message::OpenFile => {
                 self.active_menu = None;
+                let config = self.config.clone();
                 return Task::perform(
-                    async {
+                    async move {
                         let handle = rfd::AsyncFileDialog::new()
                             .add_filter("All Files", &["*"])
                             .add_filter("Rust", &["rs"])
@@ -605,13 +734,7 @@ impl Config {
                             .await;
 
                         match handle {
-                            Some(file) => {
-                                let path = file.path().to_path_buf();
-                                match std::fs::read_to_string(&path) {
-                                    Ok(content) => Ok((path, content)),
-                                    Err(e) => Err(format!("Failed to read file: {}", e)),
-                                }
-                            }
+                            Some(file) => OpenedFile::read(file.path().to_path_buf(), &config),
                             None => Err("Cancelled".to_string()),
                         }
                     },
@@ -637,6 +760,10 @@ impl Config {
             Message::Save => {
                 self.active_menu = None;
                 if let Some(tab) = self.tabs.get(self.active_tab) {
+                    if tab.read_only {
+                        self.status_message = format!("{} is read-only", tab.name);
+                        return Task::none();
+                    }
                     if let Some(path) = &tab.path {
                         let path = path.clone();
                         let content = tab.content.text();
@@ -658,6 +785,10 @@ impl Config {
             Message::SaveAs => {
                 self.active_menu = None;
                 if let Some(tab) = self.tabs.get(self.active_tab) {
+                    if tab.read_only {
+                        self.status_message = format!("{} is read-only", tab.name);
+                        return Task::none();
+                    }
                     let content = tab.content.text();
                     let default_name = tab.name.clone();
                     return Task::perform(
@@ -789,17 +920,25 @@ impl Config {
 
                 if let Some(tab) = self.tabs.get_mut(self.active_tab) {
                     let is_edit = action.is_edit();
+                    if is_edit && tab.read_only {
+                        self.status_message = format!("{} is read-only", tab.name);
+                        return Task::none();
+                    }
 
                     // Save state before edit for undo
                     if is_edit {
                         tab.save_undo_state();
                     }
 
+                    let is_scroll = matches!(action, text_editor::Action::Scroll { .. });
                     tab.content.perform(action);
 
                     if is_edit {
                         tab.modified = true;
                     }
+                    if !is_scroll {
+                        tab.update_matching_brackets();
+                    }
 
                     // Clamp scroll offset to valid range
                     let max_scroll = (tab.content.line_count() as f32 - 1.0).max(0.0);
@@ -855,14 +994,9 @@ impl Config {
                         }
                     }
 
-                    let path_clone = path.clone();
+                    let config = self.config.clone();
                     return Task::perform(
-                        async move {
-                            match std::fs::read_to_string(&path_clone) {
-                                Ok(content) => Ok((path_clone, content)),
-                                Err(e) => Err(format!("Failed to read file: {}", e)),
-                            }
-                        },
+                        async move { OpenedFile::read(path, &config) },
                         Message::FileOpened,
                     );
                 }
@@ -885,15 +1019,21 @@ impl Config {
             }
 
             Message::FileOpened(result) => match result {
-                Ok((path, content)) => {
-                    let name = path
+                Ok(file) => {
+                    let name = file
+                        .path
                         .file_name()
                         .map(|n| n.to_string_lossy().to_string())
                         .unwrap_or_else(|| "unknown".to_string());
 
-                    self.tabs.push(TabInfo::from_file(path, content));
+                    let read_only = file.read_only;
+                    self.tabs.push(TabInfo::from_file(file));
                     self.active_tab = self.tabs.len() - 1;
-                    self.status_message = format!("Opened: {}", name);
+                    self.status_message = if read_only {
+                        format!("Opened: {} (read-only)", name)
+                    } else {
+                        format!("Opened: {}", name)
+                    };
                 }
                 Err(e) => {
                     if e != "Cancelled" {
@@ -1295,16 +1435,20 @@ impl Config {
             Message::EditorCut => {
                 self.editor_context_visible = false;
                 self.active_menu = None;
-                if let Some(tab) = self.tabs.get_mut(self.active_tab) {
-                    if let Some(selected) = tab.content.selection() {
-                        tab.save_undo_state();
-                        if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                            let _ = clipboard.set_text(&selected);
-                        }
-                        tab.content.perform(text_editor::Action::Edit(text_editor::Edit::Delete));
-                        tab.modified = true;
-                        self.status_message = "Cut".to_string();
+                if let Some(tab) = self
+                    .tabs
+                    .get_mut(self.active_tab)
+                    .filter(|tab| !tab.read_only)
+                    && let Some(selected) = tab.content.selection()
+                {
+                    tab.save_undo_state();
+                    if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                        let _ = clipboard.set_text(&selected);
                     }
+                    tab.content
+                        .perform(text_editor::Action::Edit(text_editor::Edit::Delete));
+                    tab.modified = true;
+                    self.status_message = "Cut".to_string();
                 }
             }
 
@@ -1326,17 +1470,20 @@ impl Config {
             Message::EditorPaste => {
                 self.editor_context_visible = false;
                 self.active_menu = None;
-                if let Some(tab) = self.tabs.get_mut(self.active_tab) {
-                    if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                        if let Ok(clip_text) = clipboard.get_text() {
-                            tab.save_undo_state();
-                            tab.content.perform(text_editor::Action::Edit(
-                                text_editor::Edit::Paste(std::sync::Arc::new(clip_text))
-                            ));
-                            tab.modified = true;
-                            self.status_message = "Pasted".to_string();
-                        }
-                    }
+                if let Some(tab) = self
+                    .tabs
+                    .get_mut(self.active_tab)
+                    .filter(|tab| !tab.read_only)
+                    && let Ok(mut clipboard) = arboard::Clipboard::new()
+                    && let Ok(clip_text) = clipboard.get_text()
+                {
+                    tab.save_undo_state();
+                    tab.content
+                        .perform(text_editor::Action::Edit(text_editor::Edit::Paste(
+                            std::sync::Arc::new(clip_text),
+                        )));
+                    tab.modified = true;
+                    self.status_message = "Pasted".to_string();
                 }
             }
 
@@ -1501,6 +1648,7 @@ impl Config {
             }
             Message::GotoLineConfirm => {
                 self.goto_line_visible = false;
+                #[allow(clippy::collapsible_if)]
                 if let Ok(line_num) = self.goto_line_input.trim().parse::<usize>() {
                     if line_num > 0 {
                         if let Some(tab) = self.tabs.get_mut(self.active_tab) {
@@ -1851,6 +1999,7 @@ impl Config {
                 }
 
                 // Set working directory
+                #[allow(clippy::collapsible_if)]
                 if let Some(dir) = cwd {
                     if let Ok(dir_cstr) = std::ffi::CString::new(dir.to_string_lossy().as_bytes()) {
                         libc::chdir(dir_cstr.as_ptr());
@@ -1925,6 +2074,7 @@ impl Config {
                             if ch == '\x07' {
                                 break;
                             }
+                            #[allow(clippy::collapsible_if)]
                             if ch == '\x1b' {
                                 if let Some(&'\\') = chars.peek() {
                                     chars.next();
@@ -3198,6 +3348,7 @@ impl Config {
         if let Some(tab) = self.tabs.get(self.active_tab) {
             let highlight_settings = HighlightSettings {
                 language: tab.language.clone(),
+                brackets: tab.matching_brackets.clone(),
             };
 
             // Selection color: bright blue normally, dimmed when context menu is open
@@ -3210,8 +3361,14 @@ impl Config {
 
             let editor_bg = colors::BG_DARK;
 
+            let wrapping = if tab.wrap {
+                Wrapping::Word
+            } else {
+                Wrapping::None
+            };
             let editor = text_editor(&tab.content)
                 .height(Length::Fill)
+                .wrapping(wrapping)
                 .padding(16)
                 .font(Font::MONOSPACE)
                 .size(self.font_size)
@@ -3229,10 +3386,17 @@ impl Config {
                         selection: selection_color,
                     }
                 })
-                .highlight_with::<EditorHighlighter>(highlight_settings, |highlight, _theme| {
-                    highlight.to_format(Font::MONOSPACE)
-                })
                 .on_action(Message::EditorAction);
+            // Large files open without syntax highlighting
+            let editor: Element<'_, Message> = if tab.highlight {
+                editor
+                    .highlight_with::<EditorHighlighter>(highlight_settings, |highlight, _theme| {
+                        highlight.to_format(Font::MONOSPACE)
+                    })
+                    .into()
+            } else {
+                editor.into()
+            };
 
             let editor_container: Element<'_, Message> = container(editor)
                 .width(Length::Fill)
@@ -3375,6 +3539,7 @@ impl Config {
                 let line_len = line_text.trim_end().len().min(80);
                 let indent = line_text.len() - line_text.trim_start().len();
                 let indent = indent.min(40);
+                #[allow(clippy::implicit_saturating_sub)]
                 let content_len = if line_len > indent { line_len - indent } else { 0 };
 
                 let is_current = line_idx == cursor_line;
@@ -3520,6 +3685,7 @@ impl Config {
                         let name = s.split(|c: char| c == '{' || c == '<' || c.is_whitespace()).next().unwrap_or(s);
                         ("C", name.to_string())
                     } else if trimmed.starts_with("def ") || trimmed.starts_with("async def ") {
+                        #[allow(clippy::manual_strip)]
                         let s = if trimmed.starts_with("async def ") { &trimmed[10..] } else { &trimmed[4..] };
                         let name = s.split('(').next().unwrap_or(s).trim();
                         ("fn", name.to_string())
@@ -3889,6 +4055,7 @@ impl Config {
             .into()
     }
 
+    #[allow(clippy::vec_init_then_push)]
     fn view_editor_context_menu(&self) -> Element<'_, Message> {
         let has_selection = self.tabs.get(self.active_tab)
             .and_then(|t| t.content.selection())
@@ -28,12 +28,15 @@ mod colors {
     pub const MACRO: Color = Color::from_rgb(0.55, 0.80, 0.80);       // Cyan
     pub const LIFETIME: Color = Color::from_rgb(0.90, 0.70, 0.55);    // Light orange
     pub const DEFAULT: Color = Color::from_rgb(0.90, 0.90, 0.90);     // White-ish
+    pub const MATCHING_BRACKET: Color = Color::from_rgb(1.00, 0.85, 0.30); // Bright gold
 }
 
 /// Settings for the highlighter.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct HighlightSettings {
     pub language: String,
+    /// Brackets to highlight as a matched pair, as (line, byte range)
+    pub brackets: Vec<(usize, Range<usize>)>,
 }
 
 /// Format for highlighted text.
@@ -834,6 +837,8 @@ pub struct EditorHighlighter {
     state: HighlighterState,
     spans: Vec<Span>,
     current_line_idx: usize,
+    /// Matched bracket pair at the cursor, as (line, byte range)
+    brackets: Vec<(usize, Range<usize>)>,
 }
 
 impl Highlighter for EditorHighlighter {
@@ -853,6 +858,7 @@ impl Highlighter for EditorHighlighter {
             },
             spans: Vec::new(),
             current_line_idx: 0,
+            brackets: settings.brackets.clone(),
         }
     }
 
@@ -864,6 +870,19 @@ impl Highlighter for EditorHighlighter {
             self.state.in_multiline_string = false;
             self.current_line_idx = 0;
         }
+        if self.brackets != new_settings.brackets {
+            // Redraw from the first line that gained or lost a highlight
+            let first = self
+                .brackets
+                .iter()
+                .chain(&new_settings.brackets)
+                .map(|(line, _)| *line)
+                .min();
+            self.brackets = new_settings.brackets.clone();
+            if let Some(line) = first {
+                self.change_line(line);
+            }
+        }
     }
 
     fn change_line(&mut self, line: usize) {
@@ -876,6 +895,7 @@ impl Highlighter for EditorHighlighter {
     }
 
     fn highlight_line(&mut self, line: &str) -> Self::Iterator<'_> {
+        let line_idx = self.current_line_idx;
         self.spans.clear();
         self.tokenize_line(line);
         self.current_line_idx += 1;
@@ -898,6 +918,22 @@ impl Highlighter for EditorHighlighter {
                     },
                 )
             })
+            // Later spans win, so the bracket colors go last
+            .chain(
+                self.brackets
+                    .iter()
+                    .filter(|(bracket_line, range)| {
+                        *bracket_line == line_idx && range.end <= line.len()
+                    })
+                    .map(|(_, range)| {
+                        (
+                            range.clone(),
+                            HighlightFormat {
+                                color: colors::MATCHING_BRACKET,
+                            },
+                        )
+                    }),
+            )
             .collect::<Vec<_>>()
             .into_iter()
     }
@@ -1104,6 +1140,7 @@ impl EditorHighlighter {
             }
 
             // Triple-quoted strings (Python, TOML, Dart)
+            #[allow(clippy::collapsible_if)]
             if self.config.has_triple_quote_strings && i + 2 < len {
                 if (bytes[i] == b'"' && bytes[i + 1] == b'"' && bytes[i + 2] == b'"')
                     || (bytes[i] == b'\'' && bytes[i + 1] == b'\'' && bytes[i + 2] == b'\'')